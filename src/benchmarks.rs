@@ -0,0 +1,110 @@
+// rough timings of whole histories, run with cargo test --release -- --ignored --nocapture
+use crate::calculate_gain_per_trade::calculate_gain_per_trade;
+use crate::calculate_gains::calculate_gains;
+use crate::duplicates::{merge_trades, Tolerance};
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::method::{Method, ALL_METHODS};
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use rust_decimal_macros::*;
+use std::time::{Duration, Instant};
+
+static FIAT_CURRENCY: &str = "USD";
+
+// a staking reward every day with part of it sold off the next
+fn history(days: u64) -> (Vec<Trade>, Vec<Income>) {
+    let mut trades = vec![];
+    let mut incomes = vec![];
+    for day in 0..days {
+        let date = day * 86400000;
+        incomes.push(Income {
+            amount: dec!(1),
+            currency: "ETH".to_string(),
+            transaction_id: None,
+            id: format!("income-{}", day),
+            fee: None,
+            date,
+            fiat_rate: Some(Decimal::from(100 + day % 50)),
+        });
+        trades.push(Trade {
+            bought_currency: FIAT_CURRENCY.to_string(),
+            sold_currency: "ETH".to_string(),
+            amount_sold: dec!(0.5),
+            rate: dec!(0.01),
+            date: date + 1,
+            exchange_id: format!("trade-{}", day),
+            exchange: "".to_string(),
+            id: format!("trade-{}", day),
+            transaction_fee: Zero::zero(),
+            transaction_fee_currency: FIAT_CURRENCY.to_string(),
+            fiat_rate: Some(dec!(110)),
+            short_term: None,
+            long_term: None,
+            date_acquired: None,
+            cost_basis: None,
+            long_term_trade: None,
+            lot_id: None,
+            acquisition_id: None,
+        });
+    }
+    (trades, incomes)
+}
+
+fn time(days: u64, run: impl Fn(Vec<Trade>, Vec<Income>)) -> Duration {
+    let (trades, incomes) = history(days);
+    let start = Instant::now();
+    run(trades, incomes);
+    start.elapsed()
+}
+
+// quadrupling the history should take about four times as long, quadratic work would take sixteen
+fn assert_linear(name: &str, run: impl Fn(Vec<Trade>, Vec<Income>)) {
+    let small = time(25000, &run);
+    let large = time(100000, &run);
+    println!("{}: 25000 days {:?}, 100000 days {:?}", name, small, large);
+    assert!(large < small * 8, "{} does not scale linearly", name);
+}
+
+#[test]
+#[ignore]
+fn calculate_gains_scales_linearly() {
+    // half of every reward is kept so the cost and tax methods pick from a growing pile of lots
+    for method in ALL_METHODS.iter() {
+        assert_linear(
+            &format!("calculate_gains {:?}", method),
+            |trades, incomes| {
+                calculate_gains(
+                    Holdings::default(),
+                    trades,
+                    incomes,
+                    FIAT_CURRENCY.to_string(),
+                    *method,
+                );
+            },
+        );
+    }
+}
+
+#[test]
+#[ignore]
+fn calculate_gain_per_trade_scales_linearly() {
+    assert_linear("calculate_gain_per_trade", |trades, incomes| {
+        calculate_gain_per_trade(
+            Holdings::default(),
+            trades,
+            incomes,
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+    });
+}
+
+#[test]
+#[ignore]
+fn merge_trades_scales_linearly() {
+    // the trades have no exchange to go on so every one of them is matched on its details
+    assert_linear("merge_trades", |trades, _| {
+        merge_trades(vec![], trades, &Tolerance::default());
+    });
+}
@@ -1,3 +1,5 @@
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
 use crate::holding::Holdings;
 use crate::method::Method;
 use crate::trade::Trade;
@@ -55,7 +57,7 @@ pub fn calculate_gain_per_holdings(
     fiat_currency: String,
     method: Method,
 ) -> CalculateGainPerHolding {
-    let mut new_holdings = holdings;
+    let mut lots = LotStore::from(holdings);
     let mut short_term_gain = Zero::zero();
     let mut short_term_proceed = Zero::zero();
     let mut short_term_cost_basis = Zero::zero();
@@ -64,24 +66,20 @@ pub fn calculate_gain_per_holdings(
     let mut long_term_cost_basis = Zero::zero();
     let mut short_term_trades: Vec<Trade> = vec![];
     let mut long_term_trades: Vec<Trade> = vec![];
-    let mut incomes_to_apply = incomes;
 
-    for trade in trades {
-        // handle this better somewhere else
-        if trade.amount_sold > Zero::zero() {
-
-            while !incomes_to_apply.is_empty() && trade.date > incomes_to_apply[0].date {
-                let income = incomes_to_apply.remove(0);
-                new_holdings = new_holdings.add_to_currency_holdings(
-                    income.currency.clone(),
-                    income.amount,
-                    income.clone().fiat_rate(),
-                    income.date,
-                    None,
-                );
+    // incomes after the last trade are added as well, nothing is sold after them so no gain changes
+    for event in events(trades, incomes) {
+        let trade = match event {
+            Event::Income(income) => {
+                lots.add_income(&income);
+                continue;
             }
+            Event::Trade(trade) => trade,
+        };
 
-            let result = new_holdings.process_trade(trade, fiat_currency.clone(), method);       
+        // handle this better somewhere else
+        if trade.amount_sold > Zero::zero() {
+            let result = lots.process_trade(&trade, &fiat_currency, method);
 
             short_term_gain += result.short_term_gain;
             short_term_proceed += result.short_term_proceeds;
@@ -89,7 +87,6 @@ pub fn calculate_gain_per_holdings(
             long_term_gain += result.long_term_gain;
             long_term_proceed += result.long_term_proceeds;
             long_term_cost_basis += result.long_term_cost_basis;
-            new_holdings = result.holdings;
 
             for cost_basis_trade in result.cost_basis_trades {
                 if cost_basis_trade.long_term_trade.unwrap_or(false) {
@@ -116,6 +113,7 @@ pub fn calculate_gain_per_holdings(
 #[cfg(test)]
 mod tests {
     use super::calculate_gain_per_holdings;
+    use crate::income::Income;
     use crate::method::Method;
     use crate::mocks;
     use crate::{QUARTER_IN_MILLISECONDS, YEAR_IN_MILLISECONDS};
@@ -157,4 +155,28 @@ mod tests {
 
         assert_eq!((result.short_term_proceed + result.long_term_proceed).round_dp(16), total_proceeds.round_dp(16));
     }
+
+    #[test]
+    fn incomes_after_the_last_trade_change_nothing() {
+        let holdings = mocks::mock_holdings(1, 5, None, None);
+        let currency = holdings.0.keys().collect::<Vec<&String>>()[0].clone();
+        let mut trades = mocks::mock_trades(3, mocks::now_u64(), holdings.clone(), false);
+        trades.sort_by_key(|trade| trade.date);
+
+        // free lots would be picked first by lowest cost if they were added any earlier
+        let incomes = vec![Income {
+            amount: Decimal::from(1000),
+            currency,
+            transaction_id: None,
+            id: "late".to_string(),
+            fee: None,
+            date: trades.last().unwrap().date + 1,
+            fiat_rate: Some(Zero::zero()),
+        }];
+
+        let with_incomes = calculate_gain_per_holdings(holdings.clone(), trades.clone(), incomes, FIAT_CURRENCY.to_string(), Method::LCFO);
+        let without_incomes = calculate_gain_per_holdings(holdings, trades, vec!(), FIAT_CURRENCY.to_string(), Method::LCFO);
+
+        assert_eq!(with_incomes, without_incomes);
+    }
 }
@@ -1,4 +1,5 @@
-use crate::calculate_gains::calculate_gains;
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
 use crate::holding::Holdings;
 use crate::income::Income;
 use crate::method::Method;
@@ -46,44 +47,39 @@ pub fn calculate_gain_per_trade(
     fiat_currency: String,
     method: Method,
 ) -> CalculateGainPerTrade {
-    let mut new_holdings = holdings;
+    let mut lots = LotStore::from(holdings);
     let mut short_term: Decimal = Zero::zero();
     let mut long_term: Decimal = Zero::zero();
     let mut trades: Vec<Trade> = vec![];
 
-    let mut new_incomes = incomes;
+    for event in events(old_trades, incomes) {
+        match event {
+            Event::Income(income) => lots.add_income(&income),
+            Event::Trade(trade) => {
+                let mut short_term_gain = Zero::zero();
+                let mut long_term_gain = Zero::zero();
 
-    for trade in old_trades {
-        let mut incomes_to_use = vec![];
-        while !new_incomes.is_empty() && trade.date > new_incomes[0].date {
-            let income: Income = new_incomes.remove(0);
-            incomes_to_use.push(income);
-        }
-
-        let result = calculate_gains(
-            new_holdings,
-            vec![trade.clone()],
-            incomes_to_use,
-            fiat_currency.clone(),
-            method,
-        );
+                // handle this better somewhere else
+                if trade.amount_sold > Zero::zero() {
+                    let result = lots.process_trade(&trade, &fiat_currency, method);
+                    short_term_gain = result.short_term_gain;
+                    long_term_gain = result.long_term_gain;
+                }
 
-        new_holdings = result.new_holdings;
-        short_term += result.short_term_gain;
-        long_term += result.long_term_gain;
-        trades.push(Trade {
-            short_term: Some(result.short_term_gain),
-            long_term: Some(result.long_term_gain),
-            ..trade
-        });
+                short_term += short_term_gain;
+                long_term += long_term_gain;
+                trades.push(Trade {
+                    short_term: Some(short_term_gain),
+                    long_term: Some(long_term_gain),
+                    ..trade
+                });
+            }
+        }
     }
 
-    let apply_remaining_incomes =
-        calculate_gains(new_holdings, vec![], new_incomes, fiat_currency, method);
-
     CalculateGainPerTrade {
         trades,
-        holdings: apply_remaining_incomes.new_holdings,
+        holdings: lots.into(),
         short_term,
         long_term,
     }
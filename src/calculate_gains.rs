@@ -1,6 +1,11 @@
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
 use crate::holding::Holdings;
 use crate::income::Income;
 use crate::method::Method;
+use crate::price::fill::{fill_missing_rates, FilledRate, MissingRate, MissingRatePolicy};
+use crate::price::{PriceSource, PriceTable};
+use crate::stablecoin::Stablecoins;
 use crate::trade::Trade;
 use rust_decimal::prelude::{Decimal, Zero};
 use serde::{Deserialize, Serialize};
@@ -16,6 +21,14 @@ pub struct CalculateGains {
     pub short_term_gain: Decimal,
 }
 
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CalculateGainsWithPrices {
+    #[serde(flatten)]
+    pub gains: CalculateGains,
+    pub filled: Vec<FilledRate>,
+    pub missing: Vec<MissingRate>,
+}
+
 #[wasm_bindgen]
 pub fn calculate_gains_wasm(
     holdings: &JsValue,
@@ -37,6 +50,104 @@ pub fn calculate_gains_wasm(
     .unwrap()
 }
 
+#[wasm_bindgen]
+pub fn calculate_gains_with_prices_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+    method: Method,
+    prices: &JsValue,
+    policy: MissingRatePolicy,
+) -> Result<JsValue, JsValue> {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    let prices: PriceTable = prices.into_serde().unwrap();
+    calculate_gains_with_prices(
+        holdings,
+        trades,
+        incomes,
+        fiat_currency,
+        method,
+        &prices,
+        policy,
+    )
+    .map(|result| JsValue::from_serde(&result).unwrap())
+    .map_err(|missing| JsValue::from_serde(&missing).unwrap())
+}
+
+#[wasm_bindgen]
+pub fn calculate_gains_with_stablecoins_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    stablecoins: &JsValue,
+    method: Method,
+) -> JsValue {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    let stablecoins: Stablecoins = stablecoins.into_serde().unwrap();
+    JsValue::from_serde(&calculate_gains_with_stablecoins(
+        holdings,
+        trades,
+        incomes,
+        &stablecoins,
+        method,
+    ))
+    .unwrap()
+}
+
+pub fn calculate_gains_with_stablecoins(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    stablecoins: &Stablecoins,
+    method: Method,
+) -> CalculateGains {
+    let trades = trades
+        .into_iter()
+        .filter(|trade| !stablecoins.is_unchanged(trade))
+        .map(|trade| stablecoins.trade(trade))
+        .collect();
+    let incomes = incomes
+        .into_iter()
+        .map(|income| stablecoins.income(income))
+        .collect();
+    calculate_gains(
+        stablecoins.holdings(holdings),
+        trades,
+        incomes,
+        stablecoins.fiat_currency.clone(),
+        method,
+    )
+}
+
+// fills in missing fiat rates before calculating so they don't count as zero
+pub fn calculate_gains_with_prices(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+    method: Method,
+    prices: &impl PriceSource,
+    policy: MissingRatePolicy,
+) -> Result<CalculateGainsWithPrices, Vec<MissingRate>> {
+    let filled = fill_missing_rates(trades, incomes, &fiat_currency, prices, policy)?;
+    Ok(CalculateGainsWithPrices {
+        gains: calculate_gains(
+            holdings,
+            filled.trades,
+            filled.incomes,
+            fiat_currency,
+            method,
+        ),
+        filled: filled.filled,
+        missing: filled.missing,
+    })
+}
+
 pub fn calculate_gains(
     holdings: Holdings,
     trades: Vec<Trade>,
@@ -46,45 +157,26 @@ pub fn calculate_gains(
 ) -> CalculateGains {
     let mut short_term_gain = Zero::zero();
     let mut long_term_gain = Zero::zero();
-    let mut new_holdings = holdings;
-
-    let mut incomes_to_apply = incomes;
-
-    for trade in trades {
-        while !incomes_to_apply.is_empty() && trade.date > incomes_to_apply[0].date {
-            let income = incomes_to_apply.remove(0);
-            new_holdings = new_holdings.add_to_currency_holdings(
-                income.currency.clone(),
-                income.amount,
-                income.clone().fiat_rate(),
-                income.date,
-                None,
-            );
-        }
-
-        // handle this better somewhere else
-        if trade.amount_sold > Zero::zero() {
-            let result = new_holdings.process_trade(trade.clone(), fiat_currency.clone(), method);
-            short_term_gain += result.short_term_gain;
-            long_term_gain += result.long_term_gain;
-            new_holdings = result.holdings;
+    let mut lots = LotStore::from(holdings);
+
+    for event in events(trades, incomes) {
+        match event {
+            Event::Income(income) => lots.add_income(&income),
+            Event::Trade(trade) => {
+                // handle this better somewhere else
+                if trade.amount_sold > Zero::zero() {
+                    let result = lots.process_trade(&trade, &fiat_currency, method);
+                    short_term_gain += result.short_term_gain;
+                    long_term_gain += result.long_term_gain;
+                }
+            }
         }
     }
 
-    for income in incomes_to_apply {
-        new_holdings = new_holdings.add_to_currency_holdings(
-            income.currency.clone(),
-            income.amount,
-            income.clone().fiat_rate(),
-            income.date,
-            None,
-        );
-    }
-
     CalculateGains {
         short_term_gain,
         long_term_gain,
-        new_holdings,
+        new_holdings: lots.into(),
     }
 }
 
@@ -0,0 +1,196 @@
+use crate::date::{self, InvalidDate};
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::method::{Method, ALL_METHODS};
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct YearGains {
+    #[serde(rename = "shortTermGain")]
+    pub short_term_gain: Decimal,
+    #[serde(rename = "longTermGain")]
+    pub long_term_gain: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct MethodComparison {
+    pub method: Method,
+    pub years: BTreeMap<i32, YearGains>,
+    #[serde(rename = "shortTermGain")]
+    pub short_term_gain: Decimal,
+    #[serde(rename = "longTermGain")]
+    pub long_term_gain: Decimal,
+    pub holdings: Holdings,
+}
+
+#[wasm_bindgen]
+pub fn compare_methods_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+    methods: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    let methods: Vec<Method> = if methods.is_undefined() || methods.is_null() {
+        ALL_METHODS.to_vec()
+    } else {
+        methods.into_serde().unwrap()
+    };
+
+    compare_methods(holdings, trades, incomes, fiat_currency, &methods)
+        .map(|report| JsValue::from_serde(&report).unwrap())
+        .map_err(|error| JsValue::from_serde(&error).unwrap())
+}
+
+pub fn compare_methods(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+    methods: &[Method],
+) -> Result<Vec<MethodComparison>, InvalidDate> {
+    // the order trades and incomes get applied in is the same for every method so only work it out once
+    let events: Vec<Event<(&Trade, i32), &Income>> = events(
+        // handle this better somewhere else
+        trades
+            .iter()
+            .filter(|trade| trade.amount_sold > Zero::zero()),
+        incomes.iter(),
+    )
+    .map(|event| match event {
+        Event::Trade(trade) => Ok(Event::Trade((trade, date::year(trade.date)?))),
+        Event::Income(income) => Ok(Event::Income(income)),
+    })
+    .collect::<Result<_, InvalidDate>>()?;
+
+    Ok(methods
+        .iter()
+        .map(|method| {
+            let mut lots = LotStore::from(holdings.clone());
+            let mut years: BTreeMap<i32, YearGains> = BTreeMap::new();
+            let mut short_term_gain = Zero::zero();
+            let mut long_term_gain = Zero::zero();
+
+            for event in events.iter() {
+                match event {
+                    Event::Income(income) => lots.add_income(income),
+                    Event::Trade((trade, year)) => {
+                        let result = lots.process_trade(trade, &fiat_currency, *method);
+                        let year_gains = years.entry(*year).or_default();
+                        year_gains.short_term_gain += result.short_term_gain;
+                        year_gains.long_term_gain += result.long_term_gain;
+                        short_term_gain += result.short_term_gain;
+                        long_term_gain += result.long_term_gain;
+                    }
+                }
+            }
+
+            MethodComparison {
+                method: *method,
+                years,
+                short_term_gain,
+                long_term_gain,
+                holdings: lots.into(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compare_methods;
+    use crate::calculate_gains::calculate_gains;
+    use crate::date;
+    use crate::method::ALL_METHODS;
+    use crate::mocks;
+    use rust_decimal::prelude::{Decimal, Zero};
+
+    static FIAT_CURRENCY: &str = "FAKE";
+
+    #[test]
+    fn compare_methods_identical_to_calculate_gains() {
+        let holdings = mocks::mock_holdings(2, 5, None, None);
+        let mut trades = mocks::mock_trades(3, mocks::now_u64(), holdings.clone(), false);
+        trades.sort_by_key(|trade| trade.date);
+
+        let comparisons = compare_methods(
+            holdings.clone(),
+            trades.clone(),
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            &ALL_METHODS,
+        )
+        .unwrap();
+
+        assert_eq!(comparisons.len(), ALL_METHODS.len());
+        for comparison in comparisons {
+            let gains = calculate_gains(
+                holdings.clone(),
+                trades.clone(),
+                vec![],
+                FIAT_CURRENCY.to_string(),
+                comparison.method,
+            );
+
+            assert_eq!(comparison.short_term_gain, gains.short_term_gain);
+            assert_eq!(comparison.long_term_gain, gains.long_term_gain);
+            assert_eq!(comparison.holdings, gains.new_holdings);
+        }
+    }
+
+    #[test]
+    fn compare_methods_splits_gains_by_year() {
+        let holdings = mocks::mock_holdings(1, 5, None, None);
+        let mut trades = mocks::mock_trades(5, mocks::now_u64(), holdings.clone(), false);
+        trades.sort_by_key(|trade| trade.date);
+
+        let comparisons = compare_methods(
+            holdings,
+            trades.clone(),
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            &ALL_METHODS,
+        )
+        .unwrap();
+
+        for comparison in comparisons {
+            for year in comparison.years.keys() {
+                assert!(trades
+                    .iter()
+                    .any(|trade| date::year(trade.date) == Ok(*year)));
+            }
+
+            let short_term_gain: Decimal = comparison
+                .years
+                .values()
+                .fold(Zero::zero(), |acc, year| acc + year.short_term_gain);
+            assert_eq!(short_term_gain, comparison.short_term_gain);
+        }
+    }
+
+    #[test]
+    fn dates_past_what_a_calendar_can_show_are_an_error() {
+        let holdings = mocks::mock_holdings(1, 5, None, None);
+        let mut trades = mocks::mock_trades(1, mocks::now_u64(), holdings.clone(), false);
+        trades[0].date = u64::MAX;
+
+        let error = compare_methods(
+            holdings,
+            trades,
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            &ALL_METHODS,
+        )
+        .unwrap_err();
+        assert_eq!(error.date, u64::MAX);
+    }
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+// milliseconds too far in the future to be a calendar date
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct InvalidDate {
+    pub date: u64,
+}
+
+fn date_time(date: u64) -> Result<DateTime<Utc>, InvalidDate> {
+    i64::try_from(date)
+        .ok()
+        .and_then(|millis| Utc.timestamp_millis_opt(millis).single())
+        .ok_or(InvalidDate { date })
+}
+
+pub fn year(date: u64) -> Result<i32, InvalidDate> {
+    Ok(date_time(date)?.year())
+}
+
+pub fn month(date: u64) -> Result<String, InvalidDate> {
+    Ok(date_time(date)?.format("%Y-%m").to_string())
+}
+
+pub fn to_iso_string(date: u64) -> Result<String, InvalidDate> {
+    Ok(date_time(date)?
+        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        .to_string())
+}
+
+pub fn format(date: u64, format: &str) -> Result<String, InvalidDate> {
+    Ok(date_time(date)?.format(format).to_string())
+}
+
+// tax years are named after the calendar year they end in
+pub fn tax_year(date: u64, starting_month: u32) -> Result<i32, InvalidDate> {
+    let date_time = date_time(date)?;
+    if starting_month > 1 && date_time.month() >= starting_month {
+        Ok(date_time.year() + 1)
+    } else {
+        Ok(date_time.year())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{month, tax_year, to_iso_string, year, InvalidDate};
+
+    #[test]
+    fn year_from_milliseconds() {
+        assert_eq!(year(0), Ok(1970));
+        assert_eq!(year(1609459199999), Ok(2020));
+        assert_eq!(year(1609459200000), Ok(2021));
+    }
+
+    #[test]
+    fn format_milliseconds() {
+        assert_eq!(month(1609459199999), Ok("2020-12".to_string()));
+        assert_eq!(
+            to_iso_string(1609459199999),
+            Ok("2020-12-31T23:59:59.999Z".to_string())
+        );
+    }
+
+    #[test]
+    fn tax_year_from_milliseconds() {
+        // 2021-06-30 and 2021-07-01
+        assert_eq!(tax_year(1625011200000, 7), Ok(2021));
+        assert_eq!(tax_year(1625097600000, 7), Ok(2022));
+        assert_eq!(tax_year(1625097600000, 1), Ok(2021));
+    }
+
+    #[test]
+    fn dates_out_of_range_are_errors() {
+        assert_eq!(year(u64::MAX), Err(InvalidDate { date: u64::MAX }));
+        assert_eq!(
+            to_iso_string(i64::MAX as u64),
+            Err(InvalidDate {
+                date: i64::MAX as u64
+            })
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use rust_decimal_macros::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Tolerance {
+    // milliseconds two trades can be apart and still be the same trade
+    pub date: u64,
+    // relative difference allowed between amounts
+    pub amount: Decimal,
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance {
+            date: 120000,
+            amount: dec!(0.001),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Duplicate {
+    pub existing: Trade,
+    pub imported: Trade,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct MergeResult {
+    // existing trades along with every imported trade that isn't a duplicate
+    pub trades: Vec<Trade>,
+    // same exchange id with matching details, these are dropped
+    pub exact: Vec<Duplicate>,
+    // no exchange id to go on but the details match, left out until confirmed
+    pub probable: Vec<Duplicate>,
+    // same exchange id with different details, left out until confirmed
+    pub conflicts: Vec<Duplicate>,
+}
+
+#[wasm_bindgen]
+pub fn merge_trades_wasm(existing: &JsValue, imported: &JsValue, tolerance: &JsValue) -> JsValue {
+    let existing: Vec<Trade> = existing.into_serde().unwrap();
+    let imported: Vec<Trade> = imported.into_serde().unwrap();
+    let tolerance: Tolerance = if tolerance.is_undefined() || tolerance.is_null() {
+        Tolerance::default()
+    } else {
+        tolerance.into_serde().unwrap()
+    };
+    JsValue::from_serde(&merge_trades(existing, imported, &tolerance)).unwrap()
+}
+
+fn key(trade: &Trade) -> Option<(String, String)> {
+    if trade.exchange_id.is_empty() || trade.exchange.is_empty() {
+        None
+    } else {
+        Some((trade.exchange.to_lowercase(), trade.exchange_id.clone()))
+    }
+}
+
+fn close(a: Decimal, b: Decimal, tolerance: Decimal) -> bool {
+    let largest = a.abs().max(b.abs());
+    largest.is_zero() || (a - b).abs() / largest <= tolerance
+}
+
+fn same_details(a: &Trade, b: &Trade, tolerance: &Tolerance) -> bool {
+    a.bought_currency == b.bought_currency
+        && a.sold_currency == b.sold_currency
+        && a.date.max(b.date) - a.date.min(b.date) <= tolerance.date
+        && close(a.amount_sold, b.amount_sold, tolerance.amount)
+        && (a.rate.is_zero()
+            || b.rate.is_zero()
+            || close(
+                a.amount_sold / a.rate,
+                b.amount_sold / b.rate,
+                tolerance.amount,
+            ))
+}
+
+// trades that could be matched on their details, bucketed by date so only ones close enough are compared
+struct Candidates {
+    width: u64,
+    trades: Vec<Trade>,
+    buckets: HashMap<(String, String, String, u64), Vec<usize>>,
+}
+
+impl Candidates {
+    fn new(tolerance: &Tolerance) -> Candidates {
+        Candidates {
+            width: tolerance.date.max(1),
+            trades: vec![],
+            buckets: HashMap::new(),
+        }
+    }
+
+    fn bucket(trade: &Trade, bucket: u64) -> (String, String, String, u64) {
+        (
+            trade.exchange.to_lowercase(),
+            trade.bought_currency.clone(),
+            trade.sold_currency.clone(),
+            bucket,
+        )
+    }
+
+    fn insert(&mut self, trade: Trade) {
+        let bucket = Candidates::bucket(&trade, trade.date / self.width);
+        self.buckets
+            .entry(bucket)
+            .or_default()
+            .push(self.trades.len());
+        self.trades.push(trade);
+    }
+
+    // anything within the date tolerance is in the same bucket or one next to it, the first added wins
+    fn find(&self, trade: &Trade, matches: impl Fn(&Trade) -> bool) -> Option<&Trade> {
+        let bucket = trade.date / self.width;
+        (bucket.saturating_sub(1)..=bucket.saturating_add(1))
+            .filter_map(|bucket| self.buckets.get(&Candidates::bucket(trade, bucket)))
+            .flatten()
+            .filter(|index| matches(&self.trades[**index]))
+            .min()
+            .map(|index| &self.trades[*index])
+    }
+}
+
+pub fn merge_trades(
+    existing: Vec<Trade>,
+    imported: Vec<Trade>,
+    tolerance: &Tolerance,
+) -> MergeResult {
+    let mut result = MergeResult {
+        trades: existing,
+        ..Default::default()
+    };
+    result.trades.sort_by_key(|trade| trade.date);
+
+    let mut keys: HashMap<(String, String), Trade> = result
+        .trades
+        .iter()
+        .filter_map(|trade| key(trade).map(|key| (key, trade.clone())))
+        .collect();
+
+    let mut candidates = Candidates::new(tolerance);
+    for trade in result.trades.iter() {
+        candidates.insert(trade.clone());
+    }
+
+    let mut added: Vec<Trade> = vec![];
+    for trade in imported {
+        let trade_key = key(&trade);
+
+        // overlapping exports can repeat the same trade so earlier imports are checked as well
+        if let Some(existing) = trade_key.as_ref().and_then(|trade_key| keys.get(trade_key)) {
+            let duplicate = Duplicate {
+                existing: existing.clone(),
+                imported: trade.clone(),
+            };
+            if same_details(existing, &trade, tolerance) {
+                result.exact.push(duplicate);
+            } else {
+                result.conflicts.push(duplicate);
+            }
+            continue;
+        }
+
+        // trades with ids on the same exchange are only matched by id
+        let fuzzy = candidates.find(&trade, |existing| {
+            let both_keyed = trade_key.is_some() && key(existing).is_some();
+            !both_keyed && same_details(existing, &trade, tolerance)
+        });
+        if let Some(existing) = fuzzy {
+            result.probable.push(Duplicate {
+                existing: existing.clone(),
+                imported: trade,
+            });
+            continue;
+        }
+
+        if let Some(trade_key) = trade_key {
+            keys.insert(trade_key, trade.clone());
+        }
+        candidates.insert(trade.clone());
+        added.push(trade);
+    }
+
+    result.trades.append(&mut added);
+    result.trades.sort_by_key(|trade| trade.date);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_trades, Tolerance};
+    use crate::mocks;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::Decimal;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+
+    fn trade(id: &str, exchange_id: &str, date: u64, amount_sold: Decimal) -> Trade {
+        Trade {
+            exchange_id: exchange_id.to_string(),
+            exchange: "Kraken".to_string(),
+            fiat_rate: Some(dec!(10000)),
+            ..mocks::trade(id, FIAT_CURRENCY, "BTC", amount_sold, dec!(10000), date)
+        }
+    }
+
+    #[test]
+    fn exchange_ids_decide_duplicates() {
+        let existing = vec![
+            trade("a", "1", 1000, dec!(100)),
+            trade("b", "2", 2000, dec!(100)),
+        ];
+        let imported = vec![
+            trade("c", "1", 1000, dec!(100)),
+            trade("d", "2", 2000, dec!(200)),
+            trade("e", "3", 2000, dec!(100)),
+            trade("f", "3", 2000, dec!(100)),
+        ];
+
+        let result = merge_trades(existing, imported, &Tolerance::default());
+        assert_eq!(result.exact.len(), 2);
+        assert_eq!(result.exact[0].existing.id, "a");
+        assert_eq!(result.exact[1].existing.id, "e");
+        assert_eq!(result.conflicts[0].imported.id, "d");
+        assert!(result.probable.is_empty());
+        assert_eq!(result.trades.len(), 3);
+    }
+
+    #[test]
+    fn trades_without_ids_are_matched_within_tolerance() {
+        let existing = vec![trade("a", "1", 1000, dec!(100))];
+        let imported = vec![
+            trade("b", "", 61000, dec!(100.05)),
+            trade("c", "", 1000, dec!(150)),
+            trade("d", "", 600000, dec!(100)),
+        ];
+
+        let result = merge_trades(existing, imported, &Tolerance::default());
+        assert_eq!(result.probable.len(), 1);
+        assert_eq!(result.probable[0].imported.id, "b");
+        let ids: Vec<&str> = result
+            .trades
+            .iter()
+            .map(|trade| trade.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn details_are_only_matched_on_the_same_exchange() {
+        let existing = vec![trade("a", "", 1000, dec!(100))];
+        let imported = vec![
+            Trade {
+                exchange: "Binance".to_string(),
+                ..trade("b", "", 1000, dec!(100))
+            },
+            Trade {
+                exchange: "kraken".to_string(),
+                ..trade("c", "", 119000, dec!(100))
+            },
+        ];
+
+        let result = merge_trades(existing, imported, &Tolerance::default());
+        assert_eq!(result.probable.len(), 1);
+        assert_eq!(result.probable[0].imported.id, "c");
+        assert_eq!(result.trades.len(), 2);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::calculate_gains::CalculateGains;
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::method::Method;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct OutOfOrder {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub date: u64,
+    // date of the event already applied which this one would have to go before
+    pub after: u64,
+}
+
+// dates of the last events applied, anything earlier would change what has already been worked out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+struct Order {
+    #[serde(rename = "lastTradeDate")]
+    last_trade_date: Option<u64>,
+    #[serde(rename = "lastIncomeDate")]
+    last_income_date: Option<u64>,
+}
+
+impl Order {
+    // incomes on the same date as a trade are applied after it, so a trade has to be later than every income
+    fn trade(&mut self, trade: &Trade) -> Result<(), OutOfOrder> {
+        let out_of_order = |after: u64| OutOfOrder {
+            id: trade.id.clone(),
+            date: trade.date,
+            after,
+        };
+        if let Some(date) = self.last_trade_date.filter(|date| trade.date < *date) {
+            return Err(out_of_order(date));
+        }
+        if let Some(date) = self.last_income_date.filter(|date| trade.date <= *date) {
+            return Err(out_of_order(date));
+        }
+        self.last_trade_date = Some(trade.date);
+        Ok(())
+    }
+
+    fn income(&mut self, income: &Income) -> Result<(), OutOfOrder> {
+        let last_date = self.last_trade_date.max(self.last_income_date);
+        if let Some(date) = last_date.filter(|date| income.date < *date) {
+            return Err(OutOfOrder {
+                id: income.id.clone(),
+                date: income.date,
+                after: date,
+            });
+        }
+        self.last_income_date = Some(income.date);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Checkpoint {
+    pub holdings: Holdings,
+    #[serde(rename = "fiatCurrency")]
+    pub fiat_currency: String,
+    pub method: Method,
+    #[serde(rename = "shortTermGain")]
+    pub short_term_gain: Decimal,
+    #[serde(rename = "longTermGain")]
+    pub long_term_gain: Decimal,
+    #[serde(flatten)]
+    order: Order,
+}
+
+// keeps holdings and gains between calls so only new trades and incomes have to be applied
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Engine {
+    lots: LotStore,
+    fiat_currency: String,
+    method: Method,
+    short_term_gain: Decimal,
+    long_term_gain: Decimal,
+    order: Order,
+}
+
+#[wasm_bindgen]
+impl Engine {
+    #[wasm_bindgen(constructor)]
+    pub fn new_wasm(holdings: &JsValue, fiat_currency: String, method: Method) -> Engine {
+        let holdings: Holdings = if holdings.is_undefined() || holdings.is_null() {
+            Holdings::default()
+        } else {
+            holdings.into_serde().unwrap()
+        };
+        Engine::new(holdings, fiat_currency, method)
+    }
+
+    pub fn restore_wasm(checkpoint: &JsValue) -> Engine {
+        let checkpoint: Checkpoint = checkpoint.into_serde().unwrap();
+        Engine::restore(checkpoint)
+    }
+
+    pub fn checkpoint_wasm(&self) -> JsValue {
+        JsValue::from_serde(&self.checkpoint()).unwrap()
+    }
+
+    pub fn gains_wasm(&self) -> JsValue {
+        JsValue::from_serde(&self.gains()).unwrap()
+    }
+
+    pub fn add_wasm(&mut self, trades: &JsValue, incomes: &JsValue) -> Result<JsValue, JsValue> {
+        let trades: Vec<Trade> = trades.into_serde().unwrap();
+        let incomes: Vec<Income> = incomes.into_serde().unwrap();
+        self.add(trades, incomes)
+            .map(|trades| JsValue::from_serde(&trades).unwrap())
+            .map_err(|out_of_order| JsValue::from_serde(&out_of_order).unwrap())
+    }
+}
+
+impl Engine {
+    pub fn new(holdings: Holdings, fiat_currency: String, method: Method) -> Engine {
+        Engine {
+            lots: LotStore::from(holdings),
+            fiat_currency,
+            method,
+            short_term_gain: Zero::zero(),
+            long_term_gain: Zero::zero(),
+            order: Order::default(),
+        }
+    }
+
+    pub fn restore(checkpoint: Checkpoint) -> Engine {
+        Engine {
+            lots: LotStore::from(checkpoint.holdings),
+            fiat_currency: checkpoint.fiat_currency,
+            method: checkpoint.method,
+            short_term_gain: checkpoint.short_term_gain,
+            long_term_gain: checkpoint.long_term_gain,
+            order: checkpoint.order,
+        }
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            holdings: self.holdings(),
+            fiat_currency: self.fiat_currency.clone(),
+            method: self.method,
+            short_term_gain: self.short_term_gain,
+            long_term_gain: self.long_term_gain,
+            order: self.order,
+        }
+    }
+
+    pub fn holdings(&self) -> Holdings {
+        self.lots.clone().into()
+    }
+
+    // the same as calculate_gains over everything applied so far
+    pub fn gains(&self) -> CalculateGains {
+        CalculateGains {
+            new_holdings: self.holdings(),
+            short_term_gain: self.short_term_gain,
+            long_term_gain: self.long_term_gain,
+        }
+    }
+
+    pub fn add_income(&mut self, income: &Income) -> Result<(), OutOfOrder> {
+        self.order.income(income)?;
+        self.lots.add_income(income);
+        Ok(())
+    }
+
+    // returns the trade with the gains it made
+    pub fn process_trade(&mut self, trade: Trade) -> Result<Trade, OutOfOrder> {
+        self.order.trade(&trade)?;
+
+        let mut short_term_gain = Zero::zero();
+        let mut long_term_gain = Zero::zero();
+
+        // handle this better somewhere else
+        if trade.amount_sold > Zero::zero() {
+            let result = self
+                .lots
+                .process_trade(&trade, &self.fiat_currency, self.method);
+            short_term_gain = result.short_term_gain;
+            long_term_gain = result.long_term_gain;
+        }
+
+        self.short_term_gain += short_term_gain;
+        self.long_term_gain += long_term_gain;
+        Ok(Trade {
+            short_term: Some(short_term_gain),
+            long_term: Some(long_term_gain),
+            ..trade
+        })
+    }
+
+    // applies new trades and incomes in the same order calculate_gains would, nothing is applied if any are out of order
+    pub fn add(
+        &mut self,
+        trades: Vec<Trade>,
+        incomes: Vec<Income>,
+    ) -> Result<Vec<Trade>, OutOfOrder> {
+        let mut order = self.order;
+        for event in events(&trades, &incomes) {
+            match event {
+                Event::Trade(trade) => order.trade(trade)?,
+                Event::Income(income) => order.income(income)?,
+            }
+        }
+
+        let mut processed_trades = vec![];
+        for event in events(trades, incomes) {
+            match event {
+                Event::Trade(trade) => processed_trades.push(self.process_trade(trade)?),
+                Event::Income(income) => self.add_income(&income)?,
+            }
+        }
+        Ok(processed_trades)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Engine;
+    use crate::calculate_gains::calculate_gains;
+    use crate::income::Income;
+    use crate::method::Method;
+    use crate::mocks;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "FAKE";
+
+    fn income(id: &str, currency: &str, date: u64) -> Income {
+        Income {
+            amount: dec!(1),
+            currency: currency.to_string(),
+            transaction_id: None,
+            id: id.to_string(),
+            fee: None,
+            date,
+            fiat_rate: Some(dec!(10)),
+        }
+    }
+
+    #[test]
+    fn adding_in_batches_matches_calculate_gains() {
+        let holdings = mocks::mock_holdings(2, 5, None, None);
+        let mut trades = mocks::mock_trades(4, mocks::now_u64(), holdings.clone(), false);
+        trades.sort_by_key(|trade| trade.date);
+        let currency = trades[0].sold_currency.clone();
+        let mut incomes: Vec<Income> = trades
+            .iter()
+            .map(|trade| income(&format!("{}-income", trade.id), &currency, trade.date - 1))
+            .collect();
+        incomes.push(income("last", &currency, trades[7].date + 1));
+
+        let expected = calculate_gains(
+            holdings.clone(),
+            trades.clone(),
+            incomes.clone(),
+            FIAT_CURRENCY.to_string(),
+            Method::HCFO,
+        );
+
+        let mut engine = Engine::new(holdings, FIAT_CURRENCY.to_string(), Method::HCFO);
+        let later_trades = trades.split_off(4);
+        let later_incomes = incomes.split_off(4);
+        engine.add(trades, incomes).unwrap();
+
+        // the web app would keep the checkpoint between sessions
+        let checkpoint = engine.checkpoint();
+        let mut engine = Engine::restore(checkpoint);
+        engine.add(later_trades, later_incomes).unwrap();
+
+        assert_eq!(engine.gains(), expected);
+    }
+
+    #[test]
+    fn rejects_events_before_what_was_applied() {
+        let mut engine = Engine::new(
+            mocks::mock_holdings(0, 0, None, None),
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+        engine.add_income(&income("a", "BTC", 1000)).unwrap();
+
+        let error = engine.add_income(&income("b", "BTC", 999)).unwrap_err();
+        assert_eq!(error.id, "b");
+        assert_eq!(error.after, 1000);
+
+        // nothing from a batch is applied when part of it is out of order
+        let result = engine.add(
+            vec![],
+            vec![income("c", "BTC", 2000), income("d", "BTC", 500)],
+        );
+        assert!(result.is_err());
+        assert_eq!(engine.holdings().0.get("BTC").unwrap().len(), 1);
+    }
+}
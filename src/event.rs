@@ -0,0 +1,88 @@
+use crate::income::Income;
+use crate::trade::Trade;
+use std::borrow::Borrow;
+use std::iter::Peekable;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event<T, I> {
+    Trade(T),
+    Income(I),
+}
+
+// trades and incomes merged into the order they get applied in, both have to already be sorted by date
+pub struct Events<T: Iterator, I: Iterator> {
+    trades: Peekable<T>,
+    incomes: Peekable<I>,
+}
+
+impl<T, I> Iterator for Events<T, I>
+where
+    T: Iterator,
+    T::Item: Borrow<Trade>,
+    I: Iterator,
+    I::Item: Borrow<Income>,
+{
+    type Item = Event<T::Item, I::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let trade_date = self.trades.peek().map(|trade| trade.borrow().date);
+        match trade_date {
+            // incomes received on the same date as a trade come after it
+            Some(date) => match self.incomes.next_if(|income| date > income.borrow().date) {
+                Some(income) => Some(Event::Income(income)),
+                None => self.trades.next().map(Event::Trade),
+            },
+            None => self.incomes.next().map(Event::Income),
+        }
+    }
+}
+
+pub fn events<T, I>(trades: T, incomes: I) -> Events<T::IntoIter, I::IntoIter>
+where
+    T: IntoIterator,
+    T::Item: Borrow<Trade>,
+    I: IntoIterator,
+    I::Item: Borrow<Income>,
+{
+    Events {
+        trades: trades.into_iter().peekable(),
+        incomes: incomes.into_iter().peekable(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{events, Event};
+    use crate::income::Income;
+    use crate::mocks;
+    use rust_decimal_macros::*;
+
+    fn income(id: &str, date: u64) -> Income {
+        Income {
+            amount: dec!(1),
+            date,
+            currency: "BTC".to_string(),
+            id: id.to_string(),
+            fee: None,
+            fiat_rate: None,
+            transaction_id: None,
+        }
+    }
+
+    #[test]
+    fn incomes_go_before_later_trades() {
+        let holdings = mocks::mock_holdings(1, 1, None, None);
+        let mut trades = mocks::mock_trades(2, 1000, holdings, false);
+        trades[0].date = 1000;
+        trades[1].date = 2000;
+        let incomes = vec![income("a", 500), income("b", 1000), income("c", 3000)];
+
+        let order: Vec<String> = events(&trades, &incomes)
+            .map(|event| match event {
+                Event::Trade(trade) => trade.date.to_string(),
+                Event::Income(income) => income.id.clone(),
+            })
+            .collect();
+        assert_eq!(order, vec!["a", "1000", "b", "2000", "c"]);
+    }
+}
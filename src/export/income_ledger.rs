@@ -0,0 +1,225 @@
+use crate::date::{self, InvalidDate};
+use crate::income::Income;
+use rust_decimal::prelude::{Decimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct IncomeLedgerEntry {
+    pub date: u64,
+    pub currency: String,
+    pub amount: Decimal,
+    #[serde(rename = "fiatRate")]
+    pub fiat_rate: Decimal,
+    #[serde(rename = "fiatValue")]
+    pub fiat_value: Decimal,
+    pub fee: Decimal,
+    #[serde(rename = "feeFiatValue")]
+    pub fee_fiat_value: Decimal,
+    #[serde(rename = "transactionID")]
+    pub transaction_id: String,
+    #[serde(rename = "lotID")]
+    pub lot_id: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct IncomeTotal {
+    pub count: usize,
+    pub amounts: BTreeMap<String, Decimal>,
+    #[serde(rename = "fiatValue")]
+    pub fiat_value: Decimal,
+    #[serde(rename = "feeFiatValue")]
+    pub fee_fiat_value: Decimal,
+}
+
+impl IncomeTotal {
+    fn add(&mut self, entry: &IncomeLedgerEntry) {
+        self.count += 1;
+        *self.amounts.entry(entry.currency.clone()).or_default() += entry.amount;
+        self.fiat_value += entry.fiat_value;
+        self.fee_fiat_value += entry.fee_fiat_value;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct IncomeLedger {
+    pub entries: Vec<IncomeLedgerEntry>,
+    pub months: BTreeMap<String, IncomeTotal>,
+    pub years: BTreeMap<i32, IncomeTotal>,
+}
+
+#[wasm_bindgen]
+pub fn income_ledger_wasm(
+    incomes: &JsValue,
+    tax_year_starting_month: u32,
+) -> Result<JsValue, JsValue> {
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    income_ledger(incomes, tax_year_starting_month)
+        .map(|ledger| JsValue::from_serde(&ledger).unwrap())
+        .map_err(|error| JsValue::from_serde(&error).unwrap())
+}
+
+#[wasm_bindgen]
+pub fn income_ledger_csv_wasm(
+    incomes: &JsValue,
+    tax_year_starting_month: u32,
+) -> Result<String, JsValue> {
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    income_ledger(incomes, tax_year_starting_month)
+        .and_then(|ledger| ledger.to_csv())
+        .map_err(|error| JsValue::from_serde(&error).unwrap())
+}
+
+pub fn income_ledger(
+    incomes: Vec<Income>,
+    tax_year_starting_month: u32,
+) -> Result<IncomeLedger, InvalidDate> {
+    let mut ledger = IncomeLedger {
+        entries: vec![],
+        months: BTreeMap::new(),
+        years: BTreeMap::new(),
+    };
+
+    for (index, mut income) in incomes.into_iter().enumerate() {
+        income.fill_missing_id(index);
+        let fiat_rate = income.clone().fiat_rate();
+        let fee = income.fee.unwrap_or_else(Zero::zero);
+        let entry = IncomeLedgerEntry {
+            date: income.date,
+            currency: income.currency,
+            amount: income.amount,
+            fiat_rate,
+            fiat_value: income.amount * fiat_rate,
+            fee,
+            fee_fiat_value: fee * fiat_rate,
+            transaction_id: income.transaction_id.unwrap_or_default(),
+            lot_id: income.id,
+        };
+
+        ledger
+            .months
+            .entry(date::month(entry.date)?)
+            .or_default()
+            .add(&entry);
+        ledger
+            .years
+            .entry(date::tax_year(entry.date, tax_year_starting_month)?)
+            .or_default()
+            .add(&entry);
+        ledger.entries.push(entry);
+    }
+
+    Ok(ledger)
+}
+
+impl IncomeLedger {
+    pub fn to_csv(&self) -> Result<String, InvalidDate> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer
+            .write_record([
+                "Date",
+                "Currency",
+                "Amount",
+                "Fiat Rate",
+                "Fiat Value",
+                "Fee",
+                "Fee Fiat Value",
+                "Transaction ID",
+                "Lot ID",
+            ])
+            .unwrap();
+
+        for entry in self.entries.iter() {
+            writer
+                .write_record(&[
+                    date::to_iso_string(entry.date)?,
+                    entry.currency.clone(),
+                    entry.amount.to_string(),
+                    entry.fiat_rate.to_string(),
+                    entry.fiat_value.to_string(),
+                    entry.fee.to_string(),
+                    entry.fee_fiat_value.to_string(),
+                    entry.transaction_id.clone(),
+                    entry.lot_id.clone(),
+                ])
+                .unwrap();
+        }
+
+        Ok(String::from_utf8(writer.into_inner().unwrap()).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::income_ledger;
+    use crate::income::Income;
+    use rust_decimal_macros::*;
+
+    fn income(id: &str, date: u64) -> Income {
+        Income {
+            amount: dec!(2),
+            currency: "ETH".to_string(),
+            transaction_id: Some(format!("0x{}", id)),
+            id: id.to_string(),
+            fee: Some(dec!(0.1)),
+            date,
+            fiat_rate: Some(dec!(100)),
+        }
+    }
+
+    #[test]
+    fn income_ledger_groups_by_month_and_year() {
+        // 2021-06-15, 2021-06-20 and 2021-07-15
+        let ledger = income_ledger(
+            vec![
+                income("first", 1623715200000),
+                income("second", 1624147200000),
+                income("third", 1626307200000),
+            ],
+            7,
+        )
+        .unwrap();
+
+        assert_eq!(ledger.entries.len(), 3);
+        assert_eq!(ledger.entries[0].fiat_value, dec!(200));
+        assert_eq!(ledger.entries[0].fee_fiat_value, dec!(10));
+        assert_eq!(ledger.entries[0].lot_id, "first");
+
+        let june = ledger.months.get("2021-06").unwrap();
+        assert_eq!(june.count, 2);
+        assert_eq!(june.fiat_value, dec!(400));
+        assert_eq!(june.amounts.get("ETH"), Some(&dec!(4)));
+
+        assert_eq!(ledger.years.get(&2021).unwrap().count, 2);
+        assert_eq!(ledger.years.get(&2022).unwrap().count, 1);
+    }
+
+    #[test]
+    fn incomes_without_an_id_are_named_like_their_lot() {
+        let ledger = income_ledger(
+            vec![income("first", 1623715200000), income("", 1624147200000)],
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(ledger.entries[1].lot_id, "ETH-1624147200000-1");
+    }
+
+    #[test]
+    fn income_ledger_csv() {
+        let ledger = income_ledger(vec![income("first", 1623715200000)], 1).unwrap();
+        let csv = ledger.to_csv().unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "Date,Currency,Amount,Fiat Rate,Fiat Value,Fee,Fee Fiat Value,Transaction ID,Lot ID"
+        );
+        assert_eq!(
+            lines[1],
+            "2021-06-15T00:00:00.000Z,ETH,2,100,200,0.1,10.0,0xfirst,first"
+        );
+    }
+}
@@ -0,0 +1,577 @@
+use crate::date::{self, InvalidDate};
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
+use crate::holding::{CurrencyHolding, Holdings};
+use crate::income::Income;
+use crate::method::Method;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use wasm_bindgen::prelude::*;
+
+static CRYPTO_ACCOUNT: &str = "Assets:Crypto";
+static FIAT_ACCOUNT: &str = "Assets:Fiat";
+static SHORT_TERM_ACCOUNT: &str = "Income:Crypto:ShortTermGains";
+static LONG_TERM_ACCOUNT: &str = "Income:Crypto:LongTermGains";
+static INCOME_ACCOUNT: &str = "Income:Crypto:Income";
+static FEE_ACCOUNT: &str = "Expenses:Fees";
+static OPENING_ACCOUNT: &str = "Equity:Opening-Balances";
+static UNMATCHED_ACCOUNT: &str = "Equity:Unmatched";
+static ROUNDING_ACCOUNT: &str = "Equity:Rounding";
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum JournalFormat {
+    Beancount = "Beancount",
+    Ledger = "Ledger",
+}
+
+struct Lot {
+    cost: Decimal,
+    date: u64,
+    label: String,
+}
+
+struct Posting {
+    account: &'static str,
+    amount: Decimal,
+    currency: String,
+    lot: Option<Lot>,
+}
+
+struct Entry {
+    date: u64,
+    payee: String,
+    narration: String,
+    id: String,
+    postings: Vec<Posting>,
+}
+
+impl Entry {
+    fn post(&mut self, account: &'static str, amount: Decimal, currency: &str, lot: Option<Lot>) {
+        self.postings.push(Posting {
+            account,
+            amount,
+            currency: currency.to_string(),
+            lot,
+        });
+    }
+
+    // lots from holdings are labelled with the acquisition so a reduction finds the lot it was split from
+    fn post_holding(
+        &mut self,
+        account: &'static str,
+        amount: Decimal,
+        currency: &str,
+        holding: &CurrencyHolding,
+    ) {
+        self.post(
+            account,
+            amount,
+            currency,
+            Some(Lot {
+                cost: holding.rate_in_fiat,
+                date: holding.date,
+                label: holding.acquisition_id.clone(),
+            }),
+        );
+    }
+
+    // whatever is left over from dividing rates is booked so the entry balances exactly
+    fn balance(&mut self, fiat_currency: &str) {
+        let residual = self
+            .postings
+            .iter()
+            .fold(Zero::zero(), |acc: Decimal, posting| {
+                acc + match &posting.lot {
+                    Some(lot) => posting.amount * lot.cost,
+                    None => posting.amount,
+                }
+            });
+
+        if !residual.is_zero() {
+            self.post(ROUNDING_ACCOUNT, -residual, fiat_currency, None);
+        }
+    }
+}
+
+fn format_date(date: u64, separator: &str) -> Result<String, InvalidDate> {
+    date::format(date, &format!("%Y{}%m{}%d", separator, separator))
+}
+
+// beancount commodities have to start with a letter and can only hold a few symbols
+fn commodity(currency: &str, format: JournalFormat) -> String {
+    let currency = currency.to_uppercase();
+    match format {
+        JournalFormat::Beancount => {
+            let currency: String = currency
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+                        c
+                    } else {
+                        '-'
+                    }
+                })
+                .collect();
+            if currency.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                currency
+            } else {
+                format!("C{}", currency)
+            }
+        }
+        JournalFormat::Ledger => {
+            if currency.chars().all(|c| c.is_ascii_alphabetic()) {
+                currency
+            } else {
+                format!("\"{}\"", currency)
+            }
+        }
+        _ => currency,
+    }
+}
+
+fn write_entry(
+    journal: &mut String,
+    entry: &Entry,
+    fiat_currency: &str,
+    format: JournalFormat,
+) -> Result<(), InvalidDate> {
+    let fiat = commodity(fiat_currency, format);
+
+    match format {
+        JournalFormat::Ledger => {
+            writeln!(
+                journal,
+                "{} * {}",
+                format_date(entry.date, "/")?,
+                entry.payee
+            )
+            .unwrap();
+            writeln!(journal, "    ; {}", entry.narration).unwrap();
+            writeln!(journal, "    ; id: {}", entry.id).unwrap();
+        }
+        _ => {
+            writeln!(
+                journal,
+                "{} * \"{}\" \"{}\"",
+                format_date(entry.date, "-")?,
+                entry.payee.replace('"', "'"),
+                entry.narration.replace('"', "'")
+            )
+            .unwrap();
+            writeln!(journal, "  id: \"{}\"", entry.id.replace('"', "'")).unwrap();
+        }
+    }
+
+    for posting in entry.postings.iter() {
+        let amount = format!(
+            "{} {}",
+            posting.amount.normalize(),
+            commodity(&posting.currency, format)
+        );
+        let lot = match (&posting.lot, format) {
+            (None, _) => "".to_string(),
+            (Some(lot), JournalFormat::Ledger) => format!(
+                " {{{} {}}} [{}] ({})",
+                lot.cost.normalize(),
+                fiat,
+                format_date(lot.date, "/")?,
+                lot.label
+            ),
+            (Some(lot), _) => format!(
+                " {{{} {}, {}, \"{}\"}}",
+                lot.cost.normalize(),
+                fiat,
+                format_date(lot.date, "-")?,
+                lot.label.replace('"', "'")
+            ),
+        };
+        writeln!(journal, "  {}  {}{}", posting.account, amount, lot).unwrap();
+    }
+
+    journal.push('\n');
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn journal_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+    method: Method,
+    format: JournalFormat,
+) -> Result<String, JsValue> {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+
+    journal(holdings, trades, incomes, fiat_currency, method, format)
+        .map_err(|error| JsValue::from_serde(&error).unwrap())
+}
+
+fn income_entry(income: &Income, fiat_currency: &str) -> Entry {
+    let mut entry = Entry {
+        date: income.date,
+        payee: "Income".to_string(),
+        narration: format!("Received {} {}", income.amount.normalize(), income.currency),
+        id: income.id.clone(),
+        postings: vec![],
+    };
+    let fiat_rate = income.clone().fiat_rate();
+    entry.post(
+        CRYPTO_ACCOUNT,
+        income.amount,
+        &income.currency,
+        Some(Lot {
+            cost: fiat_rate,
+            date: income.date,
+            label: income.id.clone(),
+        }),
+    );
+    entry.post(
+        INCOME_ACCOUNT,
+        -income.amount * fiat_rate,
+        fiat_currency,
+        None,
+    );
+    entry
+}
+
+fn trade_entry(trade: &Trade, lots: &mut LotStore, fiat_currency: &str, method: Method) -> Entry {
+    let mut entry = Entry {
+        date: trade.date,
+        payee: trade.exchange.clone(),
+        narration: format!(
+            "Traded {} for {}",
+            trade.sold_currency, trade.bought_currency
+        ),
+        id: trade.id.clone(),
+        postings: vec![],
+    };
+
+    let result = lots.process_trade(trade, fiat_currency, method);
+
+    // a fee paid in another currency leaves from lots of its own at what they cost
+    for holding in result.fee_holdings.iter() {
+        entry.post_holding(
+            CRYPTO_ACCOUNT,
+            -holding.amount,
+            &trade.transaction_fee_currency,
+            holding,
+        );
+    }
+
+    if trade.sold_currency == fiat_currency {
+        let (amount, _) = trade.amount_bought(fiat_currency);
+        entry.post(FIAT_ACCOUNT, -trade.amount_sold, fiat_currency, None);
+        entry.post(
+            CRYPTO_ACCOUNT,
+            amount,
+            &trade.bought_currency,
+            Some(Lot {
+                cost: result
+                    .added_holding
+                    .as_ref()
+                    .map_or_else(|| trade.fiat_rate(), |holding| holding.rate_in_fiat),
+                date: trade.date,
+                label: trade.id.clone(),
+            }),
+        );
+        // anything paid over the lots cost is a fee
+        let fee = trade.amount_sold - amount * trade.fiat_rate();
+        if !fee.is_zero() {
+            entry.post(FEE_ACCOUNT, fee, fiat_currency, None);
+        }
+        return entry;
+    }
+
+    for holding in result.deducted_holdings.iter() {
+        let account = if holding.id.ends_with("/unmatched") {
+            UNMATCHED_ACCOUNT
+        } else {
+            CRYPTO_ACCOUNT
+        };
+        entry.post_holding(account, -holding.amount, &trade.sold_currency, holding);
+    }
+
+    let (amount_bought, _) = trade.amount_bought(fiat_currency);
+    if trade.bought_currency == fiat_currency {
+        entry.post(FIAT_ACCOUNT, amount_bought, fiat_currency, None);
+    } else {
+        entry.post(
+            CRYPTO_ACCOUNT,
+            amount_bought,
+            &trade.bought_currency,
+            Some(Lot {
+                cost: trade.fiat_rate() * trade.rate,
+                date: trade.date,
+                label: trade.id.clone(),
+            }),
+        );
+    }
+
+    if !result.short_term_gain.is_zero() {
+        entry.post(
+            SHORT_TERM_ACCOUNT,
+            -result.short_term_gain,
+            fiat_currency,
+            None,
+        );
+    }
+    if !result.long_term_gain.is_zero() {
+        entry.post(
+            LONG_TERM_ACCOUNT,
+            -result.long_term_gain,
+            fiat_currency,
+            None,
+        );
+    }
+
+    entry
+}
+
+pub fn journal(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+    method: Method,
+    format: JournalFormat,
+) -> Result<String, InvalidDate> {
+    let mut entries: Vec<Entry> = vec![];
+
+    let mut opening_holdings: Vec<(&String, &CurrencyHolding)> = holdings
+        .0
+        .iter()
+        .flat_map(|(currency, currency_holdings)| {
+            currency_holdings
+                .iter()
+                .map(move |currency_holding| (currency, currency_holding))
+        })
+        .collect();
+    opening_holdings.sort_by_key(|(_, currency_holding)| currency_holding.date);
+    for (currency, currency_holding) in opening_holdings {
+        let mut entry = Entry {
+            date: currency_holding.date,
+            payee: "Opening Balance".to_string(),
+            narration: format!("Opening {} lot", currency),
+            id: currency_holding.id.clone(),
+            postings: vec![],
+        };
+        entry.post_holding(
+            CRYPTO_ACCOUNT,
+            currency_holding.amount,
+            currency,
+            currency_holding,
+        );
+        entry.post(
+            OPENING_ACCOUNT,
+            -currency_holding.amount * currency_holding.rate_in_fiat,
+            &fiat_currency,
+            None,
+        );
+        entries.push(entry);
+    }
+
+    let mut lots = LotStore::from(holdings);
+
+    for event in events(trades, incomes) {
+        match event {
+            Event::Income(income) => {
+                lots.add_income(&income);
+                entries.push(income_entry(&income, &fiat_currency));
+            }
+            Event::Trade(trade) => {
+                // handle this better somewhere else
+                if trade.amount_sold > Zero::zero() {
+                    entries.push(trade_entry(&trade, &mut lots, &fiat_currency, method));
+                }
+            }
+        }
+    }
+
+    let mut journal = String::new();
+    let fiat = commodity(&fiat_currency, format);
+    let first_date = entries.iter().map(|entry| entry.date).min().unwrap_or(0);
+    let accounts = [
+        CRYPTO_ACCOUNT,
+        FIAT_ACCOUNT,
+        SHORT_TERM_ACCOUNT,
+        LONG_TERM_ACCOUNT,
+        INCOME_ACCOUNT,
+        FEE_ACCOUNT,
+        OPENING_ACCOUNT,
+        UNMATCHED_ACCOUNT,
+        ROUNDING_ACCOUNT,
+    ];
+
+    match format {
+        JournalFormat::Ledger => {
+            for account in accounts.iter() {
+                writeln!(journal, "account {}", account).unwrap();
+            }
+        }
+        _ => {
+            writeln!(journal, "option \"operating_currency\" \"{}\"", fiat).unwrap();
+            journal.push('\n');
+            for account in accounts.iter() {
+                // the unmatched account has to accept amounts which were never bought
+                let booking = if *account == UNMATCHED_ACCOUNT {
+                    " \"NONE\""
+                } else {
+                    ""
+                };
+                writeln!(
+                    journal,
+                    "{} open {}{}",
+                    format_date(first_date, "-")?,
+                    account,
+                    booking
+                )
+                .unwrap();
+            }
+        }
+    }
+    journal.push('\n');
+
+    for mut entry in entries {
+        entry.balance(&fiat_currency);
+        write_entry(&mut journal, &entry, &fiat_currency, format)?;
+    }
+
+    Ok(journal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{journal, JournalFormat};
+    use crate::date::InvalidDate;
+    use crate::holding::Holdings;
+    use crate::income::Income;
+    use crate::method::Method;
+    use crate::mocks;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::Decimal;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+    // 2021-01-01
+    const START_DATE: u64 = 1609459200000;
+    const DAY_IN_MILLISECONDS: u64 = 24 * 60 * 60 * 1000;
+
+    fn trade(
+        id: &str,
+        sold: &str,
+        bought: &str,
+        amount_sold: Decimal,
+        rate: Decimal,
+        fiat_rate: Decimal,
+        day: u64,
+    ) -> Trade {
+        Trade {
+            exchange: "Exchange".to_string(),
+            fiat_rate: Some(fiat_rate),
+            ..mocks::trade(
+                id,
+                sold,
+                bought,
+                amount_sold,
+                rate,
+                START_DATE + day * DAY_IN_MILLISECONDS,
+            )
+        }
+    }
+
+    fn history() -> (Vec<Trade>, Vec<Income>) {
+        (
+            vec![
+                trade(
+                    "buy",
+                    FIAT_CURRENCY,
+                    "BTC",
+                    dec!(300),
+                    dec!(300),
+                    dec!(300),
+                    0,
+                ),
+                trade(
+                    "sell",
+                    "BTC",
+                    FIAT_CURRENCY,
+                    dec!(0.4),
+                    dec!(0.0025),
+                    dec!(400),
+                    1,
+                ),
+            ],
+            vec![Income {
+                amount: dec!(2),
+                currency: "ETH".to_string(),
+                transaction_id: None,
+                id: "staking".to_string(),
+                fee: None,
+                date: START_DATE + 2 * DAY_IN_MILLISECONDS,
+                fiat_rate: Some(dec!(10)),
+            }],
+        )
+    }
+
+    #[test]
+    fn beancount_reduces_lots_and_books_gains() {
+        let (trades, incomes) = history();
+        let journal = journal(
+            Holdings::default(),
+            trades,
+            incomes,
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+            JournalFormat::Beancount,
+        )
+        .unwrap();
+
+        assert!(journal.contains("2021-01-01 open Assets:Crypto\n"));
+        assert!(journal.contains("  Assets:Crypto  1 BTC {300 USD, 2021-01-01, \"buy\"}\n"));
+        assert!(journal.contains("  Assets:Crypto  -0.4 BTC {300 USD, 2021-01-01, \"buy\"}\n"));
+        assert!(journal.contains("  Assets:Fiat  160 USD\n"));
+        assert!(journal.contains("  Income:Crypto:ShortTermGains  -40 USD\n"));
+        assert!(journal.contains("  Assets:Crypto  2 ETH {10 USD, 2021-01-03, \"staking\"}\n"));
+        assert!(journal.contains("  Income:Crypto:Income  -20 USD\n"));
+        assert!(!journal.contains("Equity:Rounding  "));
+    }
+
+    #[test]
+    fn ledger_uses_lot_annotations() {
+        let (trades, incomes) = history();
+        let journal = journal(
+            Holdings::default(),
+            trades,
+            incomes,
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+            JournalFormat::Ledger,
+        )
+        .unwrap();
+
+        assert!(journal.contains("2021/01/02 * Exchange\n"));
+        assert!(journal.contains("  Assets:Crypto  -0.4 BTC {300 USD} [2021/01/01] (buy)\n"));
+    }
+
+    #[test]
+    fn dates_past_what_a_calendar_can_show_are_an_error() {
+        let (mut trades, incomes) = history();
+        trades[1].date = u64::MAX;
+        let result = journal(
+            Holdings::default(),
+            trades,
+            incomes,
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+            JournalFormat::Beancount,
+        );
+
+        assert_eq!(result, Err(InvalidDate { date: u64::MAX }));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod income_ledger;
+pub mod journal;
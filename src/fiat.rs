@@ -0,0 +1,378 @@
+use crate::holding::{CurrencyHolding, Holdings};
+use crate::income::Income;
+use crate::price::fill::MissingRate;
+use crate::price::{PriceSource, PriceTable};
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, One, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+pub const FIAT_CURRENCIES: [&str; 22] = [
+    "USD", "EUR", "GBP", "JPY", "CAD", "AUD", "CHF", "CNY", "HKD", "NZD", "SEK", "NOK", "DKK",
+    "SGD", "KRW", "INR", "BRL", "MXN", "ZAR", "PLN", "TRY", "RUB",
+];
+
+fn default_fiat_currencies() -> Vec<String> {
+    FIAT_CURRENCIES
+        .iter()
+        .map(|fiat| fiat.to_string())
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct FiatSettings {
+    // every value ends up in this currency
+    #[serde(rename = "reportingCurrency")]
+    pub reporting_currency: String,
+    #[serde(rename = "fiatCurrencies", default = "default_fiat_currencies")]
+    pub fiat_currencies: Vec<String>,
+    // one unit of each other fiat currency in the reporting currency
+    #[serde(rename = "fxRates", default)]
+    pub fx_rates: PriceTable,
+    // keep other fiat currencies as lots so exchange rate moves are gains
+    #[serde(rename = "trackFiatLots", default)]
+    pub track_fiat_lots: bool,
+}
+
+impl FiatSettings {
+    pub fn new(reporting_currency: &str) -> FiatSettings {
+        FiatSettings {
+            reporting_currency: reporting_currency.to_string(),
+            fiat_currencies: default_fiat_currencies(),
+            fx_rates: PriceTable::default(),
+            track_fiat_lots: false,
+        }
+    }
+
+    // fiat other than the reporting currency
+    pub fn is_foreign_fiat(&self, currency: &str) -> bool {
+        currency != self.reporting_currency
+            && self.fiat_currencies.iter().any(|fiat| fiat == currency)
+    }
+
+    fn fx_rate(&self, currency: &str, date: u64) -> Option<Decimal> {
+        if currency == self.reporting_currency {
+            Some(Decimal::one())
+        } else {
+            self.fx_rates.rate(currency, &self.reporting_currency, date)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Conversion {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub currency: String,
+    pub date: u64,
+    pub rate: Decimal,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ConvertedData {
+    pub trades: Vec<Trade>,
+    pub incomes: Vec<Income>,
+    // tracked fiat spent without having been bought, held at the exchange rate of the day
+    pub holdings: Holdings,
+    // trades between fiat currencies which mean nothing once everything is in one currency
+    pub skipped: Vec<Trade>,
+    pub conversions: Vec<Conversion>,
+}
+
+#[wasm_bindgen]
+pub fn convert_to_reporting_currency_wasm(
+    trades: &JsValue,
+    incomes: &JsValue,
+    settings: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let trades: Vec<Trade> = trades.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    let settings: FiatSettings = settings.into_serde().unwrap();
+    convert_to_reporting_currency(trades, incomes, &settings)
+        .map(|converted| JsValue::from_serde(&converted).unwrap())
+        .map_err(|missing| JsValue::from_serde(&missing).unwrap())
+}
+
+struct Converter<'a> {
+    settings: &'a FiatSettings,
+    conversions: Vec<Conversion>,
+    missing: Vec<MissingRate>,
+}
+
+impl<'a> Converter<'a> {
+    fn fx_rate(&mut self, id: &str, currency: &str, date: u64) -> Decimal {
+        match self.settings.fx_rate(currency, date) {
+            Some(rate) => {
+                if currency != self.settings.reporting_currency {
+                    self.conversions.push(Conversion {
+                        id: id.to_string(),
+                        currency: currency.to_string(),
+                        date,
+                        rate,
+                    });
+                }
+                rate
+            }
+            None => {
+                self.missing.push(MissingRate {
+                    id: id.to_string(),
+                    currency: currency.to_string(),
+                    date,
+                });
+                Decimal::one()
+            }
+        }
+    }
+
+    // None when the trade only swaps one fiat currency for another
+    fn trade(&mut self, mut trade: Trade) -> Option<Trade> {
+        let settings = self.settings;
+        let reporting_currency = settings.reporting_currency.clone();
+        let sold_foreign = settings.is_foreign_fiat(&trade.sold_currency);
+        let bought_foreign = settings.is_foreign_fiat(&trade.bought_currency);
+        let sold_fiat = sold_foreign || trade.sold_currency == reporting_currency;
+        let bought_fiat = bought_foreign || trade.bought_currency == reporting_currency;
+
+        if sold_fiat && bought_fiat && !settings.track_fiat_lots {
+            return None;
+        }
+
+        let fee_foreign = settings.is_foreign_fiat(&trade.transaction_fee_currency)
+            && !(settings.track_fiat_lots
+                && (trade.transaction_fee_currency == trade.sold_currency
+                    || trade.transaction_fee_currency == trade.bought_currency));
+        if fee_foreign && !trade.transaction_fee.is_zero() {
+            let rate = self.fx_rate(&trade.id, &trade.transaction_fee_currency, trade.date);
+            trade.transaction_fee *= rate;
+            trade.transaction_fee_currency = reporting_currency.clone();
+        }
+
+        if settings.track_fiat_lots {
+            // other fiat is held like any other currency, priced by the exchange rate
+            if sold_foreign && trade.bought_currency == reporting_currency {
+                trade.fiat_rate = Some(Decimal::one() / trade.rate);
+            } else if sold_foreign {
+                trade.fiat_rate = Some(self.fx_rate(&trade.id, &trade.sold_currency, trade.date));
+            } else if bought_foreign && sold_fiat {
+                trade.fiat_rate = Some(trade.rate);
+            } else if bought_foreign {
+                let rate = self.fx_rate(&trade.id, &trade.bought_currency, trade.date);
+                trade.fiat_rate = Some(rate / trade.rate);
+            }
+        } else if sold_foreign {
+            let rate = self.fx_rate(&trade.id, &trade.sold_currency, trade.date);
+            trade.sold_currency = reporting_currency;
+            trade.amount_sold *= rate;
+            trade.rate *= rate;
+            trade.fiat_rate = Some(trade.rate);
+        } else if bought_foreign {
+            let rate = self.fx_rate(&trade.id, &trade.bought_currency, trade.date);
+            trade.fiat_rate = Some(rate / trade.rate);
+            trade.bought_currency = reporting_currency;
+            trade.rate /= rate;
+        }
+
+        Some(trade)
+    }
+
+    fn income(&mut self, mut income: Income) -> Income {
+        if self.settings.is_foreign_fiat(&income.currency) {
+            let rate = self.fx_rate(&income.id, &income.currency, income.date);
+            if self.settings.track_fiat_lots {
+                income.fiat_rate = Some(rate);
+            } else {
+                income.currency = self.settings.reporting_currency.clone();
+                income.amount *= rate;
+                income.fee = income.fee.map(|fee| fee * rate);
+                income.fiat_rate = Some(Decimal::one());
+            }
+        }
+        income
+    }
+}
+
+// turns trades and incomes made in several fiat currencies into ones in the reporting currency
+pub fn convert_to_reporting_currency(
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    settings: &FiatSettings,
+) -> Result<ConvertedData, Vec<MissingRate>> {
+    let mut converter = Converter {
+        settings,
+        conversions: vec![],
+        missing: vec![],
+    };
+    let mut result = ConvertedData::default();
+
+    for trade in trades {
+        match converter.trade(trade.clone()) {
+            Some(converted) => result.trades.push(converted),
+            None => result.skipped.push(trade),
+        }
+    }
+    result.incomes = incomes
+        .into_iter()
+        .map(|income| converter.income(income))
+        .collect();
+
+    if settings.track_fiat_lots {
+        result.holdings = fiat_shortfalls(&result.trades, &result.incomes, &mut converter);
+    }
+
+    if !converter.missing.is_empty() {
+        return Err(converter.missing);
+    }
+    result.conversions = converter.conversions;
+    Ok(result)
+}
+
+// fiat has usually been deposited rather than bought, so a lot is made for whatever wasn't there
+fn fiat_shortfalls(trades: &[Trade], incomes: &[Income], converter: &mut Converter) -> Holdings {
+    let settings = converter.settings;
+    let mut movements: Vec<(u64, &str, Decimal, Option<&Trade>)> = vec![];
+    for trade in trades {
+        if settings.is_foreign_fiat(&trade.bought_currency) {
+            let (amount, _) = trade.amount_bought(&settings.reporting_currency);
+            movements.push((trade.date, &trade.bought_currency, amount, None));
+        }
+        if settings.is_foreign_fiat(&trade.sold_currency) {
+            movements.push((
+                trade.date,
+                &trade.sold_currency,
+                -trade.amount_sold,
+                Some(trade),
+            ));
+        }
+    }
+    for income in incomes
+        .iter()
+        .filter(|income| settings.is_foreign_fiat(&income.currency))
+    {
+        movements.push((income.date, &income.currency, income.amount, None));
+    }
+    movements.sort_by_key(|movement| movement.0);
+
+    let mut balances: HashMap<&str, Decimal> = HashMap::new();
+    let mut holdings = Holdings::default();
+    for (date, currency, amount, trade) in movements {
+        let balance = balances.entry(currency).or_insert_with(Zero::zero);
+        *balance += amount;
+        if let (Some(trade), true) = (trade, *balance < Zero::zero()) {
+            let id = format!("{}/deposit", trade.id);
+            let rate = converter.fx_rate(&id, currency, date);
+            holdings
+                .0
+                .entry(currency.to_string())
+                .or_default()
+                .push(CurrencyHolding {
+                    amount: -*balance,
+                    rate_in_fiat: rate,
+                    date,
+                    location: trade.exchange.clone(),
+                    acquisition_id: id.clone(),
+                    id,
+                    parent_ids: vec![],
+                });
+            *balance = Zero::zero();
+        }
+    }
+
+    holdings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_to_reporting_currency, FiatSettings};
+    use crate::calculate_gains::calculate_gains;
+    use crate::method::Method;
+    use crate::mocks;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::Decimal;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "EUR";
+
+    fn trade(
+        id: &str,
+        sold_currency: &str,
+        bought_currency: &str,
+        amount_sold: Decimal,
+        rate: Decimal,
+        date: u64,
+    ) -> Trade {
+        mocks::trade(id, sold_currency, bought_currency, amount_sold, rate, date)
+    }
+
+    fn settings() -> FiatSettings {
+        let mut settings = FiatSettings::new(FIAT_CURRENCY);
+        settings
+            .fx_rates
+            .add_price("USD", FIAT_CURRENCY, 0, dec!(0.8));
+        settings
+            .fx_rates
+            .add_price("USD", FIAT_CURRENCY, 2000, dec!(0.9));
+        settings
+    }
+
+    #[test]
+    fn converts_foreign_fiat_into_the_reporting_currency() {
+        let trades = vec![
+            trade("buy", "USD", "BTC", dec!(10000), dec!(10000), 1000),
+            trade("sell", "BTC", "USD", dec!(1), dec!(0.00005), 3000),
+        ];
+        let converted = convert_to_reporting_currency(trades, vec![], &settings()).unwrap();
+
+        assert_eq!(converted.trades[0].sold_currency, FIAT_CURRENCY);
+        assert_eq!(converted.trades[0].amount_sold, dec!(8000));
+        assert_eq!(converted.trades[0].fiat_rate, Some(dec!(8000)));
+        assert_eq!(converted.trades[1].bought_currency, FIAT_CURRENCY);
+        assert_eq!(converted.trades[1].fiat_rate, Some(dec!(18000)));
+        assert_eq!(converted.conversions.len(), 2);
+
+        let gains = calculate_gains(
+            converted.holdings,
+            converted.trades,
+            converted.incomes,
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+        assert_eq!(gains.short_term_gain, dec!(10000));
+
+        assert!(convert_to_reporting_currency(
+            vec![trade("buy", "GBP", "BTC", dec!(1), dec!(1), 1000)],
+            vec![],
+            &settings()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn tracked_fiat_has_exchange_rate_gains() {
+        let mut settings = settings();
+        settings.track_fiat_lots = true;
+        let trades = vec![
+            trade("deposit", FIAT_CURRENCY, "USD", dec!(800), dec!(0.8), 500),
+            trade("buy", "USD", "BTC", dec!(2000), dec!(10000), 3000),
+            trade("swap", "USD", FIAT_CURRENCY, dec!(100), dec!(1.25), 3000),
+        ];
+        let converted = convert_to_reporting_currency(trades, vec![], &settings).unwrap();
+
+        assert!(converted.skipped.is_empty());
+        assert_eq!(converted.trades[1].fiat_rate, Some(dec!(0.9)));
+        let deposits = converted.holdings.0.get("USD").unwrap();
+        assert_eq!(deposits[0].amount, dec!(1000));
+        assert_eq!(deposits[0].rate_in_fiat, dec!(0.9));
+        assert_eq!(deposits[1].amount, dec!(100));
+
+        let gains = calculate_gains(
+            converted.holdings,
+            converted.trades,
+            converted.incomes,
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+        // 1000 USD bought at 0.8 was spent at 0.9, then 100 USD held at 0.9 went for 0.8
+        assert_eq!(gains.short_term_gain, dec!(90));
+    }
+}
@@ -9,12 +9,16 @@ impl Holdings {
         fiat_rate: Decimal,
         date: u64,
         location: Option<String>,
+        acquisition_id: String,
     ) -> Holdings {
         let currency_holding = CurrencyHolding {
             amount,
             rate_in_fiat: fiat_rate,
             date,
             location: location.unwrap_or_else(|| "".to_owned()),
+            id: acquisition_id.clone(),
+            acquisition_id,
+            parent_ids: vec![],
         };
 
         if let Some(currency_holdings) = self.0.get_mut(&currency) {
@@ -44,6 +48,7 @@ mod tests {
             Zero::zero(),
             1234,
             None,
+            "acquisition".to_string(),
         );
 
         assert_eq!(new_holdings.0.keys().len(), 1);
@@ -62,6 +67,7 @@ mod tests {
             Zero::zero(),
             1234,
             None,
+            "acquisition".to_string(),
         );
 
         assert_eq!(new_holdings.0.keys().len(), 1);
@@ -71,4 +77,23 @@ mod tests {
             .expect("Unable to get currency holding");
         assert_eq!(currency_holding.len(), 4);
     }
+
+    #[test]
+    fn new_currency_holding_is_identified_by_acquisition() {
+        let holdings = mocks::mock_holdings(0, 0, None, None);
+
+        let new_holdings = holdings.add_to_currency_holdings(
+            "BTC".to_string(),
+            Zero::zero(),
+            Zero::zero(),
+            1234,
+            None,
+            "acquisition".to_string(),
+        );
+
+        let currency_holding = &new_holdings.0.get("BTC").unwrap()[0];
+        assert_eq!(currency_holding.id, "acquisition");
+        assert_eq!(currency_holding.acquisition_id, "acquisition");
+        assert!(currency_holding.parent_ids.is_empty());
+    }
 }
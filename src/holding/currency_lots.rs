@@ -0,0 +1,292 @@
+use crate::holding::CurrencyHolding;
+use crate::method::Method;
+use crate::YEAR_IN_MILLISECONDS;
+use rust_decimal::prelude::Decimal;
+use std::collections::{BTreeMap, BTreeSet};
+
+// lots are keyed by the order they were added in, which is also first in first out
+type RateKey = (Decimal, usize);
+
+fn is_long_term(acquired: u64, date: u64) -> bool {
+    acquired.saturating_add(YEAR_IN_MILLISECONDS) <= date
+}
+
+// short and long term lots as of a date, each ordered by rate
+#[derive(Clone, Debug)]
+struct AgeIndex {
+    date: u64,
+    short_term: BTreeSet<RateKey>,
+    long_term: BTreeSet<RateKey>,
+    // short term lots by when they were acquired so the ones turning long term are found first
+    acquired: BTreeSet<(u64, usize)>,
+}
+
+impl AgeIndex {
+    fn new(lots: &BTreeMap<usize, CurrencyHolding>, date: u64) -> AgeIndex {
+        let mut index = AgeIndex {
+            date,
+            short_term: BTreeSet::new(),
+            long_term: BTreeSet::new(),
+            acquired: BTreeSet::new(),
+        };
+        for (key, lot) in lots.iter() {
+            index.insert(*key, lot);
+        }
+        index
+    }
+
+    fn insert(&mut self, key: usize, lot: &CurrencyHolding) {
+        if is_long_term(lot.date, self.date) {
+            self.long_term.insert((lot.rate_in_fiat, key));
+        } else {
+            self.short_term.insert((lot.rate_in_fiat, key));
+            self.acquired.insert((lot.date, key));
+        }
+    }
+
+    fn remove(&mut self, key: usize, lot: &CurrencyHolding) {
+        self.short_term.remove(&(lot.rate_in_fiat, key));
+        self.long_term.remove(&(lot.rate_in_fiat, key));
+        self.acquired.remove(&(lot.date, key));
+    }
+
+    // moves lots which have now been held for a year into the long term bucket
+    fn advance(&mut self, lots: &BTreeMap<usize, CurrencyHolding>, date: u64) {
+        self.date = date;
+        while let Some(&(acquired, key)) = self.acquired.iter().next() {
+            if !is_long_term(acquired, date) {
+                break;
+            }
+            self.acquired.remove(&(acquired, key));
+            let rate = lots[&key].rate_in_fiat;
+            self.short_term.remove(&(rate, key));
+            self.long_term.insert((rate, key));
+        }
+    }
+}
+
+fn first_with_rate(keys: &BTreeSet<RateKey>, rate: Decimal) -> Option<usize> {
+    keys.range((rate, 0)..=(rate, usize::MAX))
+        .next()
+        .map(|(_, key)| *key)
+}
+
+fn last_with_rate(keys: &BTreeSet<RateKey>, rate: Decimal) -> Option<usize> {
+    keys.range((rate, 0)..=(rate, usize::MAX))
+        .next_back()
+        .map(|(_, key)| *key)
+}
+
+// the lots of one currency, only the ordering the method in use needs is kept up to date
+#[derive(Clone, Debug, Default)]
+pub struct CurrencyLots {
+    lots: BTreeMap<usize, CurrencyHolding>,
+    next_key: usize,
+    amount: Decimal,
+    by_rate: Option<BTreeSet<RateKey>>,
+    by_age: Option<AgeIndex>,
+}
+
+impl PartialEq for CurrencyLots {
+    fn eq(&self, other: &CurrencyLots) -> bool {
+        self.lots.values().eq(other.lots.values())
+    }
+}
+
+impl From<Vec<CurrencyHolding>> for CurrencyLots {
+    fn from(currency_holdings: Vec<CurrencyHolding>) -> CurrencyLots {
+        let mut currency_lots = CurrencyLots::default();
+        for currency_holding in currency_holdings {
+            currency_lots.push(currency_holding);
+        }
+        currency_lots
+    }
+}
+
+impl From<CurrencyLots> for Vec<CurrencyHolding> {
+    fn from(currency_lots: CurrencyLots) -> Vec<CurrencyHolding> {
+        currency_lots.lots.into_values().collect()
+    }
+}
+
+impl CurrencyLots {
+    pub fn len(&self) -> usize {
+        self.lots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lots.is_empty()
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &CurrencyHolding> {
+        self.lots.values()
+    }
+
+    pub fn get(&self, key: usize) -> Option<&CurrencyHolding> {
+        self.lots.get(&key)
+    }
+
+    pub fn push(&mut self, lot: CurrencyHolding) {
+        let key = self.next_key;
+        self.next_key += 1;
+        self.amount += lot.amount;
+        if let Some(by_rate) = self.by_rate.as_mut() {
+            by_rate.insert((lot.rate_in_fiat, key));
+        }
+        if let Some(by_age) = self.by_age.as_mut() {
+            by_age.insert(key, &lot);
+        }
+        self.lots.insert(key, lot);
+    }
+
+    // only the amount changes so the orderings stay valid
+    pub fn reduce(&mut self, key: usize, amount: Decimal) {
+        if let Some(lot) = self.lots.get_mut(&key) {
+            lot.amount -= amount;
+            self.amount -= amount;
+        }
+    }
+
+    pub fn remove(&mut self, key: usize) -> Option<CurrencyHolding> {
+        let lot = self.lots.remove(&key)?;
+        self.amount -= lot.amount;
+        if let Some(by_rate) = self.by_rate.as_mut() {
+            by_rate.remove(&(lot.rate_in_fiat, key));
+        }
+        if let Some(by_age) = self.by_age.as_mut() {
+            by_age.remove(key, &lot);
+        }
+        Some(lot)
+    }
+
+    fn by_rate(&mut self) -> &BTreeSet<RateKey> {
+        self.by_age = None;
+        let lots = &self.lots;
+        self.by_rate.get_or_insert_with(|| {
+            lots.iter()
+                .map(|(key, lot)| (lot.rate_in_fiat, *key))
+                .collect()
+        })
+    }
+
+    fn by_age(&mut self, date: u64) -> &AgeIndex {
+        self.by_rate = None;
+        // lots only ever move from short to long term, going back in time starts over
+        if self
+            .by_age
+            .as_ref()
+            .is_some_and(|by_age| date < by_age.date)
+        {
+            self.by_age = None;
+        }
+        let lots = &self.lots;
+        let by_age = self.by_age.get_or_insert_with(|| AgeIndex::new(lots, date));
+        by_age.advance(lots, date);
+        by_age
+    }
+
+    // picks the same lot get_currency_holding would for lots acquired before the date, ties included
+    pub fn select(&mut self, method: Method, date: u64) -> Option<usize> {
+        match method {
+            Method::LIFO => self.lots.keys().next_back().copied(),
+            // highest cost goes last on ties
+            Method::HCFO => self.by_rate().iter().next_back().map(|(_, key)| *key),
+            // lowest cost goes last on ties
+            Method::LCFO => {
+                let by_rate = self.by_rate();
+                let (rate, _) = *by_rate.iter().next()?;
+                last_with_rate(by_rate, rate)
+            }
+            // short term is always taxed higher, then the lowest cost within it
+            Method::HTFO => {
+                let by_age = self.by_age(date);
+                by_age
+                    .short_term
+                    .iter()
+                    .next()
+                    .or_else(|| by_age.long_term.iter().next())
+                    .map(|(_, key)| *key)
+            }
+            // long term is always taxed lower, then the highest cost within it
+            Method::LTFO => {
+                let by_age = self.by_age(date);
+                let bucket = if by_age.long_term.is_empty() {
+                    &by_age.short_term
+                } else {
+                    &by_age.long_term
+                };
+                let (rate, _) = *bucket.iter().next_back()?;
+                first_with_rate(bucket, rate)
+            }
+            _ => self.lots.keys().next().copied(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CurrencyLots;
+    use crate::holding::CurrencyHolding;
+    use crate::holding_selection::get_currency_holding::get_currency_holding;
+    use crate::method::{Method, ALL_METHODS};
+    use crate::mocks;
+    use crate::YEAR_IN_MILLISECONDS;
+    use rust_decimal::prelude::Decimal;
+    use rust_decimal_macros::*;
+
+    fn lot(id: &str, rate_in_fiat: Decimal, date: u64) -> CurrencyHolding {
+        CurrencyHolding {
+            amount: dec!(1),
+            rate_in_fiat,
+            date,
+            location: "".to_string(),
+            id: id.to_string(),
+            acquisition_id: id.to_string(),
+            parent_ids: vec![],
+        }
+    }
+
+    #[test]
+    fn selects_like_get_currency_holding() {
+        let holdings = mocks::mock_holdings(1, 30, None, None);
+        let trade = mocks::mock_trades(1, mocks::now_u64(), holdings.clone(), false).remove(0);
+        let currency_holdings = holdings.0.values().next().unwrap().clone();
+
+        for method in ALL_METHODS.iter() {
+            let mut expected = currency_holdings.clone();
+            let mut currency_lots = CurrencyLots::from(currency_holdings.clone());
+            while !expected.is_empty() {
+                let index = get_currency_holding(&expected, *method, trade.clone());
+                let key = currency_lots.select(*method, trade.date).unwrap();
+                assert_eq!(currency_lots.remove(key), Some(expected.remove(index)));
+            }
+            assert_eq!(currency_lots.select(*method, trade.date), None);
+        }
+    }
+
+    #[test]
+    fn lots_turn_long_term_as_time_passes() {
+        let mut currency_lots = CurrencyLots::from(vec![
+            lot("old", dec!(1), 0),
+            lot("new", dec!(2), YEAR_IN_MILLISECONDS / 2),
+        ]);
+        let selected = |currency_lots: &mut CurrencyLots, date: u64| {
+            let key = currency_lots.select(Method::LTFO, date).unwrap();
+            currency_lots.get(key).unwrap().id.clone()
+        };
+
+        assert_eq!(
+            selected(&mut currency_lots, YEAR_IN_MILLISECONDS / 2),
+            "new"
+        );
+        assert_eq!(selected(&mut currency_lots, YEAR_IN_MILLISECONDS), "old");
+        assert_eq!(
+            selected(&mut currency_lots, YEAR_IN_MILLISECONDS / 2),
+            "new"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use crate::holding::currency_lots::CurrencyLots;
+use crate::holding::{CurrencyHolding, Holdings};
+use crate::income::Income;
+use crate::method::Method;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use rust_decimal_macros::*;
+use std::collections::HashMap;
+
+// holdings kept for a whole calculation, lots are found and taken out without going through the rest
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LotStore(HashMap<String, CurrencyLots>);
+
+impl From<Holdings> for LotStore {
+    fn from(holdings: Holdings) -> LotStore {
+        LotStore(
+            holdings
+                .0
+                .into_iter()
+                .map(|(currency, mut currency_holdings)| {
+                    // lots are followed by id so ones without have to be told apart
+                    for (index, currency_holding) in currency_holdings.iter_mut().enumerate() {
+                        currency_holding.fill_missing_ids(&currency, index);
+                    }
+                    (currency, currency_holdings.into())
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<LotStore> for Holdings {
+    fn from(lots: LotStore) -> Holdings {
+        Holdings(
+            lots.0
+                .into_iter()
+                .map(|(currency, currency_lots)| (currency, currency_lots.into()))
+                .collect(),
+        )
+    }
+}
+
+impl LotStore {
+    pub fn lots<'a>(
+        &'a self,
+        currency: &str,
+    ) -> impl DoubleEndedIterator<Item = &'a CurrencyHolding> {
+        self.0
+            .get(currency)
+            .into_iter()
+            .flat_map(CurrencyLots::iter)
+    }
+
+    pub fn amount(&self, currency: &str) -> Decimal {
+        self.0
+            .get(currency)
+            .map_or_else(Zero::zero, CurrencyLots::amount)
+    }
+
+    pub fn count(&self, currency: &str) -> usize {
+        self.0.get(currency).map_or(0, CurrencyLots::len)
+    }
+
+    pub fn add_to_currency_holdings(
+        &mut self,
+        currency: String,
+        amount: Decimal,
+        fiat_rate: Decimal,
+        date: u64,
+        location: Option<String>,
+        acquisition_id: String,
+    ) {
+        self.0.entry(currency).or_default().push(CurrencyHolding {
+            amount,
+            rate_in_fiat: fiat_rate,
+            date,
+            location: location.unwrap_or_else(|| "".to_owned()),
+            id: acquisition_id.clone(),
+            acquisition_id,
+            parent_ids: vec![],
+        });
+    }
+
+    pub fn add_income(&mut self, income: &Income) {
+        self.add_to_currency_holdings(
+            income.currency.clone(),
+            income.amount,
+            income.clone().fiat_rate(),
+            income.date,
+            None,
+            income.id.clone(),
+        );
+    }
+
+    // takes pieces out of the lots the method picks until the amount is covered, returns them with what is left uncovered
+    fn take(
+        &mut self,
+        currency: &str,
+        mut amount: Decimal,
+        date: u64,
+        method: Method,
+        piece_id: &str,
+        keep_whole_lots: bool,
+    ) -> (Vec<CurrencyHolding>, Decimal) {
+        let mut pieces = vec![];
+
+        if let Some(currency_lots) = self.0.get_mut(currency) {
+            while !amount.is_zero() {
+                let key = match currency_lots.select(method, date) {
+                    Some(key) => key,
+                    None => break,
+                };
+
+                let currency_holding = currency_lots.get(key).unwrap();
+                if currency_holding.amount > amount {
+                    // the lot is split, the remainder keeps its id and the piece points back to it
+                    pieces.push(CurrencyHolding {
+                        amount,
+                        id: format!("{}/{}", currency_holding.id, piece_id),
+                        parent_ids: vec![currency_holding.id.clone()],
+                        ..currency_holding.clone()
+                    });
+                    currency_lots.reduce(key, amount);
+                    amount = Zero::zero();
+                } else {
+                    amount -= currency_holding.amount;
+                    let currency_holding = currency_lots.remove(key).unwrap();
+                    pieces.push(if keep_whole_lots {
+                        currency_holding
+                    } else {
+                        CurrencyHolding {
+                            id: format!("{}/{}", currency_holding.id, piece_id),
+                            parent_ids: vec![currency_holding.id.clone()],
+                            ..currency_holding
+                        }
+                    });
+                }
+            }
+        }
+
+        (pieces, amount)
+    }
+
+    // takes the amount sold out of the lots the method picks, whatever they don't cover gets a placeholder lot
+    pub fn deduct(
+        &mut self,
+        trade: &Trade,
+        fiat_currency: &str,
+        method: Method,
+    ) -> Vec<CurrencyHolding> {
+        let (mut deducted_holdings, amount_left) = self.take(
+            &trade.sold_currency,
+            trade.amount_sold,
+            trade.date,
+            method,
+            &trade.id,
+            true,
+        );
+
+        if !amount_left.is_zero() {
+            // nothing was held to cover the rest so the trade itself is all it can be traced back to
+            deducted_holdings.push(CurrencyHolding {
+                amount: amount_left,
+                date: trade.date,
+                rate_in_fiat: if trade.sold_currency == fiat_currency {
+                    dec!(1)
+                } else {
+                    Zero::zero()
+                },
+                location: trade.exchange.clone(),
+                id: format!("{}/unmatched", trade.id),
+                acquisition_id: trade.id.clone(),
+                parent_ids: vec![],
+            });
+        }
+
+        deducted_holdings
+    }
+
+    // a fee paid in another currency uses up lots of its own, whatever isn't held was never paid for so it costs nothing
+    pub fn deduct_fee(
+        &mut self,
+        trade: &Trade,
+        fiat_currency: &str,
+        method: Method,
+    ) -> Vec<CurrencyHolding> {
+        if !trade.fee_in_other_currency(fiat_currency) {
+            return vec![];
+        }
+
+        self.take(
+            &trade.transaction_fee_currency,
+            trade.transaction_fee,
+            trade.date,
+            method,
+            &format!("{}-fee", trade.id),
+            true,
+        )
+        .0
+    }
+
+    // takes an amount out first in first out to be moved somewhere else, every piece gets an id of its own
+    pub fn withdraw(
+        &mut self,
+        currency: &str,
+        amount: Decimal,
+        date: u64,
+        move_id: &str,
+    ) -> Vec<CurrencyHolding> {
+        self.take(currency, amount, date, Method::FIFO, move_id, false)
+            .0
+    }
+
+    // lots go in by when they were acquired so the oldest are still used first
+    pub fn deposit(&mut self, currency: &str, lots: Vec<CurrencyHolding>, location: &str) {
+        let mut currency_holdings: Vec<CurrencyHolding> =
+            self.0.remove(currency).map(Vec::from).unwrap_or_default();
+        currency_holdings.extend(lots.into_iter().map(|lot| CurrencyHolding {
+            location: location.to_owned(),
+            ..lot
+        }));
+        currency_holdings.sort_by_key(|currency_holding| currency_holding.date);
+        self.0.insert(currency.to_owned(), currency_holdings.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LotStore;
+    use crate::holding::Holdings;
+    use crate::method::Method;
+    use crate::mocks;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "FAKE";
+
+    #[test]
+    fn used_lots_are_taken_out() {
+        let holdings = mocks::mock_holdings(1, 3, None, None);
+        let currency = holdings.0.keys().next().unwrap().clone();
+        let currency_holdings = holdings.0.get(&currency).unwrap().clone();
+        let mut trades = mocks::mock_trades(1, mocks::now_u64(), holdings.clone(), false);
+        trades[0].amount_sold = currency_holdings[2].amount + currency_holdings[1].amount;
+        trades[0].bought_currency = FIAT_CURRENCY.to_string();
+
+        let mut lots = LotStore::from(holdings);
+        let deducted_holdings = lots.deduct(&trades[0], FIAT_CURRENCY, Method::LIFO);
+
+        assert_eq!(
+            deducted_holdings,
+            vec![currency_holdings[2].clone(), currency_holdings[1].clone()]
+        );
+        let new_holdings = Holdings::from(lots);
+        assert_eq!(
+            new_holdings.0.get(&currency).unwrap(),
+            &vec![currency_holdings[0].clone()]
+        );
+    }
+
+    #[test]
+    fn moved_lots_point_back_to_where_they_came_from() {
+        let mut holdings = mocks::mock_holdings(1, 2, None, None);
+        let currency = holdings.0.keys().next().unwrap().clone();
+        let currency_holdings = holdings.0.get_mut(&currency).unwrap();
+        currency_holdings[0].amount = dec!(1);
+        currency_holdings[1].amount = dec!(1);
+        currency_holdings[1].id = "".to_string();
+        let first = currency_holdings[0].clone();
+
+        let mut lots = LotStore::from(holdings);
+        let second_id = lots.lots(&currency).nth(1).unwrap().id.clone();
+        assert!(!second_id.is_empty());
+
+        let moved = lots.withdraw(&currency, dec!(1.5), 0, "transfer");
+        assert_eq!(moved.len(), 2);
+        assert_eq!(moved[0].id, format!("{}/transfer", first.id));
+        assert_eq!(moved[0].parent_ids, vec![first.id.clone()]);
+        assert_eq!(moved[0].acquisition_id, first.acquisition_id);
+        assert_eq!(moved[1].amount, dec!(0.5));
+        assert_eq!(moved[1].parent_ids, vec![second_id]);
+
+        let mut wallet = LotStore::default();
+        wallet.deposit(&currency, moved, "wallet");
+        assert!(wallet
+            .lots(&currency)
+            .all(|currency_holding| currency_holding.location == "wallet"));
+        assert_eq!(lots.lots(&currency).next().unwrap().amount, dec!(0.5));
+    }
+}
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub mod add_to_currency_holdings;
+pub mod currency_lots;
+pub mod lot_store;
 pub mod process_trade;
 
 pub use {add_to_currency_holdings::*, process_trade::*};
@@ -14,7 +16,25 @@ pub struct CurrencyHolding {
     pub rate_in_fiat: Decimal,
     pub date: u64, // really u32 but bigger then max size
     pub location: String,
+    #[serde(rename = "ID", default)]
+    pub id: String,
+    #[serde(rename = "acquisitionID", default)]
+    pub acquisition_id: String,
+    #[serde(rename = "parentIDs", default)]
+    pub parent_ids: Vec<String>,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+impl CurrencyHolding {
+    // lots from before they had ids get one from where they sit in their currency's holdings
+    pub fn fill_missing_ids(&mut self, currency: &str, index: usize) {
+        if self.id.is_empty() {
+            self.id = format!("{}-{}-{}", currency, self.date, index);
+        }
+        if self.acquisition_id.is_empty() {
+            self.acquisition_id = self.id.clone();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Holdings(pub HashMap<String, Vec<CurrencyHolding>>);
@@ -1,6 +1,7 @@
-use crate::holding::Holdings;
-use crate::holding_selection::holding_selection;
+use crate::holding::lot_store::LotStore;
+use crate::holding::{CurrencyHolding, Holdings};
 use crate::method::Method;
+use crate::stablecoin::Stablecoins;
 use crate::trade::Trade;
 use crate::{MIN_HOLDING_SIZE, YEAR_IN_MILLISECONDS};
 use rust_decimal::prelude::{Decimal, Zero};
@@ -9,6 +10,24 @@ use rust_decimal::prelude::{Decimal, Zero};
 pub struct ProcessedTradeResult {
     pub holdings: Holdings,
     pub cost_basis_trades: Vec<Trade>,
+    pub deducted_holdings: Vec<CurrencyHolding>,
+    pub short_term_gain: Decimal,
+    pub long_term_gain: Decimal,
+    pub short_term_cost_basis: Decimal,
+    pub long_term_cost_basis: Decimal,
+    pub short_term_proceeds: Decimal,
+    pub long_term_proceeds: Decimal,
+}
+
+// what a trade did when the lots are kept in a store rather than returned
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessedTrade {
+    pub cost_basis_trades: Vec<Trade>,
+    pub deducted_holdings: Vec<CurrencyHolding>,
+    // lots of the fee currency when the fee was paid in neither currency traded
+    pub fee_holdings: Vec<CurrencyHolding>,
+    // none when the amount bought was too small to keep
+    pub added_holding: Option<CurrencyHolding>,
     pub short_term_gain: Decimal,
     pub long_term_gain: Decimal,
     pub short_term_cost_basis: Decimal,
@@ -18,12 +37,79 @@ pub struct ProcessedTradeResult {
 }
 
 impl Holdings {
-    pub fn process_trade(
+    pub fn process_trade_with_stablecoins(
         self: Holdings,
         trade: Trade,
+        stablecoins: &Stablecoins,
+        method: Method,
+    ) -> ProcessedTradeResult {
+        if stablecoins.is_unchanged(&trade) {
+            return ProcessedTradeResult {
+                holdings: self,
+                cost_basis_trades: vec![],
+                deducted_holdings: vec![],
+                short_term_gain: Zero::zero(),
+                long_term_gain: Zero::zero(),
+                short_term_cost_basis: Zero::zero(),
+                long_term_cost_basis: Zero::zero(),
+                short_term_proceeds: Zero::zero(),
+                long_term_proceeds: Zero::zero(),
+            };
+        }
+        let trade = stablecoins.trade(trade);
+        stablecoins
+            .holdings(self)
+            .process_trade(trade, stablecoins.fiat_currency.clone(), method)
+    }
+
+    pub fn process_trade(
+        mut self: Holdings,
+        trade: Trade,
         fiat_currency: String,
         method: Method,
     ) -> ProcessedTradeResult {
+        // only the sold currency's and fee currency's lots are needed, everything else stays where it is
+        let mut used_holdings = Holdings::default();
+        let mut currencies = vec![trade.sold_currency.clone()];
+        if trade.fee_in_other_currency(&fiat_currency) {
+            currencies.push(trade.transaction_fee_currency.clone());
+        }
+        for currency in currencies {
+            if let Some(currency_holdings) = self.0.remove(&currency) {
+                used_holdings.0.insert(currency, currency_holdings);
+            }
+        }
+
+        let mut lots = LotStore::from(used_holdings);
+        let result = lots.process_trade(&trade, &fiat_currency, method);
+        for (currency, currency_holdings) in Holdings::from(lots).0 {
+            self.0
+                .entry(currency)
+                .or_default()
+                .extend(currency_holdings);
+        }
+
+        ProcessedTradeResult {
+            holdings: self,
+            cost_basis_trades: result.cost_basis_trades,
+            deducted_holdings: result.deducted_holdings,
+            short_term_gain: result.short_term_gain,
+            long_term_gain: result.long_term_gain,
+            short_term_cost_basis: result.short_term_cost_basis,
+            long_term_cost_basis: result.long_term_cost_basis,
+            short_term_proceeds: result.short_term_proceeds,
+            long_term_proceeds: result.long_term_proceeds,
+        }
+    }
+}
+
+impl LotStore {
+    pub fn process_trade(
+        &mut self,
+        trade: &Trade,
+        fiat_currency: &str,
+        method: Method,
+    ) -> ProcessedTrade {
         let mut short_term_gain = Zero::zero();
         let mut short_term_proceeds = Zero::zero();
         let mut short_term_cost_basis = Zero::zero();
@@ -32,47 +118,50 @@ impl Holdings {
         let mut long_term_cost_basis = Zero::zero();
 
         let mut trades_with_cost_basis: Vec<Trade> = vec![];
-        let mut holdings = self;
 
-        let result = holding_selection(holdings, trade.clone(), fiat_currency.clone(), method);
-        holdings = result.new_holdings;
+        let deducted_holdings = self.deduct(trade, fiat_currency, method);
+        let fee_holdings = self.deduct_fee(trade, fiat_currency, method);
+        let other_fee_cost = fee_holdings
+            .iter()
+            .fold(Zero::zero(), |acc: Decimal, holding| {
+                acc + holding.amount * holding.rate_in_fiat
+            });
+        let mut added = false;
 
         if trade.sold_currency == fiat_currency {
-            holdings = holdings.add_to_currency_holdings(
+            // fees come out of what was bought the same as any other trade
+            let (amount, _) = trade.amount_bought(fiat_currency);
+            let fiat_rate = if other_fee_cost.is_zero() {
+                trade.fiat_rate()
+            } else {
+                trade.fiat_rate() + other_fee_cost / amount
+            };
+            self.add_to_currency_holdings(
                 trade.bought_currency.clone(),
-                trade.amount_sold / trade.rate,
-                trade.fiat_rate(),
+                amount,
+                fiat_rate,
                 trade.date,
-                Some(trade.exchange),
+                Some(trade.exchange.clone()),
+                trade.id.clone(),
             );
+            added = true;
         } else {
-            let mut fee_fiat_cost: Decimal = Zero::zero();
-            let mut amount_to_add = trade.amount_sold / trade.rate;
-
-            if !trade.transaction_fee.is_zero() {
-                if trade.transaction_fee_currency == trade.bought_currency {
-                    fee_fiat_cost += trade.transaction_fee * trade.rate * trade.fiat_rate();
-                    amount_to_add -= trade.transaction_fee;
-                } else if trade.transaction_fee_currency == trade.sold_currency {
-                    fee_fiat_cost += trade.transaction_fee * trade.fiat_rate();
-                    amount_to_add -= trade.transaction_fee / trade.rate;
-                } else if trade.transaction_fee_currency == fiat_currency {
-                    fee_fiat_cost += trade.transaction_fee;
-                    amount_to_add -= trade.transaction_fee / trade.fiat_rate();
-                }
-            }
+            let (amount_to_add, fee_fiat_cost) = trade.amount_bought(fiat_currency);
+            let fee_fiat_cost = fee_fiat_cost + other_fee_cost;
 
             if amount_to_add > MIN_HOLDING_SIZE {
-                holdings = holdings.add_to_currency_holdings(
+                self.add_to_currency_holdings(
                     trade.bought_currency.clone(),
                     amount_to_add,
                     trade.fiat_rate() * trade.rate,
                     trade.date,
                     Some(trade.exchange.clone()),
+                    trade.id.clone(),
                 );
+                added = true;
             }
 
-            for holding in result.deducted_holdings {
+            for holding in deducted_holdings.iter() {
                 let mut gain = (trade.fiat_rate() - holding.rate_in_fiat) * holding.amount;
 
                 if !fee_fiat_cost.is_zero() {
@@ -87,6 +176,8 @@ impl Holdings {
                     date_acquired: Some(holding.date),
                     cost_basis: Some(holding.rate_in_fiat * holding.amount),
                     long_term_trade: Some(false),
+                    lot_id: Some(holding.id.clone()),
+                    acquisition_id: Some(holding.acquisition_id.clone()),
                     ..trade.clone()
                 };
 
@@ -107,9 +198,15 @@ impl Holdings {
             }
         }
 
-        ProcessedTradeResult {
-            holdings,
+        ProcessedTrade {
             cost_basis_trades: trades_with_cost_basis,
+            deducted_holdings,
+            fee_holdings,
+            added_holding: if added {
+                self.lots(&trade.bought_currency).next_back().cloned()
+            } else {
+                None
+            },
             short_term_gain,
             long_term_gain,
             short_term_cost_basis,
@@ -339,4 +436,57 @@ mod tests {
             info.proceeds
         );
     }
+
+    #[test]
+    fn fee_in_another_currency_uses_its_lots() {
+        let mut holdings = mocks::mock_holdings(1, 1, None, None);
+        let currency = holdings.0.keys().collect::<Vec<&String>>()[0].clone();
+        let mut trades = mocks::mock_trades(1, mocks::now_u64(), holdings.clone(), false);
+        let mut fee_holding = holdings.0.get(&currency).unwrap()[0].clone();
+        fee_holding.amount = dec!(2);
+        fee_holding.rate_in_fiat = dec!(50);
+        fee_holding.id = "bnb".to_string();
+        holdings.0.insert("BNB".to_string(), vec![fee_holding]);
+
+        trades[0].amount_sold = holdings.0.get(&currency).unwrap()[0].amount;
+        trades[0].bought_currency = FIAT_CURRENCY.to_string();
+        let without_fee = holdings.clone().process_trade(
+            trades[0].clone(),
+            FIAT_CURRENCY.to_string(),
+            method::Method::FIFO,
+        );
+
+        trades[0].transaction_fee = dec!(0.5);
+        trades[0].transaction_fee_currency = "BNB".to_string();
+        let result = holdings.process_trade(
+            trades[0].clone(),
+            FIAT_CURRENCY.to_string(),
+            method::Method::FIFO,
+        );
+
+        assert_eq!(result.holdings.0.get("BNB").unwrap()[0].amount, dec!(1.5));
+        assert_eq!(
+            result.short_term_gain + result.long_term_gain,
+            without_fee.short_term_gain + without_fee.long_term_gain - dec!(25)
+        );
+    }
+
+    #[test]
+    fn fiat_buys_leave_out_the_fee() {
+        let holdings = mocks::mock_holdings(1, 1, None, None);
+        let mut trades = mocks::mock_trades(1, mocks::now_u64(), holdings.clone(), false);
+        trades[0].sold_currency = FIAT_CURRENCY.to_string();
+        trades[0].bought_currency = "BTC".to_string();
+        trades[0].amount_sold = dec!(1010);
+        trades[0].rate = dec!(1000);
+        trades[0].transaction_fee = dec!(10);
+        trades[0].transaction_fee_currency = FIAT_CURRENCY.to_string();
+
+        let result = holdings.process_trade(
+            trades[0].clone(),
+            FIAT_CURRENCY.to_string(),
+            method::Method::FIFO,
+        );
+        assert_eq!(result.holdings.0.get("BTC").unwrap()[0].amount, dec!(1));
+    }
 }
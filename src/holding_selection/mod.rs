@@ -1,9 +1,9 @@
+use crate::holding::lot_store::LotStore;
+use crate::stablecoin::Stablecoins;
 use crate::{holding, method, trade};
-use rust_decimal::prelude::{Decimal, Zero};
-use rust_decimal_macros::*;
 use std::clone::Clone;
 use wasm_bindgen::prelude::*;
-mod get_currency_holding;
+pub mod get_currency_holding;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -24,111 +24,70 @@ pub fn holding_selection_wasm(
     JsValue::from_serde(&holding_selection(holdings, trade, fiat_currency, method)).unwrap()
 }
 
+#[wasm_bindgen]
+pub fn holding_selection_with_stablecoins_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    stablecoins: &JsValue,
+    method: method::Method,
+) -> JsValue {
+    let holdings: holding::Holdings = holdings.into_serde().unwrap();
+    let trade: trade::Trade = trade.into_serde().unwrap();
+    let stablecoins: Stablecoins = stablecoins.into_serde().unwrap();
+    JsValue::from_serde(&holding_selection_with_stablecoins(
+        holdings,
+        trade,
+        &stablecoins,
+        method,
+    ))
+    .unwrap()
+}
+
+pub fn holding_selection_with_stablecoins(
+    holdings: holding::Holdings,
+    trade: trade::Trade,
+    stablecoins: &Stablecoins,
+    method: method::Method,
+) -> HoldingSelection {
+    if stablecoins.is_unchanged(&trade) {
+        return HoldingSelection {
+            deducted_holdings: vec![],
+            new_holdings: holdings,
+        };
+    }
+    let trade = stablecoins.trade(trade);
+    holding_selection(
+        stablecoins.holdings(holdings),
+        trade,
+        stablecoins.fiat_currency.clone(),
+        method,
+    )
+}
+
 pub fn holding_selection(
     mut holdings: holding::Holdings,
     trade: trade::Trade,
     fiat_currency: String,
     method: method::Method,
 ) -> HoldingSelection {
-    let mut currency_holding: Vec<holding::CurrencyHolding> = vec![];
-    let mut amount_used = trade.amount_sold;
-
-    while !amount_used.is_zero() {
-        let result = {
-            if let Some(current_currency_holding) = holdings.0.get_mut(&trade.sold_currency) {
-                let selected_currency_holding_index = get_currency_holding::get_currency_holding(
-                    current_currency_holding,
-                    method,
-                    trade.clone(),
-                );
-
-                if let Some(selected_currency_holding) =
-                    current_currency_holding.get_mut(selected_currency_holding_index)
-                {
-                    let result = check_currency_holding_amount(
-                        amount_used,
-                        selected_currency_holding.clone(),
-                    );
-                    currency_holding.push(result.deducted_currency_holding);
-
-                    if result.amount_remaining.is_zero() {
-                        selected_currency_holding.amount -= amount_used;
-                    } else {
-                        current_currency_holding.remove(selected_currency_holding_index);
-                    }
-
-                    amount_used = result.amount_remaining;
-
-                    if currency_holding.is_empty() {
-                        holdings.0.remove(&trade.sold_currency);
-                    }
-                    Ok(true)
-                } else {
-                    Err("Currency holdings is empty")
-                }
-            } else {
-                Err("Currency holding not found")
-            }
-        };
-
-        if result.is_err() {
-            if trade.sold_currency == fiat_currency {
-                currency_holding.push(holding::CurrencyHolding {
-                    amount: amount_used,
-                    date: trade.date,
-                    rate_in_fiat: dec!(1),
-                    location: trade.exchange.clone(),
-                });
-            } else {
-                currency_holding.push(holding::CurrencyHolding {
-                    amount: amount_used,
-                    date: trade.date,
-                    rate_in_fiat: Zero::zero(),
-                    location: trade.exchange.clone(),
-                });
-            }
-            amount_used = Zero::zero()
-        }
+    // only the sold currency's lots are needed, everything else stays where it is
+    let mut sold_holdings = holding::Holdings::default();
+    if let Some(currency_holdings) = holdings.0.remove(&trade.sold_currency) {
+        sold_holdings
+            .0
+            .insert(trade.sold_currency.clone(), currency_holdings);
     }
 
+    let mut lots = LotStore::from(sold_holdings);
+    let deducted_holdings = lots.deduct(&trade, &fiat_currency, method);
+    holdings.0.extend(holding::Holdings::from(lots).0);
+
     HoldingSelection {
-        deducted_holdings: currency_holding,
+        deducted_holdings,
         new_holdings: holdings,
     }
 }
 
-struct CheckCurrencyHoldingAmount {
-    pub amount_remaining: Decimal,
-    pub deducted_currency_holding: holding::CurrencyHolding,
-}
-
-fn check_currency_holding_amount(
-    amount_used: Decimal,
-    holding_to_check: holding::CurrencyHolding,
-) -> CheckCurrencyHoldingAmount {
-    if holding_to_check.amount > amount_used {
-        CheckCurrencyHoldingAmount {
-            amount_remaining: Zero::zero(),
-            deducted_currency_holding: holding::CurrencyHolding {
-                amount: amount_used,
-                rate_in_fiat: holding_to_check.rate_in_fiat,
-                date: holding_to_check.date,
-                location: holding_to_check.location,
-            },
-        }
-    } else {
-        CheckCurrencyHoldingAmount {
-            amount_remaining: amount_used - holding_to_check.amount,
-            deducted_currency_holding: holding::CurrencyHolding {
-                amount: holding_to_check.amount,
-                rate_in_fiat: holding_to_check.rate_in_fiat,
-                date: holding_to_check.date,
-                location: holding_to_check.location,
-            },
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use crate::mocks;
@@ -223,4 +182,54 @@ mod tests {
         let t = holdings_total - calculate_total_amount(currency_holding.clone());
         assert_eq!(t, trades[0].amount_sold);
     }
+
+    #[test]
+    fn split_holding_keeps_provenance() {
+        let holdings = mocks::mock_holdings(1, 3, None, None);
+        let currency = holdings.0.keys().collect::<Vec<&String>>()[0];
+        let original_holding = holdings.0.get(currency).unwrap()[0].clone();
+        let mut trades = mocks::mock_trades(1, 123456768, holdings.clone(), false);
+        trades[0].amount_sold = original_holding.amount / dec!(2);
+        trades[0].bought_currency = FIAT_CURRENCY.to_owned().clone();
+
+        let result = holding_selection::holding_selection(
+            holdings.clone(),
+            trades[0].clone(),
+            FIAT_CURRENCY.to_owned().clone(),
+            method::Method::FIFO,
+        );
+
+        let deducted_holding = &result.deducted_holdings[0];
+        assert_eq!(
+            deducted_holding.parent_ids,
+            vec![original_holding.id.clone()]
+        );
+        assert_eq!(
+            deducted_holding.acquisition_id,
+            original_holding.acquisition_id
+        );
+        assert_ne!(deducted_holding.id, original_holding.id);
+
+        let remaining_holding = &result.new_holdings.0.get(currency).unwrap()[0];
+        assert_eq!(remaining_holding.id, original_holding.id);
+    }
+
+    #[test]
+    fn fully_used_holding_keeps_id() {
+        let holdings = mocks::mock_holdings(1, 3, None, None);
+        let currency = holdings.0.keys().collect::<Vec<&String>>()[0];
+        let original_holding = holdings.0.get(currency).unwrap()[0].clone();
+        let mut trades = mocks::mock_trades(1, 123456768, holdings.clone(), false);
+        trades[0].amount_sold = original_holding.amount;
+        trades[0].bought_currency = FIAT_CURRENCY.to_owned().clone();
+
+        let result = holding_selection::holding_selection(
+            holdings.clone(),
+            trades[0].clone(),
+            FIAT_CURRENCY.to_owned().clone(),
+            method::Method::FIFO,
+        );
+
+        assert_eq!(result.deducted_holdings, vec![original_holding]);
+    }
 }
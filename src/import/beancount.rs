@@ -0,0 +1,602 @@
+use crate::holding::CurrencyHolding;
+use crate::import::{parse_date, parse_decimal, ImportResult};
+use crate::income::Income;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+// directives which only describe the ledger itself and hold nothing to import
+const STRUCTURAL_DIRECTIVES: [&str; 5] = ["open", "close", "commodity", "option", "account"];
+
+struct Posting {
+    account: String,
+    amount: Option<Decimal>,
+    currency: String,
+    cost: Option<Decimal>,
+    cost_date: Option<u64>,
+    label: Option<String>,
+    price: Option<Decimal>,
+}
+
+impl Posting {
+    fn is_asset(&self) -> bool {
+        self.account.starts_with("Assets") || self.account.starts_with("Liabilities")
+    }
+
+    // exported journals book whatever was sold without being held against equity
+    fn is_holding(&self) -> bool {
+        self.is_asset() || self.account.starts_with("Equity:Unmatched")
+    }
+
+    fn weight(&self, fiat_currency: &str) -> Option<Decimal> {
+        let amount = self.amount?;
+        if self.currency == fiat_currency {
+            Some(amount)
+        } else {
+            self.cost.or(self.price).map(|rate| amount * rate)
+        }
+    }
+}
+
+struct Transaction {
+    row: u64,
+    date: u64,
+    payee: String,
+    id: Option<String>,
+    postings: Vec<Posting>,
+}
+
+#[wasm_bindgen]
+pub fn import_beancount_wasm(data: String, fiat_currency: String) -> JsValue {
+    JsValue::from_serde(&import_beancount(&data, &fiat_currency)).unwrap()
+}
+
+// reads beancount journals along with ledger-cli journals using the same lot annotations
+pub fn import_beancount(data: &str, fiat_currency: &str) -> ImportResult {
+    let mut result = ImportResult::default();
+    let mut transaction: Option<Transaction> = None;
+
+    for (index, line) in data.lines().enumerate() {
+        let row = index as u64 + 1;
+        let indented = line.starts_with(char::is_whitespace);
+        // ledger keeps transaction metadata inside comments
+        let line = match line.trim_start().strip_prefix(';') {
+            Some(comment) if indented && metadata(comment.trim()).is_some() => comment,
+            _ => strip_comment(line),
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if indented {
+            let current = match transaction.as_mut() {
+                Some(current) => current,
+                None => continue,
+            };
+
+            let line = line.trim();
+            if let Some((key, value)) = metadata(line) {
+                if key == "id" {
+                    current.id = Some(value);
+                }
+            } else {
+                match parse_posting(line, fiat_currency) {
+                    Ok(posting) => current.postings.push(posting),
+                    Err(message) => result.error(row, message),
+                }
+            }
+            continue;
+        }
+
+        if let Some(finished) = transaction.take() {
+            add_transaction(finished, fiat_currency, &mut result);
+        }
+
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap_or_default();
+        let directive = words.next().unwrap_or_default();
+
+        let date = match parse_journal_date(first) {
+            Ok(date) => date,
+            Err(_) => {
+                if !STRUCTURAL_DIRECTIVES.contains(&first) {
+                    result.error(row, format!("unsupported directive \"{}\"", first));
+                }
+                continue;
+            }
+        };
+
+        if directive == "*" || directive == "!" || directive == "txn" {
+            let payee = line
+                .splitn(3, char::is_whitespace)
+                .nth(2)
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            transaction = Some(Transaction {
+                row,
+                date,
+                payee,
+                id: None,
+                postings: vec![],
+            });
+        } else if !STRUCTURAL_DIRECTIVES.contains(&directive) {
+            result.error(row, format!("unsupported directive \"{}\"", directive));
+        }
+    }
+
+    if let Some(finished) = transaction.take() {
+        add_transaction(finished, fiat_currency, &mut result);
+    }
+
+    for currency_holdings in result.holdings.0.values_mut() {
+        currency_holdings.sort_by_key(|currency_holding| currency_holding.date);
+    }
+
+    result
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches('"').to_string()
+}
+
+fn parse_journal_date(value: &str) -> Result<u64, String> {
+    parse_date(&value.replace('/', "-"), "%Y-%m-%d", None)
+}
+
+fn metadata(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once(':')?;
+    if key
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        && !key.is_empty()
+    {
+        Some((key.to_string(), unquote(value)))
+    } else {
+        None
+    }
+}
+
+// "300 USD", "USD 300" and "$300" all read as an amount
+fn parse_amount(value: &str, fiat_currency: &str) -> Result<(Decimal, String), String> {
+    let value = value.trim();
+    if let Some(dollars) = value.strip_prefix('$') {
+        return Ok((parse_decimal(dollars)?, fiat_currency.to_string()));
+    }
+
+    let words: Vec<&str> = value.split_whitespace().collect();
+    match words[..] {
+        [first, second] => match parse_decimal(first) {
+            Ok(amount) => Ok((amount, unquote(second))),
+            Err(_) => Ok((parse_decimal(second)?, unquote(first))),
+        },
+        _ => Err(format!("unable to read amount \"{}\"", value)),
+    }
+}
+
+fn parse_posting(line: &str, fiat_currency: &str) -> Result<Posting, String> {
+    // ledger accounts may hold single spaces so two spaces or a tab end the account
+    let split = line
+        .find("  ")
+        .or_else(|| line.find('\t'))
+        .or_else(|| line.find(' '));
+    let (account, rest) = match split {
+        Some(index) => (line[..index].trim(), line[index..].trim()),
+        None => (line.trim(), ""),
+    };
+
+    let mut posting = Posting {
+        account: account.to_string(),
+        amount: None,
+        currency: fiat_currency.to_string(),
+        cost: None,
+        cost_date: None,
+        label: None,
+        price: None,
+    };
+    if rest.is_empty() {
+        return Ok(posting);
+    }
+
+    let (rest, price) = match rest.split_once('@') {
+        Some((rest, price)) => (rest.trim(), Some(price)),
+        None => (rest, None),
+    };
+    let amount_end = rest.find(['{', '[', '(']).unwrap_or(rest.len());
+    let (amount, currency) = parse_amount(&rest[..amount_end], fiat_currency)?;
+    posting.amount = Some(amount);
+    posting.currency = currency;
+    let annotations = &rest[amount_end..];
+
+    if let (Some(start), Some(end)) = (annotations.find('{'), annotations.rfind('}')) {
+        let total = annotations[start..].starts_with("{{");
+        let cost = annotations[start..=end].trim_matches(|c| c == '{' || c == '}');
+        for item in cost
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            if item.starts_with('"') {
+                posting.label = Some(unquote(item));
+            } else if let Ok(date) = parse_journal_date(item) {
+                posting.cost_date = Some(date);
+            } else {
+                let (cost, _) = parse_amount(item, fiat_currency)?;
+                posting.cost = Some(if total && !amount.is_zero() {
+                    cost / amount.abs()
+                } else {
+                    cost
+                });
+            }
+        }
+    }
+    if let (Some(start), Some(end)) = (annotations.find('['), annotations.find(']')) {
+        posting.cost_date = Some(parse_journal_date(&annotations[start + 1..end])?);
+    }
+    if let (Some(start), Some(end)) = (annotations.find('('), annotations.rfind(')')) {
+        posting.label = Some(annotations[start + 1..end].trim().to_string());
+    }
+
+    if let Some(price) = price {
+        let total = price.starts_with('@');
+        let (price, _) = parse_amount(price.trim_start_matches('@'), fiat_currency)?;
+        posting.price = Some(if total && !amount.is_zero() {
+            price / amount.abs()
+        } else {
+            price
+        });
+    }
+
+    Ok(posting)
+}
+
+fn add_transaction(mut transaction: Transaction, fiat_currency: &str, result: &mut ImportResult) {
+    let row = transaction.row;
+    if let Err(message) = read_transaction(&mut transaction, fiat_currency, result) {
+        result.error(row, message);
+    }
+}
+
+fn read_transaction(
+    transaction: &mut Transaction,
+    fiat_currency: &str,
+    result: &mut ImportResult,
+) -> Result<(), String> {
+    // a single posting can leave its amount out to take whatever balances the rest
+    let missing: Vec<usize> = (0..transaction.postings.len())
+        .filter(|index| transaction.postings[*index].amount.is_none())
+        .collect();
+    if let [index] = missing[..] {
+        let weights: Option<Decimal> = transaction
+            .postings
+            .iter()
+            .filter(|posting| posting.amount.is_some())
+            .map(|posting| posting.weight(fiat_currency))
+            .sum();
+        let weights = weights.ok_or_else(|| "unable to balance transaction".to_string())?;
+        transaction.postings[index].amount = Some(-weights);
+    } else if missing.len() > 1 {
+        return Err("more than one posting is missing an amount".to_string());
+    }
+
+    let postings = &transaction.postings;
+    let crypto: Vec<&Posting> = postings
+        .iter()
+        .filter(|posting| posting.is_holding() && posting.currency != fiat_currency)
+        .collect();
+    if crypto.is_empty() {
+        return Ok(());
+    }
+
+    let id = transaction
+        .id
+        .clone()
+        .unwrap_or_else(|| format!("{}-{}", transaction.date, transaction.row));
+
+    let mut sold: BTreeMap<String, Decimal> = BTreeMap::new();
+    let mut bought: BTreeMap<String, (Decimal, Option<Decimal>)> = BTreeMap::new();
+    let mut sold_price: Option<Decimal> = None;
+    for posting in postings.iter().filter(|posting| posting.is_holding()) {
+        let amount = posting.amount.unwrap_or_default();
+        if amount < Zero::zero() {
+            *sold.entry(posting.currency.clone()).or_default() -= amount;
+            sold_price = sold_price.or(posting.price);
+        } else if amount > Zero::zero() {
+            let entry = bought.entry(posting.currency.clone()).or_default();
+            entry.0 += amount;
+            entry.1 = entry.1.or(posting.cost).or(posting.price);
+        }
+    }
+
+    let has_account = |prefix: &str| {
+        postings
+            .iter()
+            .any(|posting| posting.account.starts_with(prefix))
+    };
+
+    if sold.is_empty() && has_account("Equity") {
+        for posting in crypto.iter() {
+            let id = posting
+                .label
+                .clone()
+                .unwrap_or_else(|| format!("{}-{}", id, posting.currency));
+            result
+                .holdings
+                .0
+                .entry(posting.currency.clone())
+                .or_default()
+                .push(CurrencyHolding {
+                    amount: posting.amount.unwrap_or_default(),
+                    rate_in_fiat: posting.cost.or(posting.price).unwrap_or_default(),
+                    date: posting.cost_date.unwrap_or(transaction.date),
+                    location: transaction.payee.trim_matches('"').to_string(),
+                    acquisition_id: id.clone(),
+                    id,
+                    parent_ids: vec![],
+                });
+        }
+        return Ok(());
+    }
+
+    if sold.is_empty() && has_account("Income") {
+        let income_value: Decimal = postings
+            .iter()
+            .filter(|posting| {
+                posting.account.starts_with("Income") && posting.currency == fiat_currency
+            })
+            .map(|posting| -posting.amount.unwrap_or_default())
+            .sum();
+        for (currency, (amount, rate)) in bought {
+            let fiat_rate = rate.or_else(|| {
+                if crypto.len() == 1 && !amount.is_zero() && !income_value.is_zero() {
+                    Some(income_value / amount)
+                } else {
+                    None
+                }
+            });
+            result.incomes.push(Income {
+                amount,
+                id: if result.incomes.iter().any(|income| income.id == id) {
+                    format!("{}-{}", id, currency)
+                } else {
+                    id.clone()
+                },
+                currency,
+                transaction_id: None,
+                fee: None,
+                date: transaction.date,
+                fiat_rate,
+            });
+        }
+        return Ok(());
+    }
+
+    let (sold_currency, sold_amount, bought_currency, bought_amount, bought_rate) =
+        match (sold.len(), bought.len()) {
+            (1, 1) => {
+                let (sold_currency, sold_amount) = sold.into_iter().next().unwrap();
+                let (bought_currency, (bought_amount, bought_rate)) =
+                    bought.into_iter().next().unwrap();
+                (
+                    sold_currency,
+                    sold_amount,
+                    bought_currency,
+                    bought_amount,
+                    bought_rate,
+                )
+            }
+            _ => {
+                return Err(
+                    "unable to read transaction as a trade, income or opening balance".to_string(),
+                )
+            }
+        };
+
+    let fee = postings
+        .iter()
+        .find(|posting| posting.account.starts_with("Expenses"))
+        .map(|posting| {
+            (
+                posting.amount.unwrap_or_default().abs(),
+                posting.currency.clone(),
+            )
+        });
+    let (transaction_fee, transaction_fee_currency) =
+        fee.unwrap_or_else(|| (Zero::zero(), fiat_currency.to_string()));
+
+    // fees in either traded currency were taken out on top of the amounts that were traded
+    let sold_traded = if transaction_fee_currency == sold_currency {
+        sold_amount - transaction_fee
+    } else {
+        sold_amount
+    };
+    let bought_gross = if transaction_fee_currency == bought_currency {
+        bought_amount + transaction_fee
+    } else {
+        bought_amount
+    };
+    let rate = sold_traded / bought_gross;
+
+    let fiat_rate = if sold_currency == fiat_currency {
+        Some(bought_rate.unwrap_or(rate))
+    } else if bought_currency == fiat_currency {
+        Some(bought_gross / sold_traded)
+    } else {
+        sold_price
+            .or_else(|| bought_rate.map(|bought_rate| bought_rate * bought_gross / sold_traded))
+    };
+
+    result.trades.push(Trade {
+        bought_currency,
+        sold_currency,
+        amount_sold: sold_amount,
+        rate,
+        date: transaction.date,
+        exchange_id: id.clone(),
+        exchange: transaction.payee.trim_matches('"').to_string(),
+        id,
+        transaction_fee,
+        transaction_fee_currency,
+        fiat_rate,
+        short_term: None,
+        long_term: None,
+        date_acquired: None,
+        cost_basis: None,
+        long_term_trade: None,
+        lot_id: None,
+        acquisition_id: None,
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import_beancount;
+    use crate::export::journal::{journal, JournalFormat};
+    use crate::holding::Holdings;
+    use crate::method::Method;
+    use crate::mocks;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::Decimal;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+
+    fn trade(id: &str, sold: &str, bought: &str, amount_sold: Decimal, rate: Decimal) -> Trade {
+        Trade {
+            exchange: "Exchange".to_string(),
+            transaction_fee_currency: FIAT_CURRENCY.to_string(),
+            ..mocks::trade(id, sold, bought, amount_sold, rate, 1577836800000)
+        }
+    }
+
+    #[test]
+    fn reads_lots_trades_and_incomes() {
+        let data = r#"option "operating_currency" "USD"
+2020-01-01 open Assets:Crypto
+2020-01-01 commodity BTC
+
+2020-01-01 * "Opening Balance" "Lots bought elsewhere"
+  Assets:Crypto  1 BTC {5000 USD, 2019-06-01, "old-lot"}
+  Equity:Opening-Balances
+
+2021-01-01 * "Exchange" "Bought ETH" ; a comment
+  id: "eth-buy"
+  Assets:Crypto  -0.1 BTC {5000 USD, 2019-06-01, "old-lot"} @ 30000 USD
+  Assets:Crypto  10 ETH {300 USD}
+  Income:Crypto:LongTermGains  -2500 USD
+
+2021-02-01 * "Staking" "Rewards"
+  Assets:Crypto  0.5 ETH {1000 USD}
+  Income:Crypto:Income  -500 USD
+
+2021-03-01 price BTC 50000 USD
+2021-03-02 balance Assets:Crypto 0.9 BTC
+"#;
+        let result = import_beancount(data, FIAT_CURRENCY);
+
+        let lots = result.holdings.0.get("BTC").unwrap();
+        assert_eq!(lots[0].id, "old-lot");
+        assert_eq!(lots[0].rate_in_fiat, dec!(5000));
+        assert_eq!(lots[0].date, 1559347200000);
+
+        let trade = &result.trades[0];
+        assert_eq!(trade.id, "eth-buy");
+        assert_eq!(trade.sold_currency, "BTC");
+        assert_eq!(trade.bought_currency, "ETH");
+        assert_eq!(trade.amount_sold, dec!(0.1));
+        assert_eq!(trade.rate, dec!(0.01));
+        assert_eq!(trade.fiat_rate, Some(dec!(30000)));
+
+        assert_eq!(result.incomes[0].amount, dec!(0.5));
+        assert_eq!(result.incomes[0].fiat_rate, Some(dec!(1000)));
+
+        let rows: Vec<u64> = result.errors.iter().map(|error| error.row).collect();
+        assert_eq!(rows, vec![19, 20]);
+    }
+
+    #[test]
+    fn reads_exported_journals() {
+        for format in [JournalFormat::Beancount, JournalFormat::Ledger].iter() {
+            let mut holdings = Holdings::default();
+            holdings.0.insert(
+                "BTC".to_string(),
+                vec![crate::holding::CurrencyHolding {
+                    amount: dec!(1),
+                    rate_in_fiat: dec!(100),
+                    date: 1577836800000,
+                    location: "".to_string(),
+                    id: "lot".to_string(),
+                    acquisition_id: "lot".to_string(),
+                    parent_ids: vec![],
+                }],
+            );
+            let data = journal(
+                holdings,
+                vec![],
+                vec![],
+                FIAT_CURRENCY.to_string(),
+                Method::FIFO,
+                *format,
+            )
+            .unwrap();
+
+            let result = import_beancount(&data, FIAT_CURRENCY);
+            assert!(result.errors.is_empty());
+            assert_eq!(result.holdings.0.get("BTC").unwrap()[0].id, "lot");
+        }
+    }
+
+    #[test]
+    fn exported_trades_read_back_the_same() {
+        // the fee is paid on top of what was bought, then more is sold than was ever held
+        let buy = Trade {
+            transaction_fee: dec!(10),
+            fiat_rate: Some(dec!(1000)),
+            ..trade("buy", FIAT_CURRENCY, "BTC", dec!(1010), dec!(1000))
+        };
+        let sell = Trade {
+            date: 1577923200000,
+            fiat_rate: Some(dec!(2000)),
+            ..trade("sell", "BTC", "ETH", dec!(2), dec!(0.05))
+        };
+
+        for format in [JournalFormat::Beancount, JournalFormat::Ledger].iter() {
+            let data = journal(
+                Holdings::default(),
+                vec![buy.clone(), sell.clone()],
+                vec![],
+                FIAT_CURRENCY.to_string(),
+                Method::FIFO,
+                *format,
+            )
+            .unwrap();
+
+            let result = import_beancount(&data, FIAT_CURRENCY);
+            assert!(result.errors.is_empty());
+            assert_eq!(result.trades.len(), 2);
+            for (imported, exported) in result.trades.iter().zip([&buy, &sell].iter()) {
+                assert_eq!(imported.id, exported.id);
+                assert_eq!(imported.sold_currency, exported.sold_currency);
+                assert_eq!(imported.amount_sold, exported.amount_sold);
+                assert_eq!(imported.rate, exported.rate);
+                assert_eq!(imported.fiat_rate, exported.fiat_rate);
+                assert_eq!(imported.transaction_fee, exported.transaction_fee);
+            }
+        }
+    }
+}
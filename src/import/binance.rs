@@ -0,0 +1,538 @@
+use crate::import::{parse_date, parse_decimal, read_records, Fill, ImportResult, Side};
+use crate::income::Income;
+use crate::trade::Trade;
+use crate::transfer::{Transfer, TransferDirection};
+use rust_decimal::prelude::{Decimal, Zero};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+static EXCHANGE: &str = "Binance";
+static DUST_OPERATION: &str = "Small assets exchange BNB";
+
+// quote currencies used to split pairs such as "BTCUSDT", longer codes first
+const QUOTE_CURRENCIES: [&str; 15] = [
+    "FDUSD", "USDT", "BUSD", "USDC", "TUSD", "BIDR", "USD", "DAI", "BTC", "ETH", "BNB", "EUR",
+    "GBP", "TRY", "AUD",
+];
+
+const TRADE_OPERATIONS: [&str; 11] = [
+    "Buy",
+    "Sell",
+    "Fee",
+    "Transaction Related",
+    "Transaction Buy",
+    "Transaction Spend",
+    "Transaction Sold",
+    "Transaction Revenue",
+    "Transaction Fee",
+    "Binance Convert",
+    "Large OTC Trading",
+];
+
+const INCOME_OPERATIONS: [&str; 9] = [
+    "Simple Earn Flexible Interest",
+    "Simple Earn Locked Rewards",
+    "Launchpool Interest",
+    "Launchpool Airdrop",
+    "Distribution",
+    "Airdrop Assets",
+    "Savings Interest",
+    "POS savings interest",
+    "Staking Rewards",
+];
+
+// legs of one trade share the second they happened in, the account and the market when the statement has one
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct GroupKey {
+    date: u64,
+    dust: bool,
+    account: String,
+    market: String,
+}
+
+struct Leg {
+    row: u64,
+    coin: String,
+    change: Decimal,
+    fee: bool,
+}
+
+// binance has written dates as both "2021-01-01 00:00:00" and "21-01-01 00:00:00"
+fn parse_binance_date(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    if value.find('-') == Some(2) {
+        parse_date(value, "%y-%m-%d %H:%M:%S", None)
+    } else {
+        parse_date(value, "%Y-%m-%d %H:%M:%S", None)
+    }
+}
+
+// trade history writes the currency after each amount such as "0.01BTC"
+fn parse_amount(value: &str) -> Result<(Decimal, String), String> {
+    let value = value.trim();
+    let index = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (amount, currency) = value.split_at(index);
+    Ok((parse_decimal(amount)?.abs(), currency.trim().to_uppercase()))
+}
+
+fn split_pair(pair: &str) -> Option<(String, String)> {
+    let pair = pair.trim().to_uppercase().replace(&['/', '-', '_'][..], "");
+    QUOTE_CURRENCIES
+        .iter()
+        .find(|quote| pair.len() > quote.len() && pair.ends_with(*quote))
+        .map(|quote| {
+            (
+                pair[..pair.len() - quote.len()].to_string(),
+                quote.to_string(),
+            )
+        })
+}
+
+#[wasm_bindgen]
+pub fn import_binance_trades_wasm(data: String) -> JsValue {
+    JsValue::from_serde(&import_binance_trades(&data)).unwrap()
+}
+
+pub fn import_binance_trades(data: &str) -> ImportResult {
+    let mut result = ImportResult::default();
+
+    read_records(data, b',', &mut result, |columns, _, result| {
+        let date = parse_binance_date(columns.get_any(&["Date(UTC)", "Date"])?)?;
+        let pair = columns.get_any(&["Pair", "Market"])?;
+        let side = match columns
+            .get_any(&["Side", "Type"])?
+            .trim()
+            .to_uppercase()
+            .as_str()
+        {
+            "BUY" => Side::Buy,
+            "SELL" => Side::Sell,
+            side => return Err(format!("unknown side \"{}\"", side)),
+        };
+
+        let (amount, base, total, quote, fee, fee_currency) = if columns.get("Executed").is_ok() {
+            let (amount, base) = parse_amount(columns.get("Executed")?)?;
+            let (total, quote) = parse_amount(columns.get("Amount")?)?;
+            let (fee, fee_currency) = parse_amount(columns.get("Fee")?)?;
+            (amount, base, total, quote, fee, fee_currency)
+        } else {
+            let (base, quote) =
+                split_pair(pair).ok_or_else(|| format!("unable to split pair \"{}\"", pair))?;
+            (
+                parse_decimal(columns.get("Amount")?)?,
+                base,
+                parse_decimal(columns.get("Total")?)?,
+                quote,
+                parse_decimal(columns.get("Fee")?)?,
+                columns.get("Fee Coin")?.trim().to_uppercase(),
+            )
+        };
+        if amount.is_zero() || total.is_zero() {
+            return Err("amount is zero".to_string());
+        }
+        // the total is only the price, a fee in the quote currency was paid on top of it
+        let total = if side == Side::Buy && fee_currency == quote {
+            total + fee
+        } else {
+            total
+        };
+
+        result.trades.push(Trade::from(Fill {
+            id: format!(
+                "{}-{}-{}-{:?}-{}-{}",
+                EXCHANGE,
+                date,
+                pair.trim(),
+                side,
+                amount,
+                total
+            ),
+            exchange: EXCHANGE.to_string(),
+            exchange_id: "".to_string(),
+            date,
+            side,
+            base,
+            quote,
+            amount,
+            total,
+            fee,
+            fee_currency,
+        }));
+
+        Ok(())
+    });
+
+    number_collisions(&mut result);
+    result
+}
+
+#[wasm_bindgen]
+pub fn import_binance_transactions_wasm(data: String) -> JsValue {
+    JsValue::from_serde(&import_binance_transactions(&data)).unwrap()
+}
+
+// the transaction history statement lists every balance change, trades are one row per leg
+pub fn import_binance_transactions(data: &str) -> ImportResult {
+    let mut result = ImportResult::default();
+    let mut groups: Vec<GroupKey> = vec![];
+    let mut legs: HashMap<GroupKey, Vec<Leg>> = HashMap::new();
+
+    read_records(data, b',', &mut result, |columns, row, result| {
+        let date = parse_binance_date(columns.get("UTC_Time")?)?;
+        let operation = columns.get("Operation")?.trim();
+        let coin = columns.get("Coin")?.trim().to_uppercase();
+        let change = parse_decimal(columns.get("Change")?)?;
+        let id = format!("{}-{}-{}-{}-{}", EXCHANGE, date, operation, coin, change);
+
+        if operation == DUST_OPERATION || TRADE_OPERATIONS.contains(&operation) {
+            let key = GroupKey {
+                date,
+                dust: operation == DUST_OPERATION,
+                account: columns
+                    .get("Account")
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                market: columns
+                    .get_any(&["Pair", "Market"])
+                    .unwrap_or_default()
+                    .trim()
+                    .to_uppercase(),
+            };
+            if !legs.contains_key(&key) {
+                groups.push(key.clone());
+            }
+            legs.entry(key).or_default().push(Leg {
+                row,
+                coin,
+                change,
+                fee: operation.ends_with("Fee"),
+            });
+        } else if INCOME_OPERATIONS
+            .iter()
+            .any(|income| operation.starts_with(income))
+        {
+            if change > Zero::zero() {
+                result.incomes.push(Income {
+                    amount: change,
+                    currency: coin,
+                    transaction_id: None,
+                    id,
+                    fee: None,
+                    date,
+                    fiat_rate: None,
+                });
+            }
+        } else if operation == "Deposit" || operation == "Withdraw" {
+            result.transfers.push(Transfer {
+                id,
+                currency: coin,
+                amount: change.abs(),
+                date,
+                direction: if operation == "Deposit" {
+                    TransferDirection::Deposit
+                } else {
+                    TransferDirection::Withdrawal
+                },
+                exchange: EXCHANGE.to_string(),
+                fee: None,
+                transaction_id: None,
+            });
+        } else if !["Subscription", "Redemption", "Transfer", "purchase"]
+            .iter()
+            .any(|internal| operation.contains(internal))
+        {
+            return Err(format!("unknown operation \"{}\"", operation));
+        }
+
+        Ok(())
+    });
+
+    for key in groups {
+        let group_legs = legs.remove(&key).unwrap_or_default();
+        let trades = if key.dust {
+            dust_trades(key.date, &group_legs)
+        } else {
+            trade_from_legs(key.date, &group_legs).map(|trade| vec![trade])
+        };
+
+        match trades {
+            Ok(trades) => result.trades.extend(trades),
+            Err(message) => result.error(group_legs[0].row, message),
+        }
+    }
+
+    number_collisions(&mut result);
+    result
+}
+
+// ids are built from what binance exports so they stay the same when a file is imported again,
+// rows which are the same in every column are numbered in the order they came in
+fn number_collisions(result: &mut ImportResult) {
+    let mut seen: HashMap<String, u64> = HashMap::new();
+    let mut number = |id: &mut String| {
+        let count = seen.entry(id.clone()).or_insert(0);
+        *count += 1;
+        if *count > 1 {
+            *id = format!("{}-{}", id, count);
+        }
+    };
+    result
+        .trades
+        .iter_mut()
+        .for_each(|trade| number(&mut trade.id));
+    result
+        .incomes
+        .iter_mut()
+        .for_each(|income| number(&mut income.id));
+    result
+        .transfers
+        .iter_mut()
+        .for_each(|transfer| number(&mut transfer.id));
+}
+
+// sums the legs of one currency, there is a leg for each fill of an order
+fn total_by_coin(legs: &[&Leg]) -> Result<Option<(String, Decimal)>, String> {
+    let mut coins: Vec<String> = legs.iter().map(|leg| leg.coin.clone()).collect();
+    coins.dedup();
+    match coins.len() {
+        0 => Ok(None),
+        1 => Ok(Some((
+            coins.remove(0),
+            legs.iter()
+                .fold(Zero::zero(), |acc: Decimal, leg| acc + leg.change.abs()),
+        ))),
+        _ => Err(format!("unable to match legs in {}", coins.join(", "))),
+    }
+}
+
+fn trade_from_legs(date: u64, legs: &[Leg]) -> Result<Trade, String> {
+    let sold: Vec<&Leg> = legs
+        .iter()
+        .filter(|leg| !leg.fee && leg.change < Zero::zero())
+        .collect();
+    let bought: Vec<&Leg> = legs
+        .iter()
+        .filter(|leg| !leg.fee && leg.change > Zero::zero())
+        .collect();
+    let fees: Vec<&Leg> = legs.iter().filter(|leg| leg.fee).collect();
+
+    let (sold_currency, sold) =
+        total_by_coin(&sold)?.ok_or_else(|| "trade has nothing sold".to_string())?;
+    let (bought_currency, bought) =
+        total_by_coin(&bought)?.ok_or_else(|| "trade has nothing bought".to_string())?;
+    let (transaction_fee_currency, transaction_fee) =
+        total_by_coin(&fees)?.unwrap_or_else(|| (bought_currency.clone(), Zero::zero()));
+
+    // a fee in the sold currency leaves on top of the amount traded
+    let amount_sold = if transaction_fee_currency == sold_currency {
+        sold + transaction_fee
+    } else {
+        sold
+    };
+
+    Ok(Trade {
+        id: format!(
+            "{}-{}-{}-{}-{}-{}",
+            EXCHANGE, date, sold_currency, sold, bought_currency, bought
+        ),
+        bought_currency,
+        sold_currency,
+        amount_sold,
+        rate: sold / bought,
+        date,
+        exchange_id: "".to_string(),
+        exchange: EXCHANGE.to_string(),
+        transaction_fee,
+        transaction_fee_currency,
+        fiat_rate: None,
+        short_term: None,
+        long_term: None,
+        date_acquired: None,
+        cost_basis: None,
+        long_term_trade: None,
+        lot_id: None,
+        acquisition_id: None,
+    })
+}
+
+// each small balance converted to bnb is followed by the bnb it turned into
+fn dust_trades(date: u64, legs: &[Leg]) -> Result<Vec<Trade>, String> {
+    let sold: Vec<&Leg> = legs
+        .iter()
+        .filter(|leg| leg.change < Zero::zero())
+        .collect();
+    let bought: Vec<&Leg> = legs
+        .iter()
+        .filter(|leg| leg.change > Zero::zero())
+        .collect();
+    if sold.len() != bought.len() {
+        return Err(format!(
+            "{} small assets were exchanged for {} amounts of BNB",
+            sold.len(),
+            bought.len()
+        ));
+    }
+
+    sold.iter()
+        .zip(bought.iter())
+        .map(|(sold, bought)| {
+            trade_from_legs(
+                date,
+                &[
+                    Leg {
+                        row: sold.row,
+                        coin: sold.coin.clone(),
+                        change: sold.change,
+                        fee: false,
+                    },
+                    Leg {
+                        row: bought.row,
+                        coin: bought.coin.clone(),
+                        change: bought.change,
+                        fee: false,
+                    },
+                ],
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_binance_trades, import_binance_transactions};
+    use rust_decimal_macros::*;
+
+    #[test]
+    fn trade_history_with_bnb_fee() {
+        let data = "Date(UTC),Pair,Side,Price,Executed,Amount,Fee
+2021-01-01 00:00:00,BTCUSDT,BUY,30000,0.01BTC,300USDT,0.001BNB
+2021-01-02 00:00:00,ETHBTC,SELL,0.04,1ETH,0.04BTC,0.00004BTC
+";
+        let result = import_binance_trades(data);
+
+        assert!(result.errors.is_empty());
+        let buy = &result.trades[0];
+        assert_eq!(buy.date, 1609459200000);
+        assert_eq!(buy.sold_currency, "USDT");
+        assert_eq!(buy.bought_currency, "BTC");
+        assert_eq!(buy.amount_sold, dec!(300));
+        assert_eq!(buy.transaction_fee, dec!(0.001));
+        assert_eq!(buy.transaction_fee_currency, "BNB");
+        assert_eq!(buy.amount_bought("USD").0, dec!(0.01));
+
+        let sell = &result.trades[1];
+        assert_eq!(sell.sold_currency, "ETH");
+        assert_eq!(sell.amount_bought("USD").0, dec!(0.03996));
+    }
+
+    #[test]
+    fn older_trade_history_splits_pairs() {
+        let data = "Date(UTC),Market,Type,Price,Amount,Total,Fee,Fee Coin
+21-01-01 00:00:00,ADAUSDT,SELL,0.2,100,20,0.02,USDT
+";
+        let result = import_binance_trades(data);
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.trades[0].date, 1609459200000);
+        assert_eq!(result.trades[0].sold_currency, "ADA");
+        assert_eq!(result.trades[0].bought_currency, "USDT");
+    }
+
+    #[test]
+    fn transaction_history() {
+        let data = "User_ID,UTC_Time,Account,Operation,Coin,Change,Remark
+1,2021-01-01 00:00:00,Spot,Transaction Spend,USDT,-200,
+1,2021-01-01 00:00:00,Spot,Transaction Buy,BTC,0.004,
+1,2021-01-01 00:00:00,Spot,Transaction Spend,USDT,-100,
+1,2021-01-01 00:00:00,Spot,Transaction Buy,BTC,0.002,
+1,2021-01-01 00:00:00,Spot,Transaction Fee,BNB,-0.001,
+1,2021-01-02 00:00:00,Spot,Small assets exchange BNB,ADA,-0.5,
+1,2021-01-02 00:00:00,Spot,Small assets exchange BNB,BNB,0.0004,
+1,2021-01-02 00:00:00,Spot,Small assets exchange BNB,DOT,-0.01,
+1,2021-01-02 00:00:00,Spot,Small assets exchange BNB,BNB,0.0002,
+1,2021-01-03 00:00:00,Earn,Simple Earn Flexible Interest,USDT,0.01,
+1,2021-01-03 00:00:00,Spot,Launchpool Interest,XVS,1.5,
+1,2021-01-03 00:00:00,Earn,Simple Earn Flexible Subscription,USDT,-10,
+1,2021-01-04 00:00:00,Spot,Withdraw,BTC,-0.006,
+1,2021-01-04 00:00:00,Spot,Something New,BTC,1,
+";
+        let result = import_binance_transactions(data);
+
+        assert_eq!(result.trades.len(), 3);
+        let trade = &result.trades[0];
+        assert_eq!(trade.sold_currency, "USDT");
+        assert_eq!(trade.bought_currency, "BTC");
+        assert_eq!(trade.amount_sold, dec!(300));
+        assert_eq!(trade.rate, dec!(50000));
+        assert_eq!(trade.transaction_fee_currency, "BNB");
+
+        assert_eq!(result.trades[1].sold_currency, "ADA");
+        assert_eq!(result.trades[1].bought_currency, "BNB");
+        assert_eq!(result.trades[2].sold_currency, "DOT");
+        assert_eq!(
+            result.trades[2].amount_sold / result.trades[2].rate,
+            dec!(0.0002)
+        );
+
+        assert_eq!(result.incomes.len(), 2);
+        assert_eq!(result.transfers.len(), 1);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].row, 15);
+    }
+
+    #[test]
+    fn fills_and_markets_in_the_same_second_stay_apart() {
+        let data = "Date(UTC),Pair,Side,Price,Executed,Amount,Fee
+2021-01-01 00:00:00,BTCUSDT,BUY,30000,0.01BTC,300USDT,0.001BNB
+2021-01-01 00:00:00,BTCUSDT,BUY,30000,0.01BTC,300USDT,0.001BNB
+";
+        let result = import_binance_trades(data);
+        assert_eq!(result.trades.len(), 2);
+        assert_ne!(result.trades[0].id, result.trades[1].id);
+
+        let data = "User_ID,UTC_Time,Account,Operation,Coin,Change,Remark
+1,2021-01-01 00:00:00,Spot,Transaction Spend,USDT,-300,
+1,2021-01-01 00:00:00,Spot,Transaction Buy,BTC,0.01,
+1,2021-01-01 00:00:00,Cross Margin,Transaction Sold,ETH,-1,
+1,2021-01-01 00:00:00,Cross Margin,Transaction Revenue,USDT,2000,
+";
+        let result = import_binance_transactions(data);
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.trades.len(), 2);
+        assert_eq!(result.trades[0].bought_currency, "BTC");
+        assert_eq!(result.trades[1].sold_currency, "ETH");
+        assert_ne!(result.trades[0].id, result.trades[1].id);
+    }
+
+    #[test]
+    fn ids_survive_a_second_import() {
+        let first = "Date(UTC),Pair,Side,Price,Executed,Amount,Fee
+2021-01-01 00:00:00,BTCUSDT,BUY,30000,0.01BTC,300USDT,0.001BNB
+";
+        let both = "Date(UTC),Pair,Side,Price,Executed,Amount,Fee
+2020-12-31 00:00:00,ETHBTC,SELL,0.04,1ETH,0.04BTC,0.00004BTC
+2021-01-01 00:00:00,BTCUSDT,BUY,30000,0.01BTC,300USDT,0.001BNB
+2021-01-01 00:00:00,BTCUSDT,BUY,30000,0.01BTC,300USDT,0.001BNB
+";
+        let first = import_binance_trades(first);
+        let both = import_binance_trades(both);
+        assert_eq!(first.trades[0].id, both.trades[1].id);
+        assert_ne!(both.trades[1].id, both.trades[2].id);
+
+        let rewards = "User_ID,UTC_Time,Account,Operation,Coin,Change,Remark
+1,2021-01-03 00:00:00,Earn,Simple Earn Flexible Interest,USDT,0.01,
+1,2021-01-03 00:00:00,Earn,Simple Earn Flexible Interest,USDT,0.01,
+";
+        let result = import_binance_transactions(rewards);
+        assert_eq!(result.incomes.len(), 2);
+        assert_ne!(result.incomes[0].id, result.incomes[1].id);
+        assert_eq!(
+            result.incomes[0].id,
+            import_binance_transactions(rewards).incomes[0].id
+        );
+    }
+}
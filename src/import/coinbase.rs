@@ -0,0 +1,351 @@
+use crate::import::{parse_date, parse_decimal, Columns, Fill, ImportResult, Side};
+use crate::income::Income;
+use crate::trade::Trade;
+use crate::transfer::{Transfer, TransferDirection};
+use csv::ReaderBuilder;
+use rust_decimal::prelude::{Decimal, Zero};
+use wasm_bindgen::prelude::*;
+
+static EXCHANGE: &str = "Coinbase";
+
+const INCOME_TYPES: [&str; 8] = [
+    "Rewards Income",
+    "Staking Income",
+    "Learning Reward",
+    "Coinbase Earn",
+    "Inflation Reward",
+    "Reward Income",
+    "Interest Income",
+    "Incentives Rewards Payout",
+];
+
+const SEND_TYPES: [&str; 4] = ["Send", "Withdrawal", "Exchange Deposit", "Pro Deposit"];
+
+const RECEIVE_TYPES: [&str; 4] = [
+    "Receive",
+    "Deposit",
+    "Exchange Withdrawal",
+    "Pro Withdrawal",
+];
+
+#[wasm_bindgen]
+pub fn import_coinbase_wasm(data: String) -> JsValue {
+    JsValue::from_serde(&import_coinbase(&data)).unwrap()
+}
+
+pub fn import_coinbase(data: &str) -> ImportResult {
+    let mut result = ImportResult::default();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data.as_bytes());
+
+    // older exports start with a few lines about the account before the header
+    let mut headers = None;
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                let row = error.position().map_or(0, |position| position.line());
+                result.error(row, error.to_string());
+                continue;
+            }
+        };
+        let row = record.position().map_or(0, |position| position.line());
+
+        let headers = match &headers {
+            Some(headers) => headers,
+            None => {
+                if record
+                    .iter()
+                    .any(|field| field.trim() == "Transaction Type")
+                {
+                    headers = Some(record);
+                }
+                continue;
+            }
+        };
+
+        let columns = Columns {
+            headers,
+            record: &record,
+        };
+        if let Err(message) = parse_row(&columns, &mut result) {
+            result.error(row, message);
+        }
+    }
+
+    if headers.is_none() {
+        result.error(0, "missing transaction header".to_string());
+    }
+
+    result
+}
+
+fn parse_money(value: &str) -> Result<Decimal, String> {
+    let value: String = value
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+        .collect();
+    parse_decimal(&value).map(|amount| amount.abs())
+}
+
+fn money_column(
+    columns: &Columns,
+    name: &str,
+    predicate: impl Fn(&str) -> bool,
+) -> Result<Decimal, String> {
+    let header = columns
+        .header(predicate)
+        .ok_or_else(|| format!("missing column \"{}\"", name))?;
+    parse_money(columns.get(header)?)
+}
+
+fn fiat_currency(columns: &Columns) -> Result<String, String> {
+    if let Ok(currency) = columns.get_any(&["Spot Price Currency", "Price Currency"]) {
+        return Ok(currency.trim().to_uppercase());
+    }
+
+    // the first exports put the currency in front of each header such as "USD Spot Price at Transaction"
+    columns
+        .header(|header| header.ends_with(" Spot Price at Transaction"))
+        .and_then(|header| header.split(' ').next())
+        .map(str::to_uppercase)
+        .ok_or_else(|| "missing spot price currency".to_string())
+}
+
+fn parse_row(columns: &Columns, result: &mut ImportResult) -> Result<(), String> {
+    let timestamp = columns.get("Timestamp")?;
+    let date = parse_date(timestamp, "rfc3339", None)
+        .or_else(|_| parse_date(timestamp, "%Y-%m-%d %H:%M:%S UTC", None))?;
+    let kind = columns.get("Transaction Type")?.trim();
+    let currency = columns.get("Asset")?.trim().to_uppercase();
+    let quantity = parse_decimal(columns.get("Quantity Transacted")?)?.abs();
+    let fiat_currency = fiat_currency(columns)?;
+
+    let spot_price = money_column(columns, "Spot Price at Transaction", |header| {
+        header.ends_with("Spot Price at Transaction") || header == "Price at Transaction"
+    })?;
+    let subtotal = money_column(columns, "Subtotal", |header| header.ends_with("Subtotal"))?;
+    let total = money_column(columns, "Total", |header| {
+        header.contains("Total (inclusive")
+    })?;
+    let fees = money_column(columns, "Fees", |header| {
+        header.ends_with("Fees") || header.starts_with("Fees")
+    })?;
+    let notes = columns.get("Notes").unwrap_or_default().trim();
+
+    // newer exports have an id for each row, otherwise the row contents are used so reimports line up
+    let id = match columns.get("ID") {
+        Ok(id) if !id.trim().is_empty() => id.trim().to_string(),
+        _ => format!("{}-{}-{}-{}-{}", EXCHANGE, date, kind, currency, quantity),
+    };
+
+    // fiat moving in or out of the account
+    if currency == fiat_currency {
+        return Ok(());
+    }
+
+    if quantity.is_zero() {
+        return Err("quantity is zero".to_string());
+    }
+
+    if INCOME_TYPES.contains(&kind) {
+        result.incomes.push(Income {
+            amount: quantity,
+            currency,
+            transaction_id: None,
+            id,
+            fee: None,
+            date,
+            fiat_rate: Some(spot_price),
+        });
+        return Ok(());
+    }
+
+    let direction = if SEND_TYPES.contains(&kind) {
+        Some(TransferDirection::Withdrawal)
+    } else if RECEIVE_TYPES.contains(&kind) {
+        Some(TransferDirection::Deposit)
+    } else {
+        None
+    };
+    if let Some(direction) = direction {
+        result.transfers.push(Transfer {
+            id,
+            currency,
+            amount: quantity,
+            date,
+            direction,
+            exchange: EXCHANGE.to_string(),
+            fee: if fees.is_zero() || spot_price.is_zero() {
+                None
+            } else {
+                Some(fees / spot_price)
+            },
+            transaction_id: None,
+        });
+        return Ok(());
+    }
+
+    let subtotal = if subtotal.is_zero() {
+        quantity * spot_price
+    } else {
+        subtotal
+    };
+
+    let mut trade = match kind {
+        "Buy" | "Advanced Trade Buy" => {
+            // the fees are part of what was paid so they go into the cost basis
+            let total = if total.is_zero() {
+                subtotal + fees
+            } else {
+                total
+            };
+            let mut trade = Trade::from(Fill {
+                exchange_id: id.clone(),
+                id,
+                exchange: EXCHANGE.to_string(),
+                date,
+                side: Side::Buy,
+                base: currency,
+                quote: fiat_currency.clone(),
+                amount: quantity,
+                total,
+                fee: fees,
+                fee_currency: fiat_currency,
+            });
+            trade.fiat_rate = Some(total / quantity);
+            trade
+        }
+        "Sell" | "Advanced Trade Sell" => {
+            let mut trade = Trade::from(Fill {
+                exchange_id: id.clone(),
+                id,
+                exchange: EXCHANGE.to_string(),
+                date,
+                side: Side::Sell,
+                base: currency,
+                quote: fiat_currency.clone(),
+                amount: quantity,
+                total: subtotal,
+                fee: fees,
+                fee_currency: fiat_currency,
+            });
+            trade.fiat_rate = Some(subtotal / quantity);
+            trade
+        }
+        "Convert" => {
+            // "Converted 0.5 ETH to 0.01 BTC", the spread is already taken out of the amount received
+            let words: Vec<&str> = notes.split_whitespace().collect();
+            let (bought_amount, bought_currency) = match words.iter().position(|word| *word == "to")
+            {
+                Some(index) if index + 2 < words.len() => (
+                    parse_money(words[index + 1])?,
+                    words[index + 2].to_uppercase(),
+                ),
+                _ => return Err(format!("unable to read conversion from \"{}\"", notes)),
+            };
+            if bought_amount.is_zero() {
+                return Err(format!("unable to read conversion from \"{}\"", notes));
+            }
+
+            Trade::from(Fill {
+                exchange_id: id.clone(),
+                id,
+                exchange: EXCHANGE.to_string(),
+                date,
+                side: Side::Sell,
+                base: currency,
+                quote: bought_currency.clone(),
+                amount: quantity,
+                total: bought_amount,
+                fee: Zero::zero(),
+                fee_currency: bought_currency,
+            })
+        }
+        _ => return Err(format!("unknown transaction type \"{}\"", kind)),
+    };
+
+    if trade.fiat_rate.is_none() {
+        trade.fiat_rate = Some(spot_price);
+    }
+    result.trades.push(trade);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::import_coinbase;
+    use crate::transfer::TransferDirection;
+    use rust_decimal_macros::*;
+
+    #[test]
+    fn imports_original_export() {
+        let data = "You can use this transaction report to inform your likely tax obligations.
+Transactions
+User,someone@example.com,abc
+
+Timestamp,Transaction Type,Asset,Quantity Transacted,USD Spot Price at Transaction,USD Subtotal,USD Total (inclusive of fees),USD Fees,Notes
+2021-01-01T00:00:00Z,Buy,BTC,0.01,30000.00,300.00,305.00,5.00,Bought 0.01 BTC for $305.00 USD
+2021-01-02T00:00:00Z,Coinbase Earn,XLM,10,0.25,2.50,2.50,0.00,Received 10 XLM from Coinbase Earn
+2021-01-03T00:00:00Z,Send,BTC,0.005,32000.00,160.00,160.00,,Sent 0.005 BTC to abc
+";
+        let result = import_coinbase(data);
+
+        assert!(result.errors.is_empty());
+        let trade = &result.trades[0];
+        assert_eq!(trade.sold_currency, "USD");
+        assert_eq!(trade.bought_currency, "BTC");
+        assert_eq!(trade.amount_sold, dec!(305));
+        assert_eq!(trade.amount_bought("USD").0, dec!(0.01));
+        assert_eq!(trade.fiat_rate, Some(dec!(30500)));
+        assert_eq!(trade.transaction_fee, dec!(5));
+
+        assert_eq!(result.incomes[0].currency, "XLM");
+        assert_eq!(result.incomes[0].fiat_rate, Some(dec!(0.25)));
+
+        assert_eq!(result.transfers[0].direction, TransferDirection::Withdrawal);
+        assert_eq!(result.transfers[0].amount, dec!(0.005));
+    }
+
+    #[test]
+    fn imports_spot_price_currency_export() {
+        let data = "Timestamp,Transaction Type,Asset,Quantity Transacted,Spot Price Currency,Spot Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes
+2022-05-01T00:00:00Z,Convert,ETH,0.5,EUR,2000.00,1000.00,1000.00,0.00,Converted 0.5 ETH to 0.025 BTC
+2022-05-02T00:00:00Z,Staking Income,ETH,0.001,EUR,2100.00,2.10,2.10,0.00,
+2022-05-03T00:00:00Z,Mystery,ETH,1,EUR,2100.00,2100.00,2100.00,0.00,
+";
+        let result = import_coinbase(data);
+
+        let trade = &result.trades[0];
+        assert_eq!(trade.sold_currency, "ETH");
+        assert_eq!(trade.bought_currency, "BTC");
+        assert_eq!(trade.amount_sold, dec!(0.5));
+        assert_eq!(trade.rate, dec!(20));
+        assert_eq!(trade.fiat_rate, Some(dec!(2000)));
+        assert_eq!(result.incomes.len(), 1);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].row, 4);
+    }
+
+    #[test]
+    fn imports_current_export() {
+        let data = "ID,Timestamp,Transaction Type,Asset,Quantity Transacted,Price Currency,Price at Transaction,Subtotal,Total (inclusive of fees and/or spread),Fees and/or Spread,Notes
+65a1,2024-01-01 00:00:00 UTC,Sell,BTC,-0.01,USD,\"$40,000.00\",$400.00,$395.00,$5.00,Sold 0.01 BTC
+65a2,2024-01-02 00:00:00 UTC,Deposit,USD,100,USD,$1.00,$100.00,$100.00,$0.00,
+";
+        let result = import_coinbase(data);
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!(trade.id, "65a1");
+        assert_eq!(trade.date, 1704067200000);
+        assert_eq!(trade.sold_currency, "BTC");
+        assert_eq!(trade.amount_sold, dec!(0.01));
+        assert_eq!(trade.fiat_rate, Some(dec!(40000)));
+        assert_eq!(trade.amount_bought("USD").0.round_dp(8), dec!(395));
+    }
+}
@@ -0,0 +1,349 @@
+use crate::import::{
+    parse_date, parse_decimal_with_separator, read_records, Columns, Fill, ImportResult, Side,
+};
+use crate::income::Income;
+use crate::trade::Trade;
+use rust_decimal::prelude::Zero;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d %H:%M:%S".to_string()
+}
+
+fn default_pair_separator() -> String {
+    "-".to_string()
+}
+
+fn default_buy_values() -> Vec<String> {
+    vec!["buy".to_string()]
+}
+
+fn default_sell_values() -> Vec<String> {
+    vec!["sell".to_string()]
+}
+
+// every field other than the exchange name is the header of the column to read
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CsvMapping {
+    pub exchange: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    // "." or ",", the other one is read as a thousands separator
+    #[serde(rename = "decimalSeparator", default = "default_decimal_separator")]
+    pub decimal_separator: char,
+    pub date: String,
+    #[serde(rename = "dateFormat", default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    // a single column holding both currencies such as "BTC-USD", base first
+    #[serde(default)]
+    pub pair: Option<String>,
+    #[serde(rename = "pairSeparator", default = "default_pair_separator")]
+    pub pair_separator: String,
+    #[serde(rename = "baseCurrency", default)]
+    pub base_currency: Option<String>,
+    #[serde(rename = "quoteCurrency", default)]
+    pub quote_currency: Option<String>,
+    pub side: String,
+    #[serde(rename = "buyValues", default = "default_buy_values")]
+    pub buy_values: Vec<String>,
+    #[serde(rename = "sellValues", default = "default_sell_values")]
+    pub sell_values: Vec<String>,
+    #[serde(rename = "incomeValues", default)]
+    pub income_values: Vec<String>,
+    pub amount: String,
+    #[serde(default)]
+    pub price: Option<String>,
+    #[serde(default)]
+    pub total: Option<String>,
+    #[serde(default)]
+    pub fee: Option<String>,
+    #[serde(rename = "feeCurrency", default)]
+    pub fee_currency: Option<String>,
+    // whether the total column already holds a fee paid in the quote currency
+    #[serde(rename = "totalIncludesFee", default)]
+    pub total_includes_fee: bool,
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+#[wasm_bindgen]
+pub fn import_csv_wasm(data: String, mapping: &JsValue) -> JsValue {
+    let mapping: CsvMapping = mapping.into_serde().unwrap();
+    JsValue::from_serde(&import_csv(&data, &mapping)).unwrap()
+}
+
+pub fn import_csv(data: &str, mapping: &CsvMapping) -> ImportResult {
+    let mut result = ImportResult::default();
+    // the csv reader only splits on a single byte
+    if !mapping.delimiter.is_ascii() {
+        result.error(
+            0,
+            format!("delimiter \"{}\" is not ascii", mapping.delimiter),
+        );
+        return result;
+    }
+    if mapping.decimal_separator != '.' && mapping.decimal_separator != ',' {
+        result.error(
+            0,
+            format!(
+                "decimal separator \"{}\" has to be \".\" or \",\"",
+                mapping.decimal_separator
+            ),
+        );
+        return result;
+    }
+
+    read_records(
+        data,
+        mapping.delimiter as u8,
+        &mut result,
+        |columns, row, result| parse_row(columns, mapping, row, result),
+    );
+    result
+}
+
+fn parse_row(
+    columns: &Columns,
+    mapping: &CsvMapping,
+    row: u64,
+    result: &mut ImportResult,
+) -> Result<(), String> {
+    let parse_decimal =
+        |value: &str| parse_decimal_with_separator(value, mapping.decimal_separator);
+    let date = parse_date(
+        columns.get(&mapping.date)?,
+        &mapping.date_format,
+        mapping.timezone.as_deref(),
+    )?;
+
+    let (base, quote) = match columns.get_optional(&mapping.pair)? {
+        Some(pair) => {
+            let mut currencies = pair.split(mapping.pair_separator.as_str());
+            match (currencies.next(), currencies.next()) {
+                (Some(base), Some(quote)) if !base.is_empty() && !quote.is_empty() => {
+                    (base.trim().to_uppercase(), quote.trim().to_uppercase())
+                }
+                _ => return Err(format!("invalid pair \"{}\"", pair)),
+            }
+        }
+        None => (
+            columns
+                .get_optional(&mapping.base_currency)?
+                .unwrap_or_default()
+                .trim()
+                .to_uppercase(),
+            columns
+                .get_optional(&mapping.quote_currency)?
+                .unwrap_or_default()
+                .trim()
+                .to_uppercase(),
+        ),
+    };
+    if base.is_empty() {
+        return Err("missing currency".to_string());
+    }
+
+    let id = match columns.get_optional(&mapping.id)? {
+        Some(id) => id.trim().to_string(),
+        None => format!("{}-{}-{}", mapping.exchange, date, row),
+    };
+    let amount = parse_decimal(columns.get(&mapping.amount)?)?.abs();
+    let fee = match columns.get_optional(&mapping.fee)? {
+        Some(fee) => parse_decimal(fee)?.abs(),
+        None => Zero::zero(),
+    };
+
+    let side = columns.get(&mapping.side)?.trim();
+    let matches =
+        |values: &Vec<String>| values.iter().any(|value| value.eq_ignore_ascii_case(side));
+
+    if matches(&mapping.income_values) {
+        result.incomes.push(Income {
+            amount,
+            currency: base,
+            transaction_id: None,
+            id,
+            fee: if fee.is_zero() { None } else { Some(fee) },
+            date,
+            fiat_rate: None,
+        });
+        return Ok(());
+    }
+
+    let side = if matches(&mapping.buy_values) {
+        Side::Buy
+    } else if matches(&mapping.sell_values) {
+        Side::Sell
+    } else {
+        return Err(format!("unknown side \"{}\"", side));
+    };
+
+    if quote.is_empty() {
+        return Err("missing quote currency".to_string());
+    }
+    if amount.is_zero() {
+        return Err("amount is zero".to_string());
+    }
+
+    let total = match (
+        columns.get_optional(&mapping.total)?,
+        columns.get_optional(&mapping.price)?,
+    ) {
+        (Some(total), _) => parse_decimal(total)?.abs(),
+        (None, Some(price)) => parse_decimal(price)?.abs() * amount,
+        (None, None) => return Err("either a price or total column is required".to_string()),
+    };
+    if total.is_zero() {
+        return Err("total is zero".to_string());
+    }
+
+    let fee_currency = match columns.get_optional(&mapping.fee_currency)? {
+        Some(fee_currency) if !fee_currency.trim().is_empty() => fee_currency.trim().to_uppercase(),
+        _ => quote.clone(),
+    };
+
+    let total = if side == Side::Buy && fee_currency == quote && !mapping.total_includes_fee {
+        total + fee
+    } else {
+        total
+    };
+
+    result.trades.push(Trade::from(Fill {
+        exchange_id: id.clone(),
+        id,
+        exchange: mapping.exchange.clone(),
+        date,
+        side,
+        base,
+        quote,
+        amount,
+        total,
+        fee,
+        fee_currency,
+    }));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_csv, CsvMapping};
+    use rust_decimal_macros::*;
+
+    fn mapping() -> CsvMapping {
+        CsvMapping {
+            exchange: "Example".to_string(),
+            delimiter: ',',
+            decimal_separator: '.',
+            date: "Time".to_string(),
+            date_format: "%Y-%m-%dT%H:%M:%S".to_string(),
+            timezone: Some("+01:00".to_string()),
+            pair: Some("Market".to_string()),
+            pair_separator: "-".to_string(),
+            base_currency: None,
+            quote_currency: None,
+            side: "Type".to_string(),
+            buy_values: vec!["buy".to_string()],
+            sell_values: vec!["sell".to_string()],
+            income_values: vec!["staking".to_string()],
+            amount: "Size".to_string(),
+            price: Some("Price".to_string()),
+            total: None,
+            fee: Some("Fee".to_string()),
+            fee_currency: Some("Fee Currency".to_string()),
+            total_includes_fee: false,
+            id: Some("ID".to_string()),
+        }
+    }
+
+    #[test]
+    fn imports_trades_and_incomes() {
+        let data = "ID,Time,Market,Type,Size,Price,Fee,Fee Currency
+1,2021-01-01T01:00:00,BTC-USD,BUY,2,100,1,USD
+2,2021-01-02T01:00:00,BTC-USD,sell,1,300,0.01,BTC
+3,2021-01-03T01:00:00,ETH-USD,staking,0.5,,,
+";
+        let result = import_csv(data, &mapping());
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.trades.len(), 2);
+        assert_eq!(result.incomes.len(), 1);
+
+        let buy = &result.trades[0];
+        assert_eq!(buy.date, 1609459200000);
+        assert_eq!(buy.sold_currency, "USD");
+        assert_eq!(buy.bought_currency, "BTC");
+        assert_eq!(buy.amount_sold, dec!(201));
+        assert_eq!(buy.rate, dec!(100));
+        assert_eq!(buy.transaction_fee_currency, "USD");
+        assert_eq!(buy.amount_bought("USD").0, dec!(2));
+
+        let sell = &result.trades[1];
+        assert_eq!(sell.sold_currency, "BTC");
+        assert_eq!(sell.bought_currency, "USD");
+        assert_eq!(sell.amount_sold, dec!(1));
+        assert_eq!(sell.amount_bought("USD").0.round_dp(8), dec!(297));
+
+        assert_eq!(result.incomes[0].currency, "ETH");
+        assert_eq!(result.incomes[0].amount, dec!(0.5));
+    }
+
+    #[test]
+    fn reports_errors_per_row() {
+        let data = "ID,Time,Market,Type,Size,Price,Fee,Fee Currency
+1,2021-01-01T01:00:00,BTC-USD,buy,2,100,,
+2,not a date,BTC-USD,buy,2,100,,
+3,2021-01-01T01:00:00,BTCUSD,buy,2,100,,
+4,2021-01-01T01:00:00,BTC-USD,transfer,2,100,,
+";
+        let result = import_csv(data, &mapping());
+
+        assert_eq!(result.trades.len(), 1);
+        let rows: Vec<u64> = result.errors.iter().map(|error| error.row).collect();
+        assert_eq!(rows, vec![3, 4, 5]);
+        assert!(result.errors[1].message.contains("BTCUSD"));
+    }
+
+    #[test]
+    fn reads_decimal_commas() {
+        let data = "ID;Time;Market;Type;Size;Price;Fee;Fee Currency
+1;2021-01-01T01:00:00;BTC-USD;buy;0,5;1.000,25;;
+2;2021-01-01T01:00:00;BTC-USD;buy;0.5;100;;
+";
+        let mapping = CsvMapping {
+            delimiter: ';',
+            decimal_separator: ',',
+            ..mapping()
+        };
+        let result = import_csv(data, &mapping);
+
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].amount_sold, dec!(500.125));
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].row, 3);
+    }
+
+    #[test]
+    fn refuses_delimiters_which_are_not_ascii() {
+        let mapping = CsvMapping {
+            delimiter: '§',
+            ..mapping()
+        };
+        let result = import_csv("ID§Time\n", &mapping);
+
+        assert!(result.trades.is_empty());
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.errors[0].message.contains("not ascii"));
+    }
+}
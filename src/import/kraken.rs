@@ -0,0 +1,352 @@
+use crate::import::{parse_date, parse_decimal, read_records, Fill, ImportResult, Side};
+use crate::income::Income;
+use crate::trade::Trade;
+use crate::transfer::{Transfer, TransferDirection};
+use rust_decimal::prelude::{Decimal, Zero};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+static EXCHANGE: &str = "Kraken";
+static DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+// quote currencies used to split pairs such as "XXBTZUSD" or "DOTEUR", the longest one which fits is used
+const QUOTE_CURRENCIES: [&str; 21] = [
+    "ZUSD", "ZEUR", "ZGBP", "ZCAD", "ZJPY", "ZCHF", "ZAUD", "USDT", "USDC", "XXBT", "XETH", "USD",
+    "EUR", "GBP", "CAD", "AUD", "CHF", "JPY", "XBT", "ETH", "DAI",
+];
+
+struct Leg {
+    row: u64,
+    date: u64,
+    asset: String,
+    amount: Decimal,
+    fee: Decimal,
+}
+
+// kraken still uses the old X and Z prefixed codes for its first assets and suffixes staked balances
+pub fn normalize_asset(asset: &str) -> String {
+    let asset = asset.trim().to_uppercase();
+    let asset = asset.split('.').next().unwrap_or_default();
+
+    let asset = match asset {
+        "XETC" | "XETH" | "XLTC" | "XMLN" | "XREP" | "XXBT" | "XXDG" | "XXLM" | "XXMR" | "XXRP"
+        | "XZEC" | "ZAUD" | "ZCAD" | "ZCHF" | "ZEUR" | "ZGBP" | "ZJPY" | "ZUSD" => &asset[1..],
+        "ETH2" => "ETH",
+        _ => asset,
+    };
+
+    match asset {
+        "XBT" => "BTC".to_string(),
+        "XDG" => "DOGE".to_string(),
+        _ => asset.to_string(),
+    }
+}
+
+#[wasm_bindgen]
+pub fn import_kraken_ledgers_wasm(data: String) -> JsValue {
+    JsValue::from_serde(&import_kraken_ledgers(&data)).unwrap()
+}
+
+pub fn import_kraken_ledgers(data: &str) -> ImportResult {
+    let mut result = ImportResult::default();
+    let mut refids: Vec<String> = vec![];
+    let mut legs: HashMap<String, Vec<Leg>> = HashMap::new();
+
+    read_records(data, b',', &mut result, |columns, row, result| {
+        let txid = columns.get("txid")?.trim().to_string();
+        // pending deposits and withdrawals show up a second time without a txid
+        if txid.is_empty() {
+            return Ok(());
+        }
+
+        let refid = columns.get("refid")?.trim().to_string();
+        let kind = columns.get("type")?.trim().to_lowercase();
+        let subtype = columns.get("subtype")?.trim().to_lowercase();
+        let date = parse_date(columns.get("time")?, DATE_FORMAT, None)?;
+        let asset = normalize_asset(columns.get("asset")?);
+        let amount = parse_decimal(columns.get("amount")?)?;
+        let fee = parse_decimal(columns.get("fee")?)?.abs();
+
+        match (kind.as_str(), subtype.as_str()) {
+            ("trade", _) | ("spend", _) | ("receive", _) => {
+                if !legs.contains_key(&refid) {
+                    refids.push(refid.clone());
+                }
+                legs.entry(refid).or_default().push(Leg {
+                    row,
+                    date,
+                    asset,
+                    amount,
+                    fee,
+                });
+            }
+            ("staking", _) | ("earn", "reward") => {
+                if amount > Zero::zero() {
+                    result.incomes.push(Income {
+                        amount,
+                        currency: asset,
+                        transaction_id: Some(refid),
+                        id: txid,
+                        fee: if fee.is_zero() { None } else { Some(fee) },
+                        date,
+                        fiat_rate: None,
+                    });
+                }
+            }
+            ("deposit", _) | ("withdrawal", _) => result.transfers.push(Transfer {
+                id: txid,
+                currency: asset,
+                amount: amount.abs(),
+                date,
+                direction: if kind == "deposit" {
+                    TransferDirection::Deposit
+                } else {
+                    TransferDirection::Withdrawal
+                },
+                exchange: EXCHANGE.to_string(),
+                fee: if fee.is_zero() { None } else { Some(fee) },
+                transaction_id: Some(refid),
+            }),
+            // moves between the spot and staking wallets of the same account
+            ("transfer", _) | ("earn", _) => {}
+            _ => return Err(format!("unknown ledger type \"{}\"", kind)),
+        }
+
+        Ok(())
+    });
+
+    for refid in refids {
+        let refid_legs = legs.remove(&refid).unwrap_or_default();
+        match trade_from_legs(&refid, &refid_legs) {
+            Ok(trade) => result.trades.push(trade),
+            Err(message) => result.error(refid_legs[0].row, message),
+        }
+    }
+
+    result
+}
+
+fn trade_from_legs(refid: &str, legs: &[Leg]) -> Result<Trade, String> {
+    let sold: Vec<&Leg> = legs
+        .iter()
+        .filter(|leg| leg.amount < Zero::zero())
+        .collect();
+    let bought: Vec<&Leg> = legs
+        .iter()
+        .filter(|leg| leg.amount > Zero::zero())
+        .collect();
+    let (sold, bought) = match (sold.as_slice(), bought.as_slice()) {
+        ([sold], [bought]) => (*sold, *bought),
+        _ => {
+            return Err(format!(
+                "trade {} needs one leg sold and one bought, found {}",
+                refid,
+                legs.len()
+            ))
+        }
+    };
+
+    let amount_sold = -sold.amount;
+    let rate = amount_sold / bought.amount;
+
+    // each leg pays its own fee, fees on the bought side are moved over so one fee covers both
+    let (transaction_fee, transaction_fee_currency) = if sold.fee.is_zero() {
+        (bought.fee, bought.asset.clone())
+    } else {
+        (sold.fee + bought.fee * rate, sold.asset.clone())
+    };
+
+    Ok(Trade {
+        bought_currency: bought.asset.clone(),
+        sold_currency: sold.asset.clone(),
+        amount_sold: amount_sold + sold.fee,
+        rate,
+        date: sold.date.min(bought.date),
+        // the refid of trade legs is the trade's own txid, which is what the trades export uses
+        exchange_id: refid.to_string(),
+        exchange: EXCHANGE.to_string(),
+        id: refid.to_string(),
+        transaction_fee,
+        transaction_fee_currency,
+        fiat_rate: None,
+        short_term: None,
+        long_term: None,
+        date_acquired: None,
+        cost_basis: None,
+        long_term_trade: None,
+        lot_id: None,
+        acquisition_id: None,
+    })
+}
+
+fn split_pair(pair: &str) -> Option<(String, String)> {
+    let pair = pair.trim().to_uppercase();
+    if let Some((base, quote)) = pair.split_once('/') {
+        return Some((normalize_asset(base), normalize_asset(quote)));
+    }
+
+    QUOTE_CURRENCIES
+        .iter()
+        .filter(|quote| pair.len() > quote.len() && pair.ends_with(*quote))
+        .max_by_key(|quote| quote.len())
+        .map(|quote| {
+            let base = &pair[..pair.len() - quote.len()];
+            (normalize_asset(base), normalize_asset(quote))
+        })
+}
+
+#[wasm_bindgen]
+pub fn import_kraken_trades_wasm(data: String) -> JsValue {
+    JsValue::from_serde(&import_kraken_trades(&data)).unwrap()
+}
+
+pub fn import_kraken_trades(data: &str) -> ImportResult {
+    let mut result = ImportResult::default();
+
+    read_records(data, b',', &mut result, |columns, _, result| {
+        let pair = columns.get("pair")?;
+        let (base, quote) =
+            split_pair(pair).ok_or_else(|| format!("unable to split pair \"{}\"", pair))?;
+        let side = match columns.get("type")?.trim() {
+            "buy" => Side::Buy,
+            "sell" => Side::Sell,
+            side => return Err(format!("unknown side \"{}\"", side)),
+        };
+        // every fill of an order has its own txid, the order's would make them look like duplicates
+        let txid = columns.get("txid")?.trim().to_string();
+        // the cost leaves out the fee, which was paid on top of it when buying
+        let cost = parse_decimal(columns.get("cost")?)?;
+        let fee = parse_decimal(columns.get("fee")?)?;
+        let total = match side {
+            Side::Buy => cost + fee,
+            Side::Sell => cost,
+        };
+
+        result.trades.push(Trade::from(Fill {
+            id: txid.clone(),
+            exchange: EXCHANGE.to_string(),
+            exchange_id: txid,
+            date: parse_date(columns.get("time")?, DATE_FORMAT, None)?,
+            side,
+            base,
+            quote: quote.clone(),
+            amount: parse_decimal(columns.get("vol")?)?,
+            total,
+            fee,
+            fee_currency: quote,
+        }));
+
+        Ok(())
+    });
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_kraken_ledgers, import_kraken_trades, normalize_asset, split_pair};
+    use crate::transfer::TransferDirection;
+    use rust_decimal_macros::*;
+
+    #[test]
+    fn assets_are_normalized() {
+        assert_eq!(normalize_asset("XXBT"), "BTC");
+        assert_eq!(normalize_asset("ZUSD"), "USD");
+        assert_eq!(normalize_asset("XETH"), "ETH");
+        assert_eq!(normalize_asset("ETH2.S"), "ETH");
+        assert_eq!(normalize_asset("DOT.S"), "DOT");
+        assert_eq!(normalize_asset("XBT.M"), "BTC");
+        assert_eq!(normalize_asset("XTZ"), "XTZ");
+        assert_eq!(normalize_asset("USDT"), "USDT");
+    }
+
+    #[test]
+    fn ledger_legs_are_joined() {
+        let data = r#""txid","refid","time","type","subtype","aclass","asset","amount","fee","balance"
+"L1","T1","2021-01-01 00:00:00","trade","","currency","ZUSD","-300.0000","0.7800","700.0000"
+"L2","T1","2021-01-01 00:00:00","trade","","currency","XXBT","0.0100000000","0.0000000000","0.0100000000"
+"L3","T2","2021-01-02 00:00:00.5","trade","","currency","XXBT","-0.0050000000","0.0000000000","0.0050000000"
+"L4","T2","2021-01-02 00:00:00.5","trade","","currency","XETH","0.2000000000","0.0010000000","0.1990000000"
+"L5","S1","2021-01-03 00:00:00","staking","","currency","DOT.S","0.5000000000","0.0000000000","0.5000000000"
+"","D1","2021-01-04 00:00:00","deposit","","currency","XXBT","1.0000000000","0.0000000000",""
+"L6","D1","2021-01-04 00:05:00","deposit","","currency","XXBT","1.0000000000","0.0000000000","1.0050000000"
+"L7","T3","2021-01-05 00:00:00","trade","","currency","XXBT","-0.1000000000","0.0000000000","0.9050000000"
+"#;
+        let result = import_kraken_ledgers(data);
+
+        assert_eq!(result.trades.len(), 2);
+        let buy = &result.trades[0];
+        assert_eq!(buy.sold_currency, "USD");
+        assert_eq!(buy.bought_currency, "BTC");
+        assert_eq!(buy.amount_sold, dec!(300.78));
+        assert_eq!(buy.rate, dec!(30000));
+        assert_eq!(buy.transaction_fee, dec!(0.78));
+        assert_eq!(buy.transaction_fee_currency, "USD");
+        assert_eq!(buy.amount_bought("USD").0, dec!(0.01));
+        assert_eq!(buy.exchange_id, "T1");
+
+        let swap = &result.trades[1];
+        assert_eq!(swap.date, 1609545600500);
+        assert_eq!(swap.sold_currency, "BTC");
+        assert_eq!(swap.bought_currency, "ETH");
+        assert_eq!(swap.transaction_fee_currency, "ETH");
+        assert_eq!(swap.amount_bought("USD").0, dec!(0.199));
+
+        assert_eq!(result.incomes.len(), 1);
+        assert_eq!(result.incomes[0].currency, "DOT");
+        assert_eq!(result.transfers.len(), 1);
+        assert_eq!(result.transfers[0].direction, TransferDirection::Deposit);
+
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].row, 9);
+    }
+
+    #[test]
+    fn trades_split_pairs() {
+        let data = r#""txid","ordertxid","pair","time","type","ordertype","price","cost","fee","vol","margin","misc","ledgers"
+"T1","O1","XXBTZUSD","2021-01-01 00:00:00.1234","buy","limit","30000.0","300.0","0.78","0.01","0.0","","L1,L2"
+"T2","O2","DOTEUR","2021-01-02 00:00:00","sell","market","20.0","200.0","0.5","10","0.0","","L3,L4"
+"T3","O2","DOTEUR","2021-01-02 00:00:01","sell","market","20.0","200.0","0.5","10","0.0","","L5,L6"
+"#;
+        let result = import_kraken_trades(data);
+
+        assert!(result.errors.is_empty());
+        assert_eq!(result.trades[0].bought_currency, "BTC");
+        assert_eq!(result.trades[0].sold_currency, "USD");
+        assert_eq!(result.trades[0].rate, dec!(30000));
+        assert_eq!(result.trades[1].sold_currency, "DOT");
+        assert_eq!(result.trades[1].bought_currency, "EUR");
+        assert_eq!(result.trades[1].transaction_fee_currency, "EUR");
+        // fills of the same order stay apart
+        assert_eq!(result.trades[1].exchange_id, "T2");
+        assert_eq!(result.trades[2].exchange_id, "T3");
+    }
+
+    #[test]
+    fn pairs_split_on_the_longest_quote() {
+        let split = |pair: &str| split_pair(pair).unwrap();
+        assert_eq!(split("XETHXXBT"), ("ETH".to_string(), "BTC".to_string()));
+        assert_eq!(split("USDTZUSD"), ("USDT".to_string(), "USD".to_string()));
+        assert_eq!(split("ADAGBP"), ("ADA".to_string(), "GBP".to_string()));
+        assert_eq!(split("LINKETH"), ("LINK".to_string(), "ETH".to_string()));
+        assert_eq!(split("ETHDAI"), ("ETH".to_string(), "DAI".to_string()));
+        assert_eq!(split("SOLJPY"), ("SOL".to_string(), "JPY".to_string()));
+        assert_eq!(split("ETHUSDT"), ("ETH".to_string(), "USDT".to_string()));
+    }
+
+    #[test]
+    fn trades_and_ledgers_read_a_fill_the_same() {
+        let trades = r#""txid","ordertxid","pair","time","type","ordertype","price","cost","fee","vol","margin","misc","ledgers"
+"T1","O1","XXBTZUSD","2021-01-01 00:00:00","buy","limit","30000.0","300.0","0.78","0.01","0.0","","L1,L2"
+"#;
+        let ledgers = r#""txid","refid","time","type","subtype","aclass","asset","amount","fee","balance"
+"L1","T1","2021-01-01 00:00:00","trade","","currency","ZUSD","-300.0000","0.7800","700.0000"
+"L2","T1","2021-01-01 00:00:00","trade","","currency","XXBT","0.0100000000","0.0000000000","0.0100000000"
+"#;
+        let from_trades = import_kraken_trades(trades);
+        let from_ledgers = import_kraken_ledgers(ledgers);
+
+        assert_eq!(from_trades.trades, from_ledgers.trades);
+        assert_eq!(from_trades.trades[0].amount_bought("USD").0, dec!(0.01));
+    }
+}
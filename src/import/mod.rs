@@ -0,0 +1,333 @@
+pub mod beancount;
+pub mod binance;
+pub mod coinbase;
+pub mod generic;
+pub mod kraken;
+
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::trade::Trade;
+use crate::transfer::Transfer;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use csv::{ReaderBuilder, StringRecord};
+use rust_decimal::prelude::{Decimal, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ImportError {
+    pub row: u64,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct ImportResult {
+    pub trades: Vec<Trade>,
+    pub incomes: Vec<Income>,
+    #[serde(default)]
+    pub transfers: Vec<Transfer>,
+    // lots which were already held before the imported history starts
+    #[serde(default)]
+    pub holdings: Holdings,
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportResult {
+    pub fn error(&mut self, row: u64, message: String) {
+        self.errors.push(ImportError { row, message });
+    }
+}
+
+pub struct Columns<'a> {
+    pub headers: &'a StringRecord,
+    pub record: &'a StringRecord,
+}
+
+impl<'a> Columns<'a> {
+    pub fn get(&self, column: &str) -> Result<&'a str, String> {
+        self.headers
+            .iter()
+            .position(|header| header.trim() == column)
+            .and_then(|index| self.record.get(index))
+            .ok_or_else(|| format!("missing column \"{}\"", column))
+    }
+
+    pub fn get_optional(&self, column: &Option<String>) -> Result<Option<&'a str>, String> {
+        match column {
+            Some(column) => self.get(column).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // the first of the columns which the file has, for exports which renamed their headers
+    pub fn get_any(&self, columns: &[&str]) -> Result<&'a str, String> {
+        columns
+            .iter()
+            .find_map(|column| self.get(column).ok())
+            .ok_or_else(|| format!("missing column \"{}\"", columns.join("\" or \"")))
+    }
+
+    pub fn header(&self, predicate: impl Fn(&str) -> bool) -> Option<&'a str> {
+        self.headers
+            .iter()
+            .map(str::trim)
+            .find(|header| predicate(header))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+// a single fill of base against quote as exchanges usually report it
+pub struct Fill {
+    pub id: String,
+    pub exchange: String,
+    pub exchange_id: String,
+    pub date: u64,
+    pub side: Side,
+    pub base: String,
+    pub quote: String,
+    pub amount: Decimal,
+    // everything paid for a buy with a fee in the quote currency included, what a sell made before its fee
+    pub total: Decimal,
+    pub fee: Decimal,
+    pub fee_currency: String,
+}
+
+impl From<Fill> for Trade {
+    fn from(fill: Fill) -> Trade {
+        // the rate is the price alone so the whole amount bought is left after the fee
+        let price_total = if fill.fee_currency == fill.quote {
+            fill.total - fill.fee
+        } else {
+            fill.total
+        };
+        let (bought_currency, sold_currency, amount_sold, rate) = match fill.side {
+            Side::Buy => (fill.base, fill.quote, fill.total, price_total / fill.amount),
+            Side::Sell => (fill.quote, fill.base, fill.amount, fill.amount / fill.total),
+        };
+
+        Trade {
+            bought_currency,
+            sold_currency,
+            amount_sold,
+            rate,
+            date: fill.date,
+            exchange_id: fill.exchange_id,
+            exchange: fill.exchange,
+            id: fill.id,
+            transaction_fee: fill.fee,
+            transaction_fee_currency: fill.fee_currency,
+            fiat_rate: None,
+            short_term: None,
+            long_term: None,
+            date_acquired: None,
+            cost_basis: None,
+            long_term_trade: None,
+            lot_id: None,
+            acquisition_id: None,
+        }
+    }
+}
+
+// calls parse with each row, collecting the errors against the rows line number
+pub fn read_records(
+    data: &str,
+    delimiter: u8,
+    result: &mut ImportResult,
+    mut parse: impl FnMut(&Columns, u64, &mut ImportResult) -> Result<(), String>,
+) {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(data.as_bytes());
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(error) => {
+            result.error(1, error.to_string());
+            return;
+        }
+    };
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                let row = error.position().map_or(0, |position| position.line());
+                result.error(row, error.to_string());
+                continue;
+            }
+        };
+        let row = record.position().map_or(0, |position| position.line());
+
+        let columns = Columns {
+            headers: &headers,
+            record: &record,
+        };
+        if let Err(message) = parse(&columns, row, result) {
+            result.error(row, message);
+        }
+    }
+}
+
+pub fn parse_decimal(value: &str) -> Result<Decimal, String> {
+    parse_decimal_with_separator(value, '.')
+}
+
+// the other of "." and "," can only group thousands, anything which could be read either way is an error
+pub fn parse_decimal_with_separator(
+    value: &str,
+    decimal_separator: char,
+) -> Result<Decimal, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(Zero::zero());
+    }
+    let group_separator = if decimal_separator == ',' { '.' } else { ',' };
+    let ambiguous = || format!("ambiguous number \"{}\"", value);
+
+    let (whole, fraction) = match value.split_once(decimal_separator) {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (value, None),
+    };
+    if fraction.is_some_and(|fraction| {
+        fraction.contains(decimal_separator) || fraction.contains(group_separator)
+    }) {
+        return Err(ambiguous());
+    }
+
+    let mut groups = whole.split(group_separator);
+    let first_digits = groups
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches(['-', '+'])
+        .len();
+    let mut groups = groups.peekable();
+    if groups.peek().is_some()
+        && (first_digits == 0
+            || first_digits > 3
+            || groups.any(|group| group.len() != 3 || !group.chars().all(|c| c.is_ascii_digit())))
+    {
+        return Err(ambiguous());
+    }
+
+    let mut number = whole.replace(group_separator, "");
+    if let Some(fraction) = fraction {
+        number.push('.');
+        number.push_str(fraction);
+    }
+
+    Decimal::from_str(&number)
+        .or_else(|_| Decimal::from_scientific(&number))
+        .map_err(|_| format!("invalid number \"{}\"", value))
+}
+
+// dates before 1970 can't be held in milliseconds since then
+fn to_milliseconds<T: TimeZone>(date_time: DateTime<T>) -> Option<u64> {
+    u64::try_from(date_time.timestamp_millis()).ok()
+}
+
+// formats are chrono format strings, "unix" and "unixMilliseconds" read timestamps and "rfc3339" reads iso dates
+pub fn parse_date(value: &str, format: &str, timezone: Option<&str>) -> Result<u64, String> {
+    let value = value.trim();
+    let invalid = || format!("invalid date \"{}\" for format \"{}\"", value, format);
+
+    match format {
+        "unix" => {
+            return parse_decimal(value)
+                .ok()
+                .and_then(|seconds| (seconds * Decimal::from(1000)).to_u64())
+                .ok_or_else(invalid)
+        }
+        "unixMilliseconds" => return value.parse::<u64>().map_err(|_| invalid()),
+        "rfc3339" => {
+            return DateTime::parse_from_rfc3339(value)
+                .ok()
+                .and_then(to_milliseconds)
+                .ok_or_else(invalid)
+        }
+        _ => {}
+    }
+
+    if let Ok(date_time) = DateTime::parse_from_str(value, format) {
+        return to_milliseconds(date_time).ok_or_else(invalid);
+    }
+
+    let offset = match timezone {
+        None => FixedOffset::east_opt(0).unwrap(),
+        Some("UTC") | Some("Z") => FixedOffset::east_opt(0).unwrap(),
+        Some(timezone) => FixedOffset::from_str(timezone)
+            .map_err(|_| format!("invalid timezone \"{}\"", timezone))?,
+    };
+
+    let naive_date_time = NaiveDateTime::parse_from_str(value, format)
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, format)
+                .map(|naive_date| naive_date.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .map_err(|_| invalid())?;
+
+    offset
+        .from_local_datetime(&naive_date_time)
+        .single()
+        .and_then(to_milliseconds)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_date, parse_decimal, parse_decimal_with_separator};
+    use rust_decimal_macros::*;
+
+    #[test]
+    fn dates_use_format_and_timezone() {
+        assert_eq!(
+            parse_date("2021-01-01 00:00:00", "%Y-%m-%d %H:%M:%S", None),
+            Ok(1609459200000)
+        );
+        assert_eq!(
+            parse_date("2021-01-01 02:00:00", "%Y-%m-%d %H:%M:%S", Some("+02:00")),
+            Ok(1609459200000)
+        );
+        assert_eq!(
+            parse_date("01/01/2021", "%m/%d/%Y", None),
+            Ok(1609459200000)
+        );
+        assert_eq!(parse_date("1609459200", "unix", None), Ok(1609459200000));
+        assert_eq!(
+            parse_date("2021-01-01T00:00:00Z", "rfc3339", None),
+            Ok(1609459200000)
+        );
+        assert!(parse_date("yesterday", "%Y-%m-%d", None).is_err());
+        assert!(parse_date("1969-12-31", "%Y-%m-%d", None).is_err());
+        assert!(parse_date("1969-12-31T23:59:59Z", "rfc3339", None).is_err());
+    }
+
+    #[test]
+    fn decimals_allow_separators_and_exponents() {
+        assert_eq!(parse_decimal("1,000.5"), Ok(dec!(1000.5)));
+        assert_eq!(parse_decimal("1e-8"), Ok(dec!(0.00000001)));
+        assert!(parse_decimal("abc").is_err());
+    }
+
+    #[test]
+    fn decimals_with_a_comma() {
+        assert_eq!(
+            parse_decimal_with_separator("1.000,5", ','),
+            Ok(dec!(1000.5))
+        );
+        assert_eq!(parse_decimal_with_separator("-0,25", ','), Ok(dec!(-0.25)));
+        assert_eq!(parse_decimal_with_separator("1.000", ','), Ok(dec!(1000)));
+        // these could be read either way so they are refused
+        assert!(parse_decimal("1,5").is_err());
+        assert!(parse_decimal("1,000,00").is_err());
+        assert!(parse_decimal("1.000,5").is_err());
+        assert!(parse_decimal_with_separator("1.5", ',').is_err());
+        assert!(parse_decimal_with_separator("1,2,3", ',').is_err());
+    }
+}
@@ -19,4 +19,11 @@ impl Income {
     pub fn fiat_rate(self: Income) -> Decimal {
         self.fiat_rate.unwrap_or_else(Zero::zero)
     }
+
+    // incomes are added to holdings as a lot named after them, so ones without an id are named the way lots are
+    pub fn fill_missing_id(&mut self, index: usize) {
+        if self.id.is_empty() {
+            self.id = format!("{}-{}-{}", self.currency, self.date, index);
+        }
+    }
 }
@@ -1,15 +1,33 @@
 use rust_decimal::prelude::Decimal;
 use rust_decimal_macros::*;
 
+#[cfg(test)]
+mod benchmarks;
 pub mod calculate_gain_per_trade;
 pub mod calculate_gains;
 pub mod calculate_gain_per_holdings;
+pub mod compare_methods;
+pub mod date;
+pub mod duplicates;
+pub mod engine;
+pub mod event;
+pub mod export;
+pub mod fiat;
 pub mod holding;
 pub mod holding_selection;
+pub mod import;
 pub mod income;
 pub mod method;
 pub mod mocks;
+pub mod performance;
+pub mod position_history;
+pub mod price;
+pub mod provenance;
+pub mod saved_data;
+pub mod stablecoin;
+pub mod tax_report;
 pub mod trade;
+pub mod transfer;
 
 const YEAR_IN_MILLISECONDS: u64 = 31536000000;
 const QUARTER_IN_MILLISECONDS: u64 = 7776000000;
@@ -11,3 +11,12 @@ pub enum Method {
     LTFO = "LTFO",
     HTFO = "HTFO",
 }
+
+pub const ALL_METHODS: [Method; 6] = [
+    Method::FIFO,
+    Method::LIFO,
+    Method::HCFO,
+    Method::LCFO,
+    Method::LTFO,
+    Method::HTFO,
+];
@@ -38,11 +38,15 @@ pub fn mock_currency_holdings(
 ) -> Vec<holding::CurrencyHolding> {
     let mut currency_holdings: Vec<holding::CurrencyHolding> = vec![];
     for _ in 0..amount {
+        let id = rand_string();
         currency_holdings.push(holding::CurrencyHolding {
             amount: rand_decimal(),
             rate_in_fiat: rand_decimal(),
             date: date_in_range(starting_date, ending_date),
             location: rand_string(),
+            id: id.clone(),
+            acquisition_id: id,
+            parent_ids: vec![],
         });
     }
 
@@ -124,9 +128,42 @@ pub fn mock_trades(
                 date_acquired: None,
                 cost_basis: None,
                 long_term_trade: None,
+                lot_id: None,
+                acquisition_id: None,
             });
         }
     }
 
     trades
 }
+
+// a trade with no fee and no fiat rate, tests fill in whatever else they need
+pub fn trade(
+    id: &str,
+    sold_currency: &str,
+    bought_currency: &str,
+    amount_sold: Decimal,
+    rate: Decimal,
+    date: u64,
+) -> trade::Trade {
+    trade::Trade {
+        bought_currency: bought_currency.to_string(),
+        sold_currency: sold_currency.to_string(),
+        amount_sold,
+        rate,
+        date,
+        exchange_id: id.to_string(),
+        exchange: "".to_string(),
+        id: id.to_string(),
+        transaction_fee: Zero::zero(),
+        transaction_fee_currency: sold_currency.to_string(),
+        fiat_rate: None,
+        short_term: None,
+        long_term: None,
+        date_acquired: None,
+        cost_basis: None,
+        long_term_trade: None,
+        lot_id: None,
+        acquisition_id: None,
+    }
+}
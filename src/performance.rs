@@ -0,0 +1,521 @@
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::price::{PriceSource, PriceTable};
+use crate::trade::Trade;
+use crate::MIN_HOLDING_SIZE;
+use rust_decimal::prelude::{Decimal, FromPrimitive, One, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+const DAY_IN_MILLISECONDS: f64 = 86400000.0;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PerformanceMetrics {
+    #[serde(rename = "startingValue")]
+    pub starting_value: Decimal,
+    #[serde(rename = "endingValue")]
+    pub ending_value: Decimal,
+    #[serde(rename = "netInvested")]
+    pub net_invested: Decimal,
+    #[serde(rename = "timeWeightedReturn")]
+    pub time_weighted_return: Option<Decimal>,
+    #[serde(rename = "moneyWeightedReturn")]
+    pub money_weighted_return: Option<Decimal>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct MissingPrice {
+    pub currency: String,
+    pub date: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Performance {
+    pub total: PerformanceMetrics,
+    pub currencies: HashMap<String, PerformanceMetrics>,
+    #[serde(rename = "missingPrices")]
+    pub missing_prices: Vec<MissingPrice>,
+}
+
+struct Movement {
+    date: u64,
+    currency: String,
+    amount: Decimal,
+    // fiat moved into the currency, negative when moved out
+    flow: Decimal,
+    // money entering or leaving the portfolio as a whole rather than moving between currencies
+    external: bool,
+}
+
+#[wasm_bindgen]
+pub fn calculate_performance_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+    prices: &JsValue,
+    start: u64,
+    end: u64,
+) -> JsValue {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    let prices: PriceTable = prices.into_serde().unwrap();
+
+    JsValue::from_serde(&calculate_performance(
+        holdings,
+        trades,
+        incomes,
+        fiat_currency,
+        &prices,
+        start,
+        end,
+    ))
+    .unwrap()
+}
+
+pub fn calculate_performance(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+    prices: &impl PriceSource,
+    start: u64,
+    end: u64,
+) -> Performance {
+    let mut starting_amounts: HashMap<String, Decimal> = HashMap::new();
+    for (currency, currency_holdings) in holdings.0 {
+        if currency != fiat_currency {
+            let amount = starting_amounts.entry(currency).or_default();
+            for currency_holding in currency_holdings {
+                *amount += currency_holding.amount;
+            }
+        }
+    }
+
+    let mut movements: Vec<Movement> = vec![];
+    for movement in self::movements(trades, incomes, &fiat_currency) {
+        if movement.date <= start {
+            *starting_amounts.entry(movement.currency).or_default() += movement.amount;
+        } else if movement.date <= end {
+            movements.push(movement);
+        }
+    }
+
+    let mut currencies: Vec<String> = starting_amounts.keys().cloned().collect();
+    for movement in movements.iter() {
+        if !currencies.contains(&movement.currency) {
+            currencies.push(movement.currency.clone());
+        }
+    }
+
+    let mut missing_prices: Vec<MissingPrice> = vec![];
+    let mut valuation = Valuation {
+        prices,
+        fiat_currency: &fiat_currency,
+        missing_prices: &mut missing_prices,
+    };
+
+    let total = valuation.metrics(&starting_amounts, &movements, None, start, end);
+    let currencies = currencies
+        .into_iter()
+        .map(|currency| {
+            let metrics =
+                valuation.metrics(&starting_amounts, &movements, Some(&currency), start, end);
+            (currency, metrics)
+        })
+        .collect();
+
+    Performance {
+        total,
+        currencies,
+        missing_prices,
+    }
+}
+
+struct Valuation<'a, P: PriceSource> {
+    prices: &'a P,
+    fiat_currency: &'a str,
+    missing_prices: &'a mut Vec<MissingPrice>,
+}
+
+impl<'a, P: PriceSource> Valuation<'a, P> {
+    fn value(&mut self, amounts: &HashMap<String, Decimal>, date: u64) -> Decimal {
+        let mut value = Zero::zero();
+
+        for (currency, amount) in amounts.iter() {
+            if amount.abs() < MIN_HOLDING_SIZE {
+                continue;
+            }
+
+            match self.prices.rate(currency, self.fiat_currency, date) {
+                Some(rate) => value += *amount * rate,
+                None => {
+                    let missing_price = MissingPrice {
+                        currency: currency.clone(),
+                        date,
+                    };
+                    if !self.missing_prices.contains(&missing_price) {
+                        self.missing_prices.push(missing_price);
+                    }
+                }
+            }
+        }
+
+        value
+    }
+
+    fn metrics(
+        &mut self,
+        starting_amounts: &HashMap<String, Decimal>,
+        movements: &[Movement],
+        currency: Option<&String>,
+        start: u64,
+        end: u64,
+    ) -> PerformanceMetrics {
+        let mut amounts: HashMap<String, Decimal> = starting_amounts
+            .iter()
+            .filter(|(amount_currency, _)| currency.is_none() || currency == Some(*amount_currency))
+            .map(|(amount_currency, amount)| (amount_currency.clone(), *amount))
+            .collect();
+
+        let starting_value = self.value(&amounts, start);
+        let mut previous_value = starting_value;
+        let mut time_weighted_return: Option<Decimal> = None;
+        let mut net_invested = Zero::zero();
+        let mut cash_flows: Vec<(u64, Decimal)> = vec![(start, -starting_value)];
+
+        for movement in movements {
+            if currency.is_some() && currency != Some(&movement.currency) {
+                continue;
+            }
+
+            let flow = if currency.is_some() || movement.external {
+                movement.flow
+            } else {
+                Zero::zero()
+            };
+
+            if flow.is_zero() {
+                *amounts.entry(movement.currency.clone()).or_default() += movement.amount;
+                continue;
+            }
+
+            // each cash flow starts a new sub period
+            let value_before = self.value(&amounts, movement.date);
+            if previous_value > Zero::zero() {
+                time_weighted_return = Some(
+                    time_weighted_return.unwrap_or(Decimal::one()) * value_before / previous_value,
+                );
+            }
+
+            *amounts.entry(movement.currency.clone()).or_default() += movement.amount;
+            previous_value = self.value(&amounts, movement.date);
+            net_invested += flow;
+            cash_flows.push((movement.date, -flow));
+        }
+
+        let ending_value = self.value(&amounts, end);
+        if previous_value > Zero::zero() {
+            time_weighted_return = Some(
+                time_weighted_return.unwrap_or(Decimal::one()) * ending_value / previous_value,
+            );
+        }
+        cash_flows.push((end, ending_value));
+
+        PerformanceMetrics {
+            starting_value,
+            ending_value,
+            net_invested,
+            time_weighted_return: time_weighted_return.map(|value| value - Decimal::one()),
+            money_weighted_return: xirr(&cash_flows),
+        }
+    }
+}
+
+fn net_present_value(cash_flows: &[(u64, f64)], rate: f64) -> f64 {
+    let first_date = cash_flows[0].0;
+    cash_flows
+        .iter()
+        .map(|(date, amount)| {
+            let years = (*date - first_date) as f64 / DAY_IN_MILLISECONDS / 365.0;
+            amount / (1.0 + rate).powf(years)
+        })
+        .sum()
+}
+
+// annualised rate where the cash flows net to zero, found by bisection
+fn xirr(cash_flows: &[(u64, Decimal)]) -> Option<Decimal> {
+    let cash_flows: Vec<(u64, f64)> = cash_flows
+        .iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(date, amount)| (*date, amount.to_f64().unwrap_or(0.0)))
+        .collect();
+
+    if !cash_flows.iter().any(|(_, amount)| *amount > 0.0)
+        || !cash_flows.iter().any(|(_, amount)| *amount < 0.0)
+    {
+        return None;
+    }
+
+    let mut low = -0.999999;
+    let mut high = 1.0;
+    while net_present_value(&cash_flows, low).signum()
+        == net_present_value(&cash_flows, high).signum()
+    {
+        high *= 10.0;
+        if high > 1e9 {
+            return None;
+        }
+    }
+
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        if net_present_value(&cash_flows, middle).signum()
+            == net_present_value(&cash_flows, low).signum()
+        {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    Decimal::from_f64((low + high) / 2.0).map(|rate| rate.round_dp(8))
+}
+
+fn movements(trades: Vec<Trade>, incomes: Vec<Income>, fiat_currency: &str) -> Vec<Movement> {
+    let mut movements: Vec<Movement> = vec![];
+
+    for income in incomes {
+        movements.push(Movement {
+            date: income.date,
+            currency: income.currency,
+            amount: income.amount,
+            flow: Zero::zero(),
+            external: false,
+        });
+    }
+
+    for trade in trades {
+        // handle this better somewhere else
+        if trade.amount_sold <= Zero::zero() {
+            continue;
+        }
+
+        let (amount_bought, _) = trade.amount_bought(fiat_currency);
+        if trade.sold_currency == fiat_currency {
+            movements.push(Movement {
+                date: trade.date,
+                currency: trade.bought_currency.clone(),
+                amount: amount_bought,
+                flow: trade.amount_sold,
+                external: true,
+            });
+            continue;
+        }
+
+        if trade.bought_currency == fiat_currency {
+            movements.push(Movement {
+                date: trade.date,
+                currency: trade.sold_currency.clone(),
+                amount: -trade.amount_sold,
+                flow: -amount_bought,
+                external: true,
+            });
+            continue;
+        }
+
+        let value = trade.amount_sold * trade.fiat_rate();
+        movements.push(Movement {
+            date: trade.date,
+            currency: trade.sold_currency.clone(),
+            amount: -trade.amount_sold,
+            flow: -value,
+            external: false,
+        });
+        movements.push(Movement {
+            date: trade.date,
+            currency: trade.bought_currency.clone(),
+            amount: amount_bought,
+            flow: value,
+            external: false,
+        });
+    }
+
+    movements.sort_by_key(|movement| movement.date);
+    movements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::calculate_performance;
+    use crate::holding::Holdings;
+    use crate::mocks;
+    use crate::price::PriceTable;
+    use crate::trade::Trade;
+    use crate::YEAR_IN_MILLISECONDS;
+    use rust_decimal::prelude::{Decimal, Zero};
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+    const START_DATE: u64 = 1577836800000;
+    const HALF_YEAR: u64 = YEAR_IN_MILLISECONDS / 2;
+
+    fn trade(
+        sold: &str,
+        bought: &str,
+        amount_sold: Decimal,
+        rate: Decimal,
+        fiat_rate: Decimal,
+        date: u64,
+    ) -> Trade {
+        let holdings = mocks::mock_holdings(1, 1, None, None);
+        let mut trade = mocks::mock_trades(1, START_DATE, holdings, false).remove(0);
+        trade.sold_currency = sold.to_string();
+        trade.bought_currency = bought.to_string();
+        trade.amount_sold = amount_sold;
+        trade.rate = rate;
+        trade.fiat_rate = Some(fiat_rate);
+        trade.transaction_fee = Zero::zero();
+        trade.date = date;
+        trade
+    }
+
+    #[test]
+    fn single_purchase_returns() {
+        let mut prices = PriceTable::default();
+        prices.add_price("BTC", FIAT_CURRENCY, START_DATE, dec!(100));
+        prices.add_price(
+            "BTC",
+            FIAT_CURRENCY,
+            START_DATE + YEAR_IN_MILLISECONDS,
+            dec!(150),
+        );
+
+        let performance = calculate_performance(
+            Holdings::default(),
+            vec![trade(
+                FIAT_CURRENCY,
+                "BTC",
+                dec!(100),
+                dec!(100),
+                dec!(100),
+                START_DATE,
+            )],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            &prices,
+            START_DATE - 1,
+            START_DATE + YEAR_IN_MILLISECONDS,
+        );
+
+        assert!(performance.missing_prices.is_empty());
+        assert_eq!(performance.total.net_invested, dec!(100));
+        assert_eq!(performance.total.ending_value, dec!(150));
+        assert_eq!(performance.total.time_weighted_return, Some(dec!(0.5)));
+        assert_eq!(
+            performance.total.money_weighted_return.unwrap().round_dp(4),
+            dec!(0.5)
+        );
+        assert_eq!(
+            performance.currencies.get("BTC").unwrap(),
+            &performance.total
+        );
+    }
+
+    #[test]
+    fn time_weighted_return_ignores_contribution_timing() {
+        let prices = |_: &str, _: &str, date: u64| {
+            if date < START_DATE + HALF_YEAR {
+                Some(dec!(100))
+            } else if date < START_DATE + YEAR_IN_MILLISECONDS {
+                Some(dec!(200))
+            } else {
+                Some(dec!(100))
+            }
+        };
+
+        let performance = calculate_performance(
+            Holdings::default(),
+            vec![
+                trade(
+                    FIAT_CURRENCY,
+                    "BTC",
+                    dec!(100),
+                    dec!(100),
+                    dec!(100),
+                    START_DATE,
+                ),
+                trade(
+                    FIAT_CURRENCY,
+                    "BTC",
+                    dec!(1000),
+                    dec!(200),
+                    dec!(200),
+                    START_DATE + HALF_YEAR,
+                ),
+            ],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            &prices,
+            START_DATE - 1,
+            START_DATE + YEAR_IN_MILLISECONDS,
+        );
+
+        // doubled then halved
+        assert_eq!(
+            performance.total.time_weighted_return,
+            Some(Decimal::zero())
+        );
+        assert_eq!(performance.total.net_invested, dec!(1100));
+        assert_eq!(performance.total.ending_value, dec!(600));
+        assert!(performance.total.money_weighted_return.unwrap() < Zero::zero());
+    }
+
+    #[test]
+    fn trades_between_currencies_are_not_invested() {
+        let prices = |currency: &str, _: &str, _: u64| match currency {
+            "BTC" => Some(dec!(100)),
+            _ => Some(dec!(10)),
+        };
+
+        let performance = calculate_performance(
+            Holdings::default(),
+            vec![
+                trade(
+                    FIAT_CURRENCY,
+                    "BTC",
+                    dec!(100),
+                    dec!(100),
+                    dec!(100),
+                    START_DATE,
+                ),
+                trade(
+                    "BTC",
+                    "ETH",
+                    dec!(0.5),
+                    dec!(0.1),
+                    dec!(100),
+                    START_DATE + 10,
+                ),
+            ],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            &prices,
+            START_DATE - 1,
+            START_DATE + 20,
+        );
+
+        assert_eq!(performance.total.net_invested, dec!(100));
+        assert_eq!(
+            performance.currencies.get("BTC").unwrap().net_invested,
+            dec!(50)
+        );
+        assert_eq!(
+            performance.currencies.get("ETH").unwrap().net_invested,
+            dec!(50)
+        );
+        assert_eq!(performance.total.ending_value, dec!(100));
+    }
+}
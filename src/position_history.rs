@@ -0,0 +1,309 @@
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
+use crate::holding::{CurrencyHolding, Holdings};
+use crate::income::Income;
+use crate::method::Method;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PositionRow {
+    pub date: u64,
+    #[serde(rename = "transactionID")]
+    pub transaction_id: String,
+    pub amount: Decimal,
+    #[serde(rename = "costBasis")]
+    pub cost_basis: Decimal,
+    #[serde(rename = "averagePrice")]
+    pub average_price: Decimal,
+    #[serde(rename = "realizedGain")]
+    pub realized_gain: Decimal,
+    #[serde(rename = "openLots")]
+    pub open_lots: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PositionHistory(pub HashMap<String, Vec<PositionRow>>);
+
+// running totals of what is held in a currency so rows don't have to add up every lot again
+#[derive(Clone, Debug, Default)]
+struct Position {
+    amount: Decimal,
+    cost_basis: Decimal,
+}
+
+impl Position {
+    fn add(&mut self, currency_holding: &CurrencyHolding) {
+        self.amount += currency_holding.amount;
+        self.cost_basis += currency_holding.amount * currency_holding.rate_in_fiat;
+    }
+
+    fn remove(&mut self, currency_holding: &CurrencyHolding) {
+        self.amount -= currency_holding.amount;
+        self.cost_basis -= currency_holding.amount * currency_holding.rate_in_fiat;
+    }
+}
+
+impl PositionHistory {
+    fn record(
+        &mut self,
+        position: &Position,
+        open_lots: usize,
+        currency: &str,
+        date: u64,
+        transaction_id: &str,
+        realized_gain: Decimal,
+    ) {
+        let rows = self.0.entry(currency.to_owned()).or_default();
+        let previous_realized_gain = rows
+            .last()
+            .map(|row| row.realized_gain)
+            .unwrap_or_else(Zero::zero);
+
+        rows.push(PositionRow {
+            date,
+            transaction_id: transaction_id.to_owned(),
+            amount: position.amount,
+            cost_basis: position.cost_basis,
+            average_price: if position.amount.is_zero() {
+                Zero::zero()
+            } else {
+                position.cost_basis / position.amount
+            },
+            realized_gain: previous_realized_gain + realized_gain,
+            open_lots,
+        });
+    }
+}
+
+fn apply_income(
+    lots: &mut LotStore,
+    positions: &mut HashMap<String, Position>,
+    income: Income,
+    history: &mut PositionHistory,
+) {
+    lots.add_income(&income);
+    let position = positions.entry(income.currency.clone()).or_default();
+    if let Some(currency_holding) = lots.lots(&income.currency).next_back() {
+        position.add(currency_holding);
+    }
+    history.record(
+        position,
+        lots.count(&income.currency),
+        &income.currency,
+        income.date,
+        &income.id,
+        Zero::zero(),
+    );
+}
+
+#[wasm_bindgen]
+pub fn calculate_position_history_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+    method: Method,
+) -> JsValue {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+
+    JsValue::from_serde(&calculate_position_history(
+        holdings,
+        trades,
+        incomes,
+        fiat_currency,
+        method,
+    ))
+    .unwrap()
+}
+
+pub fn calculate_position_history(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+    method: Method,
+) -> PositionHistory {
+    let mut history = PositionHistory::default();
+    let mut positions: HashMap<String, Position> = HashMap::new();
+    for (currency, currency_holdings) in holdings.0.iter() {
+        let position = positions.entry(currency.clone()).or_default();
+        for currency_holding in currency_holdings {
+            position.add(currency_holding);
+        }
+    }
+    let mut lots = LotStore::from(holdings);
+
+    for event in events(trades, incomes) {
+        let trade = match event {
+            Event::Income(income) => {
+                apply_income(&mut lots, &mut positions, income, &mut history);
+                continue;
+            }
+            Event::Trade(trade) => trade,
+        };
+
+        // handle this better somewhere else
+        if trade.amount_sold > Zero::zero() {
+            let result = lots.process_trade(&trade, &fiat_currency, method);
+
+            // the placeholder for an amount which was never held was not part of the position
+            let unmatched_id = format!("{}/unmatched", trade.id);
+            let sold_position = positions.entry(trade.sold_currency.clone()).or_default();
+            for currency_holding in result.deducted_holdings.iter() {
+                if currency_holding.id != unmatched_id {
+                    sold_position.remove(currency_holding);
+                }
+            }
+            if trade.sold_currency != fiat_currency {
+                history.record(
+                    sold_position,
+                    lots.count(&trade.sold_currency),
+                    &trade.sold_currency,
+                    trade.date,
+                    &trade.id,
+                    result.short_term_gain + result.long_term_gain,
+                );
+            }
+            let bought_position = positions.entry(trade.bought_currency.clone()).or_default();
+            if let Some(currency_holding) = result.added_holding.as_ref() {
+                bought_position.add(currency_holding);
+            }
+            if trade.bought_currency != fiat_currency {
+                history.record(
+                    bought_position,
+                    lots.count(&trade.bought_currency),
+                    &trade.bought_currency,
+                    trade.date,
+                    &trade.id,
+                    Zero::zero(),
+                );
+            }
+
+            if !result.fee_holdings.is_empty() {
+                let fee_position = positions
+                    .entry(trade.transaction_fee_currency.clone())
+                    .or_default();
+                for currency_holding in result.fee_holdings.iter() {
+                    fee_position.remove(currency_holding);
+                }
+                history.record(
+                    fee_position,
+                    lots.count(&trade.transaction_fee_currency),
+                    &trade.transaction_fee_currency,
+                    trade.date,
+                    &trade.id,
+                    Zero::zero(),
+                );
+            }
+        }
+    }
+
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::calculate_position_history;
+    use crate::calculate_gains::calculate_gains;
+    use crate::income::Income;
+    use crate::method::Method;
+    use crate::mocks;
+    use rust_decimal::prelude::{Decimal, Zero};
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "FAKE";
+
+    #[test]
+    fn position_history_follows_holdings() {
+        let holdings = mocks::mock_holdings(1, 5, None, None);
+        let currency = holdings.0.keys().collect::<Vec<&String>>()[0].clone();
+        let mut trades = mocks::mock_trades(3, mocks::now_u64(), holdings.clone(), false);
+        trades.sort_by_key(|trade| trade.date);
+        for trade in trades.iter_mut() {
+            trade.bought_currency = FIAT_CURRENCY.to_string();
+        }
+
+        let history = calculate_position_history(
+            holdings.clone(),
+            trades.clone(),
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+        let gains = calculate_gains(
+            holdings,
+            trades.clone(),
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+
+        let rows = history.0.get(&currency).expect("missing currency history");
+        assert_eq!(rows.len(), trades.len());
+        assert!(!history.0.contains_key(FIAT_CURRENCY));
+
+        let last_row = rows.last().unwrap();
+        let currency_holdings = gains.new_holdings.0.get(&currency).unwrap();
+        let amount: Decimal = currency_holdings
+            .iter()
+            .fold(Zero::zero(), |acc, item| acc + item.amount);
+        assert_eq!(last_row.amount, amount);
+        let cost_basis: Decimal = currency_holdings.iter().fold(Zero::zero(), |acc, item| {
+            acc + item.amount * item.rate_in_fiat
+        });
+        // the running total only differs by rounding in the last digit
+        assert!((last_row.cost_basis - cost_basis).abs() < dec!(0.000001));
+        assert_eq!(last_row.open_lots, currency_holdings.len());
+        assert_eq!(
+            last_row.realized_gain,
+            gains.short_term_gain + gains.long_term_gain
+        );
+    }
+
+    #[test]
+    fn position_history_includes_incomes() {
+        let holdings = mocks::mock_holdings(0, 0, None, None);
+        let incomes = vec![
+            Income {
+                amount: dec!(2),
+                currency: "BTC".to_string(),
+                transaction_id: None,
+                id: "first".to_string(),
+                fee: None,
+                date: 1000,
+                fiat_rate: Some(dec!(10)),
+            },
+            Income {
+                amount: dec!(2),
+                currency: "BTC".to_string(),
+                transaction_id: None,
+                id: "second".to_string(),
+                fee: None,
+                date: 2000,
+                fiat_rate: Some(dec!(20)),
+            },
+        ];
+
+        let history = calculate_position_history(
+            holdings,
+            vec![],
+            incomes,
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+
+        let rows = history.0.get("BTC").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].transaction_id, "second");
+        assert_eq!(rows[1].amount, dec!(4));
+        assert_eq!(rows[1].cost_basis, dec!(60));
+        assert_eq!(rows[1].average_price, dec!(15));
+        assert_eq!(rows[1].open_lots, 2);
+    }
+}
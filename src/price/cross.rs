@@ -0,0 +1,222 @@
+use crate::price::{PriceSource, PriceTable};
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, One, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+const WEEK_IN_MILLISECONDS: u64 = 7 * 24 * 60 * 60 * 1000;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PricePath {
+    // from the priced currency through each intermediate to the fiat currency
+    pub currencies: Vec<String>,
+    pub rate: Decimal,
+    // where each step along the path was priced
+    pub sources: Vec<String>,
+}
+
+impl PricePath {
+    pub fn describe(&self) -> String {
+        format!(
+            "{} ({})",
+            self.currencies.join(" -> "),
+            self.sources.join(", ")
+        )
+    }
+}
+
+// prices currencies without a fiat price by chaining through the ones which have one
+pub struct CrossRates<P: PriceSource> {
+    pub prices: P,
+    // tried in order so the most liquid go first
+    pub intermediates: Vec<String>,
+    pub max_hops: usize,
+    // rates implied by trades older than this are treated as missing
+    pub max_age: u64,
+    implied: PriceTable,
+    traded_with: HashMap<String, Vec<String>>,
+}
+
+impl<P: PriceSource> CrossRates<P> {
+    pub fn new(prices: P, intermediates: Vec<String>) -> CrossRates<P> {
+        CrossRates {
+            prices,
+            intermediates,
+            max_hops: 3,
+            max_age: WEEK_IN_MILLISECONDS,
+            implied: PriceTable::default(),
+            traded_with: HashMap::new(),
+        }
+    }
+
+    fn add_implied(&mut self, currency: &str, quote_currency: &str, date: u64, rate: Decimal) {
+        self.implied.add_price(currency, quote_currency, date, rate);
+        let traded_with = self.traded_with.entry(currency.to_owned()).or_default();
+        if !traded_with.iter().any(|traded| traded == quote_currency) {
+            traded_with.push(quote_currency.to_owned());
+        }
+    }
+
+    // the rates trades were made at price each side in the other one
+    pub fn add_trades(&mut self, trades: &[Trade], fiat_currency: &str) {
+        for trade in trades.iter().filter(|trade| !trade.rate.is_zero()) {
+            let date = trade.date;
+            self.add_implied(
+                &trade.bought_currency,
+                &trade.sold_currency,
+                date,
+                trade.rate,
+            );
+            self.add_implied(
+                &trade.sold_currency,
+                &trade.bought_currency,
+                date,
+                Decimal::one() / trade.rate,
+            );
+
+            if let Some(fiat_rate) = trade.fiat_rate {
+                if trade.sold_currency != fiat_currency && trade.bought_currency != fiat_currency {
+                    self.add_implied(&trade.sold_currency, fiat_currency, date, fiat_rate);
+                    self.add_implied(
+                        &trade.bought_currency,
+                        fiat_currency,
+                        date,
+                        fiat_rate * trade.rate,
+                    );
+                }
+            }
+        }
+    }
+
+    fn step(&self, currency: &str, quote_currency: &str, date: u64) -> Option<(Decimal, String)> {
+        self.prices
+            .rate(currency, quote_currency, date)
+            .map(|rate| (rate, self.prices.source(currency, quote_currency, date)))
+            .or_else(|| {
+                self.implied
+                    .rate_within(currency, quote_currency, date, self.max_age)
+                    .map(|rate| (rate, "trades".to_string()))
+            })
+    }
+
+    // breadth first so the path with the fewest steps wins
+    pub fn path(&self, currency: &str, fiat_currency: &str, date: u64) -> Option<PricePath> {
+        let mut visited: HashSet<String> = HashSet::new();
+        visited.insert(currency.to_owned());
+        let mut queue = VecDeque::new();
+        queue.push_back(PricePath {
+            currencies: vec![currency.to_owned()],
+            rate: Decimal::one(),
+            sources: vec![],
+        });
+
+        while let Some(path) = queue.pop_front() {
+            let last = path.currencies.last().unwrap().clone();
+            let candidates = std::iter::once(fiat_currency.to_owned())
+                .chain(self.intermediates.iter().cloned())
+                .chain(self.traded_with.get(&last).cloned().unwrap_or_default());
+
+            for next in candidates {
+                if visited.contains(&next) {
+                    continue;
+                }
+                let (rate, source) = match self.step(&last, &next, date) {
+                    Some(step) => step,
+                    None => continue,
+                };
+
+                let mut next_path = path.clone();
+                next_path.currencies.push(next.clone());
+                next_path.rate *= rate;
+                next_path.sources.push(source);
+                if next == fiat_currency {
+                    return Some(next_path);
+                }
+
+                visited.insert(next);
+                if next_path.sources.len() < self.max_hops {
+                    queue.push_back(next_path);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<P: PriceSource> PriceSource for CrossRates<P> {
+    fn rate(&self, currency: &str, fiat_currency: &str, date: u64) -> Option<Decimal> {
+        self.path(currency, fiat_currency, date)
+            .map(|path| path.rate)
+    }
+
+    fn name(&self) -> String {
+        "cross rates".to_string()
+    }
+
+    fn source(&self, currency: &str, fiat_currency: &str, date: u64) -> String {
+        self.path(currency, fiat_currency, date)
+            .map(|path| path.describe())
+            .unwrap_or_else(|| self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrossRates;
+    use crate::mocks;
+    use crate::price::{PriceSource, PriceTable};
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+
+    #[test]
+    fn chains_through_intermediates() {
+        let mut prices = PriceTable::default();
+        prices.add_price("BTC", FIAT_CURRENCY, 0, dec!(10000));
+        prices.add_price("ETH", FIAT_CURRENCY, 0, dec!(500));
+        prices.add_price("ALT", "ETH", 0, dec!(0.1));
+        prices.add_price("ALT", "BTC", 0, dec!(0.004));
+        prices.add_price("TINY", "ALT", 0, dec!(2));
+
+        let cross = CrossRates::new(prices, vec!["BTC".to_string(), "ETH".to_string()]);
+        let path = cross.path("ALT", FIAT_CURRENCY, 10).unwrap();
+        assert_eq!(path.currencies, vec!["ALT", "BTC", "USD"]);
+        assert_eq!(path.rate, dec!(40));
+
+        // ALT is not an intermediate so it can't be reached
+        assert_eq!(cross.rate("TINY", FIAT_CURRENCY, 10), None);
+        assert_eq!(cross.rate("BTC", FIAT_CURRENCY, 10), Some(dec!(10000)));
+
+        // steps say where the wrapped source got the rate from rather than just its name
+        let wrapped = CrossRates::new(cross, vec![]);
+        assert_eq!(
+            wrapped.source("ALT", FIAT_CURRENCY, 10),
+            "ALT -> USD (ALT -> BTC -> USD (price table, price table))"
+        );
+    }
+
+    #[test]
+    fn uses_rates_from_trades() {
+        let mut prices = PriceTable::default();
+        prices.add_price("ETH", FIAT_CURRENCY, 0, dec!(500));
+
+        let trade = mocks::trade("1", "ETH", "ALT", dec!(1), dec!(0.01), 100);
+        let mut cross = CrossRates::new(prices, vec![]);
+        cross.add_trades(&[trade], FIAT_CURRENCY);
+
+        assert_eq!(cross.rate("ALT", FIAT_CURRENCY, 99), None);
+        assert_eq!(cross.rate("ALT", FIAT_CURRENCY, 100), Some(dec!(5)));
+        assert_eq!(
+            cross.source("ALT", FIAT_CURRENCY, 100),
+            "ALT -> ETH -> USD (trades, price table)"
+        );
+
+        // a trade from long ago says little about what ALT is worth now
+        assert_eq!(
+            cross.rate("ALT", FIAT_CURRENCY, 100 + cross.max_age),
+            Some(dec!(5))
+        );
+        assert_eq!(cross.rate("ALT", FIAT_CURRENCY, 101 + cross.max_age), None);
+    }
+}
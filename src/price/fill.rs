@@ -0,0 +1,270 @@
+use crate::income::Income;
+use crate::price::PriceSource;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, One, Zero};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MissingRatePolicy {
+    Error = "Error",
+    Warn = "Warn",
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct FilledRate {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub currency: String,
+    pub date: u64,
+    pub rate: Decimal,
+    pub source: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct MissingRate {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub currency: String,
+    pub date: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct FilledRates {
+    pub trades: Vec<Trade>,
+    pub incomes: Vec<Income>,
+    pub filled: Vec<FilledRate>,
+    // only kept when the policy is to warn, these still have no rate
+    pub missing: Vec<MissingRate>,
+}
+
+// rate of one unit of the sold currency, or the bought currency when buying with fiat
+fn trade_rate(
+    trade: &Trade,
+    fiat_currency: &str,
+    prices: &impl PriceSource,
+) -> (String, Option<(Decimal, String)>) {
+    if trade.rate.is_zero() {
+        return (trade.sold_currency.clone(), None);
+    }
+
+    // trades against fiat already hold their own price
+    if trade.sold_currency == fiat_currency {
+        return (
+            trade.bought_currency.clone(),
+            Some((trade.rate, "trade".to_string())),
+        );
+    }
+    if trade.bought_currency == fiat_currency {
+        return (
+            trade.sold_currency.clone(),
+            Some((Decimal::one() / trade.rate, "trade".to_string())),
+        );
+    }
+
+    let rate = prices
+        .rate(&trade.sold_currency, fiat_currency, trade.date)
+        .map(|rate| {
+            (
+                rate,
+                prices.source(&trade.sold_currency, fiat_currency, trade.date),
+            )
+        })
+        .or_else(|| {
+            prices
+                .rate(&trade.bought_currency, fiat_currency, trade.date)
+                .map(|rate| {
+                    (
+                        rate / trade.rate,
+                        format!(
+                            "{} via {}",
+                            prices.source(&trade.bought_currency, fiat_currency, trade.date),
+                            trade.bought_currency
+                        ),
+                    )
+                })
+        });
+    (trade.sold_currency.clone(), rate)
+}
+
+pub fn fill_missing_rates(
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: &str,
+    prices: &impl PriceSource,
+    policy: MissingRatePolicy,
+) -> Result<FilledRates, Vec<MissingRate>> {
+    let mut result = FilledRates {
+        trades,
+        incomes,
+        ..Default::default()
+    };
+
+    for trade in result
+        .trades
+        .iter_mut()
+        .filter(|trade| trade.fiat_rate.is_none())
+    {
+        match trade_rate(trade, fiat_currency, prices) {
+            (currency, Some((rate, source))) => {
+                trade.fiat_rate = Some(rate);
+                result.filled.push(FilledRate {
+                    id: trade.id.clone(),
+                    currency,
+                    date: trade.date,
+                    rate,
+                    source,
+                });
+            }
+            (currency, None) => result.missing.push(MissingRate {
+                id: trade.id.clone(),
+                currency,
+                date: trade.date,
+            }),
+        }
+    }
+
+    for income in result
+        .incomes
+        .iter_mut()
+        .filter(|income| income.fiat_rate.is_none())
+    {
+        let rate = if income.currency == fiat_currency {
+            Some(Decimal::one())
+        } else {
+            prices.rate(&income.currency, fiat_currency, income.date)
+        };
+        match rate {
+            Some(rate) => {
+                income.fiat_rate = Some(rate);
+                result.filled.push(FilledRate {
+                    id: income.id.clone(),
+                    currency: income.currency.clone(),
+                    date: income.date,
+                    rate,
+                    source: prices.source(&income.currency, fiat_currency, income.date),
+                });
+            }
+            None => result.missing.push(MissingRate {
+                id: income.id.clone(),
+                currency: income.currency.clone(),
+                date: income.date,
+            }),
+        }
+    }
+
+    if policy == MissingRatePolicy::Error && !result.missing.is_empty() {
+        return Err(result.missing);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill_missing_rates, MissingRatePolicy};
+    use crate::income::Income;
+    use crate::mocks;
+    use crate::price::PriceTable;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::Decimal;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+
+    fn trade(id: &str, sold_currency: &str, bought_currency: &str, rate: Decimal) -> Trade {
+        Trade {
+            transaction_fee_currency: FIAT_CURRENCY.to_string(),
+            ..mocks::trade(id, sold_currency, bought_currency, dec!(1), rate, 1000)
+        }
+    }
+
+    fn income(currency: &str) -> Income {
+        Income {
+            amount: dec!(1),
+            currency: currency.to_string(),
+            transaction_id: None,
+            id: currency.to_string(),
+            fee: None,
+            date: 1000,
+            fiat_rate: None,
+        }
+    }
+
+    #[test]
+    fn fills_rates_and_records_sources() {
+        let mut prices = PriceTable::default();
+        prices.add_price("ETH", FIAT_CURRENCY, 0, dec!(500));
+        prices.add_price("LTC", FIAT_CURRENCY, 0, dec!(50));
+
+        let trades = vec![
+            trade("buy", FIAT_CURRENCY, "BTC", dec!(10000)),
+            trade("sell", "BTC", FIAT_CURRENCY, dec!(0.0001)),
+            trade("swap", "BTC", "ETH", dec!(0.05)),
+            trade("swap-back", "DOGE", "LTC", dec!(0.01)),
+        ];
+        let result = fill_missing_rates(
+            trades,
+            vec![income("ETH")],
+            FIAT_CURRENCY,
+            &prices,
+            MissingRatePolicy::Error,
+        )
+        .unwrap();
+
+        let rates: Vec<Decimal> = result
+            .trades
+            .iter()
+            .map(|trade| trade.fiat_rate.unwrap())
+            .collect();
+        assert_eq!(
+            rates,
+            vec![dec!(10000), dec!(10000), dec!(10000), dec!(5000)]
+        );
+        assert_eq!(result.incomes[0].fiat_rate, Some(dec!(500)));
+
+        let sources: Vec<&str> = result
+            .filled
+            .iter()
+            .map(|filled| filled.source.as_str())
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                "trade",
+                "trade",
+                "price table via ETH",
+                "price table via LTC",
+                "price table"
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_rates_follow_the_policy() {
+        let prices = PriceTable::default();
+        let trades = vec![trade("swap", "BTC", "ETH", dec!(20))];
+
+        let missing = fill_missing_rates(
+            trades.clone(),
+            vec![income("ETH")],
+            FIAT_CURRENCY,
+            &prices,
+            MissingRatePolicy::Error,
+        )
+        .unwrap_err();
+        assert_eq!(missing.len(), 2);
+
+        let result = fill_missing_rates(
+            trades,
+            vec![],
+            FIAT_CURRENCY,
+            &prices,
+            MissingRatePolicy::Warn,
+        )
+        .unwrap();
+        assert_eq!(result.missing[0].currency, "BTC");
+        assert_eq!(result.trades[0].fiat_rate, None);
+    }
+}
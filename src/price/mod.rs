@@ -0,0 +1,114 @@
+pub mod cross;
+pub mod fill;
+pub mod ohlc;
+pub mod sanity;
+
+use rust_decimal::prelude::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub trait PriceSource {
+    // rate of one unit of currency in fiat_currency at the given date
+    fn rate(&self, currency: &str, fiat_currency: &str, date: u64) -> Option<Decimal>;
+
+    // recorded next to every rate taken from this source
+    fn name(&self) -> String {
+        "price source".to_string()
+    }
+
+    // where a particular rate came from, sources which combine prices can say how
+    fn source(&self, _currency: &str, _fiat_currency: &str, _date: u64) -> String {
+        self.name()
+    }
+}
+
+impl<F> PriceSource for F
+where
+    F: Fn(&str, &str, u64) -> Option<Decimal>,
+{
+    fn rate(&self, currency: &str, fiat_currency: &str, date: u64) -> Option<Decimal> {
+        self(currency, fiat_currency, date)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PricePoint {
+    pub date: u64,
+    pub rate: Decimal,
+}
+
+// prices keyed by currency then fiat currency, each list sorted by date
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PriceTable(pub HashMap<String, HashMap<String, Vec<PricePoint>>>);
+
+impl PriceTable {
+    pub fn add_price(&mut self, currency: &str, fiat_currency: &str, date: u64, rate: Decimal) {
+        let prices = self
+            .0
+            .entry(currency.to_owned())
+            .or_default()
+            .entry(fiat_currency.to_owned())
+            .or_default();
+        let index = prices.partition_point(|price| price.date <= date);
+        prices.insert(index, PricePoint { date, rate });
+    }
+
+    fn latest(&self, currency: &str, fiat_currency: &str, date: u64) -> Option<&PricePoint> {
+        let prices = self.0.get(currency)?.get(fiat_currency)?;
+        let index = prices.partition_point(|price| price.date <= date);
+        if index == 0 {
+            None
+        } else {
+            Some(&prices[index - 1])
+        }
+    }
+
+    // latest known price unless it is older than max_age
+    pub fn rate_within(
+        &self,
+        currency: &str,
+        fiat_currency: &str,
+        date: u64,
+        max_age: u64,
+    ) -> Option<Decimal> {
+        self.latest(currency, fiat_currency, date)
+            .filter(|price| date - price.date <= max_age)
+            .map(|price| price.rate)
+    }
+}
+
+impl PriceSource for PriceTable {
+    // latest known price at or before the date
+    fn rate(&self, currency: &str, fiat_currency: &str, date: u64) -> Option<Decimal> {
+        self.latest(currency, fiat_currency, date)
+            .map(|price| price.rate)
+    }
+
+    fn name(&self) -> String {
+        "price table".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PriceSource, PriceTable};
+    use rust_decimal_macros::*;
+
+    #[test]
+    fn price_table_uses_latest_price() {
+        let mut prices = PriceTable::default();
+        prices.add_price("BTC", "USD", 2000, dec!(200));
+        prices.add_price("BTC", "USD", 1000, dec!(100));
+
+        assert_eq!(prices.rate("BTC", "USD", 999), None);
+        assert_eq!(prices.rate("BTC", "USD", 1000), Some(dec!(100)));
+        assert_eq!(prices.rate("BTC", "USD", 1999), Some(dec!(100)));
+        assert_eq!(prices.rate("BTC", "USD", 5000), Some(dec!(200)));
+        assert_eq!(prices.rate("BTC", "EUR", 5000), None);
+        assert_eq!(
+            prices.rate_within("BTC", "USD", 2999, 1000),
+            Some(dec!(200))
+        );
+        assert_eq!(prices.rate_within("BTC", "USD", 3001, 1000), None);
+    }
+}
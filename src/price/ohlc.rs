@@ -0,0 +1,375 @@
+use crate::import::{parse_date, parse_decimal, read_records, Columns, ImportError, ImportResult};
+use crate::price::PriceSource;
+use rust_decimal::prelude::{Decimal, ToPrimitive};
+use rust_decimal_macros::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+// timestamps below this are in seconds rather than milliseconds
+const SECONDS_CUTOFF: u64 = 100000000000;
+// a file with a single candle has no gaps to go on so it is taken as daily
+const DAY_IN_MILLISECONDS: u64 = 86400000;
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum OhlcPolicy {
+    Close = "Close",
+    Open = "Open",
+    Midpoint = "Midpoint",
+    Nearest = "Nearest",
+    Interpolate = "Interpolate",
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Candle {
+    // start of the period the candle covers
+    pub date: u64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+}
+
+// a candle along with how long it covers, files of daily and hourly candles can be mixed
+struct Period {
+    candle: Candle,
+    interval: u64,
+}
+
+struct Candles(Vec<Period>);
+
+impl Candles {
+    fn rate(&self, date: u64, policy: OhlcPolicy) -> Option<Decimal> {
+        let index = self.0.partition_point(|period| period.candle.date <= date);
+        if index == 0 {
+            return None;
+        }
+        let candle = &self.0[index - 1].candle;
+        let interval = self.0[index - 1].interval;
+        let elapsed = date - candle.date;
+        if elapsed >= interval {
+            return None;
+        }
+
+        Some(match policy {
+            OhlcPolicy::Close => candle.close,
+            OhlcPolicy::Open => candle.open,
+            OhlcPolicy::Midpoint => (candle.high + candle.low) / dec!(2),
+            OhlcPolicy::Nearest => {
+                if elapsed * 2 < interval {
+                    candle.open
+                } else {
+                    candle.close
+                }
+            }
+            OhlcPolicy::Interpolate => {
+                candle.open
+                    + (candle.close - candle.open) * Decimal::from(elapsed)
+                        / Decimal::from(interval)
+            }
+            _ => candle.close,
+        })
+    }
+}
+
+// offline prices loaded from daily or hourly candles so reports can be reproduced
+pub struct OhlcDatabase {
+    policy: OhlcPolicy,
+    pairs: HashMap<String, HashMap<String, Candles>>,
+    cache: RefCell<HashMap<(String, String, u64), Option<Decimal>>>,
+}
+
+impl OhlcDatabase {
+    pub fn new(policy: OhlcPolicy) -> OhlcDatabase {
+        OhlcDatabase {
+            policy,
+            pairs: HashMap::new(),
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn policy(&self) -> OhlcPolicy {
+        self.policy
+    }
+
+    // rates cached under the old policy would be wrong under the new one
+    pub fn set_policy(&mut self, policy: OhlcPolicy) {
+        self.policy = policy;
+        self.cache.borrow_mut().clear();
+    }
+
+    // candles are taken to be as long as the smallest gap between them
+    pub fn add_candles(&mut self, currency: &str, fiat_currency: &str, mut candles: Vec<Candle>) {
+        candles.sort_by_key(|candle| candle.date);
+        let interval = candles
+            .windows(2)
+            .map(|window| window[1].date - window[0].date)
+            .filter(|gap| *gap > 0)
+            .min()
+            .unwrap_or(DAY_IN_MILLISECONDS);
+        self.add_candles_with_interval(currency, fiat_currency, candles, interval);
+    }
+
+    pub fn add_candles_with_interval(
+        &mut self,
+        currency: &str,
+        fiat_currency: &str,
+        candles: Vec<Candle>,
+        interval: u64,
+    ) {
+        let pair = self
+            .pairs
+            .entry(currency.to_owned())
+            .or_default()
+            .entry(fiat_currency.to_owned())
+            .or_insert_with(|| Candles(vec![]));
+        pair.0.extend(
+            candles
+                .into_iter()
+                .map(|candle| Period { candle, interval }),
+        );
+        pair.0.sort_by_key(|period| period.candle.date);
+        pair.0.dedup_by_key(|period| period.candle.date);
+        self.cache.borrow_mut().clear();
+    }
+
+    // csv with a date or timestamp column along with open, high, low and close
+    pub fn load_csv(
+        &mut self,
+        currency: &str,
+        fiat_currency: &str,
+        data: &str,
+    ) -> Vec<ImportError> {
+        let mut candles = vec![];
+        let mut result = ImportResult::default();
+        read_records(data, b',', &mut result, |columns, _, _| {
+            candles.push(Candle {
+                date: parse_timestamp(column(columns, &["date", "time", "timestamp", "unix"])?)?,
+                open: parse_decimal(column(columns, &["open"])?)?,
+                high: parse_decimal(column(columns, &["high"])?)?,
+                low: parse_decimal(column(columns, &["low"])?)?,
+                close: parse_decimal(column(columns, &["close"])?)?,
+            });
+            Ok(())
+        });
+        self.add_candles(currency, fiat_currency, candles);
+        result.errors
+    }
+
+    // json as a list of candle objects or exchange style [time, open, high, low, close] lists
+    pub fn load_json(
+        &mut self,
+        currency: &str,
+        fiat_currency: &str,
+        data: &str,
+    ) -> Vec<ImportError> {
+        let mut errors = vec![];
+        let rows = match serde_json::from_str::<Value>(data) {
+            Ok(Value::Array(rows)) => rows,
+            Ok(_) => {
+                return vec![ImportError {
+                    row: 0,
+                    message: "expected a list of candles".to_string(),
+                }]
+            }
+            Err(error) => {
+                return vec![ImportError {
+                    row: error.line() as u64,
+                    message: error.to_string(),
+                }]
+            }
+        };
+
+        let mut candles = vec![];
+        for (index, row) in rows.iter().enumerate() {
+            match json_candle(row) {
+                Ok(candle) => candles.push(candle),
+                Err(message) => errors.push(ImportError {
+                    row: index as u64,
+                    message,
+                }),
+            }
+        }
+        self.add_candles(currency, fiat_currency, candles);
+        errors
+    }
+}
+
+impl PriceSource for OhlcDatabase {
+    fn rate(&self, currency: &str, fiat_currency: &str, date: u64) -> Option<Decimal> {
+        let key = (currency.to_owned(), fiat_currency.to_owned(), date);
+        if let Some(rate) = self.cache.borrow().get(&key) {
+            return *rate;
+        }
+
+        let rate = self
+            .pairs
+            .get(currency)
+            .and_then(|fiats| fiats.get(fiat_currency))
+            .and_then(|candles| candles.rate(date, self.policy));
+        self.cache.borrow_mut().insert(key, rate);
+        rate
+    }
+
+    fn name(&self) -> String {
+        format!("ohlc {:?}", self.policy).to_lowercase()
+    }
+}
+
+fn column<'a>(columns: &Columns<'a>, names: &[&str]) -> Result<&'a str, String> {
+    let header = columns
+        .header(|header| names.iter().any(|name| header.eq_ignore_ascii_case(name)))
+        .ok_or_else(|| format!("missing column \"{}\"", names.join("\" or \"")))?;
+    columns.get(header)
+}
+
+fn parse_timestamp(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    if let Ok(number) = parse_decimal(value) {
+        let number = number
+            .to_u64()
+            .ok_or_else(|| format!("invalid date \"{}\"", value))?;
+        return Ok(if number < SECONDS_CUTOFF {
+            number * 1000
+        } else {
+            number
+        });
+    }
+
+    ["rfc3339", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%d"]
+        .iter()
+        .find_map(|format| parse_date(value, format, None).ok())
+        .ok_or_else(|| format!("invalid date \"{}\"", value))
+}
+
+fn json_value(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Number(value) => Ok(value.to_string()),
+        _ => Err(format!("unexpected value {}", value)),
+    }
+}
+
+fn json_candle(row: &Value) -> Result<Candle, String> {
+    let fields: Vec<String> = match row {
+        Value::Array(values) if values.len() >= 5 => values[..5]
+            .iter()
+            .map(json_value)
+            .collect::<Result<_, _>>()?,
+        Value::Object(object) => {
+            let date = ["date", "time", "timestamp"]
+                .iter()
+                .find_map(|key| object.get(*key))
+                .ok_or_else(|| "missing date".to_string())?;
+            let mut fields = vec![json_value(date)?];
+            for key in ["open", "high", "low", "close"].iter() {
+                let value = object.get(*key).ok_or_else(|| format!("missing {}", key))?;
+                fields.push(json_value(value)?);
+            }
+            fields
+        }
+        _ => return Err("unable to read candle".to_string()),
+    };
+
+    Ok(Candle {
+        date: parse_timestamp(&fields[0])?,
+        open: parse_decimal(&fields[1])?,
+        high: parse_decimal(&fields[2])?,
+        low: parse_decimal(&fields[3])?,
+        close: parse_decimal(&fields[4])?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Candle, OhlcDatabase, OhlcPolicy};
+    use crate::price::PriceSource;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+
+    static DAILY: &str = "Date,Open,High,Low,Close,Volume
+2021-01-02,110,130,100,120,5
+2021-01-01,100,120,90,110,5
+";
+
+    #[test]
+    fn looks_up_daily_candles_by_policy() {
+        let mut prices = OhlcDatabase::new(OhlcPolicy::Close);
+        assert!(prices.load_csv("BTC", FIAT_CURRENCY, DAILY).is_empty());
+
+        // 2021-01-02 06:00
+        let date = 1609567200000;
+        assert_eq!(prices.rate("BTC", FIAT_CURRENCY, date), Some(dec!(120)));
+        prices.set_policy(OhlcPolicy::Open);
+        assert_eq!(prices.rate("BTC", FIAT_CURRENCY, date), Some(dec!(110)));
+        prices.set_policy(OhlcPolicy::Midpoint);
+        assert_eq!(prices.rate("BTC", FIAT_CURRENCY, date), Some(dec!(115)));
+        prices.set_policy(OhlcPolicy::Nearest);
+        assert_eq!(prices.rate("BTC", FIAT_CURRENCY, date), Some(dec!(110)));
+        prices.set_policy(OhlcPolicy::Interpolate);
+        assert_eq!(prices.policy(), OhlcPolicy::Interpolate);
+        assert_eq!(prices.rate("BTC", FIAT_CURRENCY, date), Some(dec!(112.5)));
+
+        // before the first and after the last candle
+        assert_eq!(prices.rate("BTC", FIAT_CURRENCY, 1609459199999), None);
+        assert_eq!(prices.rate("BTC", FIAT_CURRENCY, 1609632000000), None);
+        assert_eq!(prices.name(), "ohlc interpolate");
+    }
+
+    #[test]
+    fn loads_hourly_json() {
+        let mut prices = OhlcDatabase::new(OhlcPolicy::Close);
+        let data = r#"[
+            [1609459200000, "100", "101", "99", "100.5"],
+            {"time": 1609462800, "open": 100.5, "high": 102, "low": 100, "close": 101},
+            ["bad"]
+        ]"#;
+        let errors = prices.load_json("ETH", FIAT_CURRENCY, data);
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].row, 2);
+        assert_eq!(
+            prices.rate("ETH", FIAT_CURRENCY, 1609462800000 + 3599999),
+            Some(dec!(101))
+        );
+        assert_eq!(
+            prices.rate("ETH", FIAT_CURRENCY, 1609462800000 + 3600000),
+            None
+        );
+    }
+
+    #[test]
+    fn each_load_keeps_its_own_interval() {
+        let candle = |date: u64| Candle {
+            date,
+            open: dec!(100),
+            high: dec!(100),
+            low: dec!(100),
+            close: dec!(100),
+        };
+        let mut prices = OhlcDatabase::new(OhlcPolicy::Close);
+        prices.add_candles("BTC", FIAT_CURRENCY, vec![candle(1609459200000)]);
+        prices.add_candles(
+            "BTC",
+            FIAT_CURRENCY,
+            vec![candle(1609545600000), candle(1609549200000)],
+        );
+
+        // a single candle is taken as daily rather than lasting forever
+        assert!(prices
+            .rate("BTC", FIAT_CURRENCY, 1609459200000 + 86399999)
+            .is_some());
+        // the hourly candles loaded later don't shrink the daily one
+        assert!(prices
+            .rate("BTC", FIAT_CURRENCY, 1609459200000 + 7200000)
+            .is_some());
+        assert_eq!(
+            prices.rate("BTC", FIAT_CURRENCY, 1609549200000 + 3600000),
+            None
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use crate::price::{PriceSource, PriceTable};
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, FromPrimitive, One, Zero};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PriceField {
+    #[serde(rename = "rate")]
+    Rate,
+    #[serde(rename = "fiatRate")]
+    FiatRate,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PriceProblem {
+    // further from the price source than the threshold allows
+    Deviation,
+    // one over the value is close to the price source, the pair was read the wrong way around
+    Inverted,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PriceIssue {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub field: PriceField,
+    pub problem: PriceProblem,
+    pub value: Decimal,
+    pub expected: Decimal,
+    pub deviation: Decimal,
+    pub suggestion: Decimal,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct PriceReport {
+    pub issues: Vec<PriceIssue>,
+    // trades the price source had nothing to compare against
+    pub unchecked: Vec<String>,
+}
+
+#[wasm_bindgen]
+pub fn check_prices_wasm(
+    trades: &JsValue,
+    fiat_currency: String,
+    prices: &JsValue,
+    threshold: f64,
+) -> Result<JsValue, JsValue> {
+    let trades: Vec<Trade> = trades.into_serde().unwrap();
+    let prices: PriceTable = prices.into_serde().unwrap();
+    let threshold = Decimal::from_f64(threshold)
+        .ok_or_else(|| JsValue::from_str(&format!("invalid threshold {}", threshold)))?;
+    Ok(JsValue::from_serde(&check_prices(&trades, &fiat_currency, &prices, threshold)).unwrap())
+}
+
+#[wasm_bindgen]
+pub fn apply_price_corrections_wasm(trades: &JsValue, issues: &JsValue) -> JsValue {
+    let trades: Vec<Trade> = trades.into_serde().unwrap();
+    let issues: Vec<PriceIssue> = issues.into_serde().unwrap();
+    JsValue::from_serde(&apply_price_corrections(trades, &issues)).unwrap()
+}
+
+fn fiat_price(
+    currency: &str,
+    fiat_currency: &str,
+    date: u64,
+    prices: &impl PriceSource,
+) -> Option<Decimal> {
+    if currency == fiat_currency {
+        Some(Decimal::one())
+    } else {
+        prices.rate(currency, fiat_currency, date)
+    }
+}
+
+fn check(
+    id: &str,
+    field: PriceField,
+    value: Decimal,
+    expected: Decimal,
+    threshold: Decimal,
+) -> Option<PriceIssue> {
+    if expected.is_zero() {
+        return None;
+    }
+
+    // too far off to be represented is as far off as can be
+    let deviation = |value: Decimal| {
+        value
+            .checked_sub(expected)
+            .and_then(|difference| difference.checked_div(expected))
+            .map_or(Decimal::max_value(), |deviation| deviation.abs())
+    };
+    if deviation(value) <= threshold {
+        return None;
+    }
+
+    let inverse = Decimal::one().checked_div(value);
+    let inverted = inverse.is_some_and(|inverse| deviation(inverse) <= threshold);
+    Some(PriceIssue {
+        id: id.to_string(),
+        field,
+        problem: if inverted {
+            PriceProblem::Inverted
+        } else {
+            PriceProblem::Deviation
+        },
+        value,
+        expected,
+        deviation: deviation(value),
+        suggestion: match inverse {
+            Some(inverse) if inverted => inverse,
+            _ => expected,
+        },
+    })
+}
+
+// compares the rates trades were imported with against a price source
+pub fn check_prices(
+    trades: &[Trade],
+    fiat_currency: &str,
+    prices: &impl PriceSource,
+    threshold: Decimal,
+) -> PriceReport {
+    let mut report = PriceReport::default();
+
+    for trade in trades {
+        let sold_price = fiat_price(&trade.sold_currency, fiat_currency, trade.date, prices);
+        let bought_price = fiat_price(&trade.bought_currency, fiat_currency, trade.date, prices);
+        let mut checked = false;
+
+        // rate is how much was sold for one of what was bought
+        if let (Some(sold_price), Some(bought_price)) = (sold_price, bought_price) {
+            checked = true;
+            if let Some(expected) = bought_price.checked_div(sold_price) {
+                report.issues.extend(check(
+                    &trade.id,
+                    PriceField::Rate,
+                    trade.rate,
+                    expected,
+                    threshold,
+                ));
+            }
+        }
+
+        // buying with fiat prices what was bought, anything else prices what was sold
+        let fiat_price = if trade.sold_currency == fiat_currency {
+            bought_price
+        } else {
+            sold_price
+        };
+        if let (Some(fiat_rate), Some(expected)) = (trade.fiat_rate, fiat_price) {
+            checked = true;
+            report.issues.extend(check(
+                &trade.id,
+                PriceField::FiatRate,
+                fiat_rate,
+                expected,
+                threshold,
+            ));
+        }
+
+        if !checked {
+            report.unchecked.push(trade.id.clone());
+        }
+    }
+
+    report
+}
+
+pub fn apply_price_corrections(mut trades: Vec<Trade>, issues: &[PriceIssue]) -> Vec<Trade> {
+    for issue in issues {
+        for trade in trades.iter_mut().filter(|trade| trade.id == issue.id) {
+            match issue.field {
+                PriceField::Rate => trade.rate = issue.suggestion,
+                PriceField::FiatRate => trade.fiat_rate = Some(issue.suggestion),
+            }
+        }
+    }
+    trades
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_price_corrections, check_prices, PriceField, PriceProblem};
+    use crate::mocks;
+    use crate::price::PriceTable;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::Decimal;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+
+    fn trade(
+        id: &str,
+        sold_currency: &str,
+        bought_currency: &str,
+        rate: Decimal,
+        fiat_rate: Decimal,
+    ) -> Trade {
+        Trade {
+            fiat_rate: Some(fiat_rate),
+            ..mocks::trade(id, sold_currency, bought_currency, dec!(1), rate, 1000)
+        }
+    }
+
+    #[test]
+    fn flags_deviations_and_inverted_rates() {
+        let mut prices = PriceTable::default();
+        prices.add_price("BTC", FIAT_CURRENCY, 0, dec!(10000));
+        prices.add_price("ETH", FIAT_CURRENCY, 0, dec!(500));
+
+        let trades = vec![
+            trade("fine", FIAT_CURRENCY, "BTC", dec!(10100), dec!(10100)),
+            trade("inverted", "BTC", "ETH", dec!(20), dec!(10000)),
+            trade("wrong", "ETH", FIAT_CURRENCY, dec!(0.002), dec!(800)),
+            trade("unknown", "DOGE", "LTC", dec!(1), dec!(1)),
+        ];
+        let report = check_prices(&trades, FIAT_CURRENCY, &prices, dec!(0.05));
+
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(report.issues[0].id, "inverted");
+        assert_eq!(report.issues[0].field, PriceField::Rate);
+        assert_eq!(report.issues[0].problem, PriceProblem::Inverted);
+        assert_eq!(report.issues[0].suggestion, dec!(0.05));
+        assert_eq!(report.issues[1].id, "wrong");
+        assert_eq!(report.issues[1].field, PriceField::FiatRate);
+        assert_eq!(report.issues[1].problem, PriceProblem::Deviation);
+        assert_eq!(report.issues[1].suggestion, dec!(500));
+        assert_eq!(report.unchecked, vec!["unknown"]);
+
+        let corrected = apply_price_corrections(trades, &report.issues);
+        assert_eq!(corrected[1].rate, dec!(0.05));
+        assert_eq!(corrected[2].fiat_rate, Some(dec!(500)));
+    }
+
+    #[test]
+    fn rates_too_far_off_to_divide_are_flagged() {
+        let mut prices = PriceTable::default();
+        prices.add_price(
+            "SHIB",
+            FIAT_CURRENCY,
+            0,
+            dec!(0.0000000000000000000000000001),
+        );
+
+        let trades = vec![trade("huge", "SHIB", "BTC", dec!(1), Decimal::max_value())];
+        let report = check_prices(&trades, FIAT_CURRENCY, &prices, dec!(0.05));
+
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].field, PriceField::FiatRate);
+        assert_eq!(report.issues[0].problem, PriceProblem::Deviation);
+        assert_eq!(report.issues[0].deviation, Decimal::max_value());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
+use crate::holding::{CurrencyHolding, Holdings};
+use crate::income::Income;
+use crate::method::Method;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LotRecord {
+    pub currency: String,
+    pub lot: CurrencyHolding,
+    // trade ids which used up this lot or a piece of it
+    #[serde(rename = "disposedBy")]
+    pub disposed_by: Vec<String>,
+}
+
+impl LotRecord {
+    pub fn cost_basis(&self) -> Decimal {
+        self.lot.amount * self.lot.rate_in_fiat
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Provenance(pub HashMap<String, LotRecord>);
+
+impl Provenance {
+    pub fn record(&mut self, currency: &str, lot: &CurrencyHolding) {
+        self.0.entry(lot.id.clone()).or_insert_with(|| LotRecord {
+            currency: currency.to_owned(),
+            lot: lot.clone(),
+            disposed_by: vec![],
+        });
+    }
+
+    pub fn record_holdings(&mut self, holdings: &Holdings) {
+        for (currency, currency_holdings) in holdings.0.iter() {
+            for lot in currency_holdings {
+                self.record(currency, lot);
+            }
+        }
+    }
+
+    fn record_income(&mut self, lots: &LotStore, income: &Income) {
+        if let Some(lot) = lots.lots(&income.currency).next_back() {
+            self.record(&income.currency, lot);
+        }
+    }
+
+    pub fn record_disposal(&mut self, currency: &str, lot: &CurrencyHolding, trade_id: &str) {
+        self.record(currency, lot);
+        if let Some(record) = self.0.get_mut(&lot.id) {
+            record.disposed_by.push(trade_id.to_owned());
+        }
+    }
+
+    // walks parent ids from the given lot back to the lots it was originally split from
+    pub fn lineage(&self, lot_id: &str) -> Vec<&LotRecord> {
+        let mut lineage = vec![];
+        let mut to_visit = vec![lot_id.to_owned()];
+
+        while let Some(id) = to_visit.pop() {
+            if lineage
+                .iter()
+                .any(|record: &&LotRecord| record.lot.id == id)
+            {
+                continue;
+            }
+
+            if let Some(record) = self.0.get(&id) {
+                to_visit.extend(record.lot.parent_ids.iter().rev().cloned());
+                lineage.push(record);
+            }
+        }
+
+        lineage
+    }
+
+    // what the lots this one was split from cost when they were acquired, none when the lot was never recorded
+    pub fn acquisition_cost(&self, lot_id: &str) -> Option<Decimal> {
+        let lineage = self.lineage(lot_id);
+        if lineage.is_empty() {
+            return None;
+        }
+
+        Some(
+            lineage
+                .iter()
+                .filter(|record| record.lot.parent_ids.is_empty())
+                .fold(Zero::zero(), |acc: Decimal, record| {
+                    acc + record.cost_basis()
+                }),
+        )
+    }
+
+    pub fn origins(&self, lot_id: &str) -> Vec<String> {
+        let mut origins: Vec<String> = vec![];
+
+        for record in self.lineage(lot_id) {
+            if record.lot.parent_ids.is_empty() && !origins.contains(&record.lot.acquisition_id) {
+                origins.push(record.lot.acquisition_id.clone());
+            }
+        }
+
+        origins
+    }
+}
+
+#[wasm_bindgen]
+pub fn calculate_provenance_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+    method: Method,
+) -> JsValue {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+
+    JsValue::from_serde(&calculate_provenance(
+        holdings,
+        trades,
+        incomes,
+        fiat_currency,
+        method,
+    ))
+    .unwrap()
+}
+
+pub fn calculate_provenance(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+    method: Method,
+) -> Provenance {
+    let mut provenance = Provenance::default();
+    provenance.record_holdings(&holdings);
+
+    let mut lots = LotStore::from(holdings);
+
+    // new lots only come from incomes and the bought side of trades so they're recorded as they're added
+    for event in events(trades, incomes) {
+        match event {
+            Event::Income(income) => {
+                lots.add_income(&income);
+                provenance.record_income(&lots, &income);
+            }
+            Event::Trade(trade) => {
+                if trade.amount_sold > Zero::zero() {
+                    let result = lots.process_trade(&trade, &fiat_currency, method);
+                    for lot in result.deducted_holdings.iter() {
+                        provenance.record_disposal(&trade.sold_currency, lot, &trade.id);
+                    }
+                    for lot in result.fee_holdings.iter() {
+                        provenance.record_disposal(&trade.transaction_fee_currency, lot, &trade.id);
+                    }
+                    if let Some(lot) = result.added_holding.as_ref() {
+                        provenance.record(&trade.bought_currency, lot);
+                    }
+                }
+            }
+        }
+    }
+
+    provenance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::calculate_provenance;
+    use crate::method::Method;
+    use crate::mocks;
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "FAKE";
+
+    #[test]
+    fn disposal_traces_back_to_purchase() {
+        let holdings = mocks::mock_holdings(1, 1, None, None);
+        let currency = holdings.0.keys().collect::<Vec<&String>>()[0].clone();
+        let original_holding = holdings.0.get(&currency).unwrap()[0].clone();
+
+        let mut trades = mocks::mock_trades(2, mocks::now_u64(), holdings.clone(), false);
+        trades.sort_by_key(|trade| trade.date);
+        trades[0].amount_sold = original_holding.amount / dec!(4);
+        trades[1].amount_sold = original_holding.amount / dec!(4);
+
+        let provenance = calculate_provenance(
+            holdings,
+            trades.clone(),
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+
+        let lot_id = format!("{}/{}", original_holding.id, trades[1].id);
+        let lineage = provenance.lineage(&lot_id);
+        assert_eq!(lineage.len(), 2);
+        assert_eq!(lineage[0].disposed_by, vec![trades[1].id.clone()]);
+        assert_eq!(lineage[1].lot.id, original_holding.id);
+        assert_eq!(
+            provenance.origins(&lot_id),
+            vec![original_holding.acquisition_id]
+        );
+
+        let bought_lot = provenance
+            .0
+            .get(&trades[0].id)
+            .expect("bought lot not recorded");
+        assert_eq!(bought_lot.currency, trades[0].bought_currency);
+    }
+
+    #[test]
+    fn unmatched_disposal_traces_back_to_its_trade() {
+        let holdings = mocks::mock_holdings(1, 1, None, None);
+        let currency = holdings.0.keys().collect::<Vec<&String>>()[0].clone();
+        let original_holding = holdings.0.get(&currency).unwrap()[0].clone();
+
+        let mut trades = mocks::mock_trades(1, mocks::now_u64(), holdings.clone(), false);
+        trades[0].amount_sold = original_holding.amount * dec!(2);
+        trades[0].bought_currency = FIAT_CURRENCY.to_string();
+
+        let provenance = calculate_provenance(
+            holdings,
+            trades.clone(),
+            vec![],
+            FIAT_CURRENCY.to_string(),
+            Method::FIFO,
+        );
+
+        let lot_id = format!("{}/unmatched", trades[0].id);
+        assert_eq!(provenance.origins(&lot_id), vec![trades[0].id.clone()]);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::method::Method;
+use crate::trade::Trade;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Settings {
+    #[serde(rename = "fiatCurrency")]
+    pub fiat_currency: String,
+    #[serde(rename = "gainCalculationMethod")]
+    pub gain_calculation_method: Method,
+    #[serde(rename = "fiatRateMethod", alias = "fiatRate", default)]
+    pub fiat_rate_method: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            fiat_currency: "USD".to_string(),
+            gain_calculation_method: Method::FIFO,
+            fiat_rate_method: None,
+        }
+    }
+}
+
+// the data file written by the cryptotithe web app
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SavedData {
+    #[serde(rename = "savedDate", default)]
+    pub saved_date: Option<serde_json::Value>,
+    #[serde(default)]
+    pub version: Option<u32>,
+    #[serde(default)]
+    pub trades: Vec<Trade>,
+    #[serde(default)]
+    pub incomes: Vec<Income>,
+    #[serde(default)]
+    pub holdings: Holdings,
+    #[serde(default)]
+    pub settings: Settings,
+}
+
+#[wasm_bindgen]
+pub fn load_saved_data_wasm(data: String) -> Result<JsValue, JsValue> {
+    load_saved_data(&data)
+        .map(|saved_data| JsValue::from_serde(&saved_data).unwrap())
+        .map_err(|error| JsValue::from_str(&error))
+}
+
+pub fn load_saved_data(data: &str) -> Result<SavedData, String> {
+    let mut saved_data: SavedData =
+        serde_json::from_str(data).map_err(|error| error.to_string())?;
+
+    // the calculations expect everything in date order
+    saved_data.trades.sort_by_key(|trade| trade.date);
+    saved_data.incomes.sort_by_key(|income| income.date);
+    for (index, income) in saved_data.incomes.iter_mut().enumerate() {
+        income.fill_missing_id(index);
+    }
+
+    // files from before lots had ids need them so disposals can be traced back
+    for (currency, currency_holdings) in saved_data.holdings.0.iter_mut() {
+        currency_holdings.sort_by_key(|currency_holding| currency_holding.date);
+        for (index, currency_holding) in currency_holdings.iter_mut().enumerate() {
+            currency_holding.fill_missing_ids(currency, index);
+        }
+    }
+
+    Ok(saved_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load_saved_data;
+    use crate::calculate_gains::calculate_gains;
+    use crate::method::Method;
+    use rust_decimal_macros::*;
+
+    static SAVED_DATA: &str = r#"{
+        "savedDate": "2021-02-01T00:00:00.000Z",
+        "version": 1,
+        "settings": {
+            "fiatRateMethod": "Double Average",
+            "fiatCurrency": "USD",
+            "gainCalculationMethod": "LIFO"
+        },
+        "holdings": {
+            "BTC": [
+                { "amount": 1, "rateInFiat": 10000, "date": 1577836800000, "location": "Coinbase" }
+            ]
+        },
+        "trades": [
+            {
+                "boughtCurrency": "USD",
+                "soldCurrency": "BTC",
+                "amountSold": 0.5,
+                "rate": 0.00005,
+                "date": 1612137600000,
+                "exchangeID": "abc",
+                "exchange": "Coinbase",
+                "ID": "second",
+                "transactionFee": 0,
+                "transactionFeeCurrency": "USD",
+                "fiatRate": 20000
+            },
+            {
+                "boughtCurrency": "USD",
+                "soldCurrency": "BTC",
+                "amountSold": 0.25,
+                "rate": 0.00004,
+                "date": 1609459200000,
+                "exchangeID": "def",
+                "exchange": "Coinbase",
+                "ID": "first",
+                "transactionFee": 0,
+                "transactionFeeCurrency": "USD",
+                "fiatRate": 25000
+            }
+        ],
+        "incomes": [
+            { "amount": 0.1, "currency": "ETH", "ID": "income", "date": 1609459200000, "fiatRate": 700 },
+            { "amount": 0.1, "currency": "ETH", "ID": "", "date": 1612137600000, "fiatRate": 1300 }
+        ]
+    }"#;
+
+    #[test]
+    fn loads_saved_data() {
+        let saved_data = load_saved_data(SAVED_DATA).unwrap();
+
+        assert_eq!(saved_data.settings.fiat_currency, "USD");
+        assert_eq!(saved_data.settings.gain_calculation_method, Method::LIFO);
+        assert_eq!(
+            saved_data.settings.fiat_rate_method,
+            Some("Double Average".to_string())
+        );
+        assert_eq!(saved_data.trades[0].id, "first");
+        assert_eq!(saved_data.trades[0].fiat_rate, Some(dec!(25000)));
+        assert_eq!(saved_data.incomes[0].fiat_rate, Some(dec!(700)));
+        assert_eq!(saved_data.incomes[1].id, "ETH-1612137600000-1");
+
+        let currency_holding = &saved_data.holdings.0.get("BTC").unwrap()[0];
+        assert!(!currency_holding.id.is_empty());
+        assert_eq!(currency_holding.acquisition_id, currency_holding.id);
+
+        let result = calculate_gains(
+            saved_data.holdings,
+            saved_data.trades,
+            saved_data.incomes,
+            saved_data.settings.fiat_currency,
+            saved_data.settings.gain_calculation_method,
+        );
+        assert_eq!(result.long_term_gain, dec!(8750));
+    }
+
+    #[test]
+    fn reports_invalid_files() {
+        assert!(load_saved_data("{ \"trades\": 1 }").is_err());
+    }
+}
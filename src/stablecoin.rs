@@ -0,0 +1,318 @@
+use crate::holding::{CurrencyHolding, Holdings};
+use crate::income::Income;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, One, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+pub const STABLECOINS: [&str; 7] = ["USDT", "USDC", "DAI", "BUSD", "TUSD", "USDP", "GUSD"];
+
+fn default_stablecoins() -> Vec<String> {
+    STABLECOINS.iter().map(|coin| coin.to_string()).collect()
+}
+
+#[wasm_bindgen]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StablecoinPolicy {
+    // the same as the fiat currency, they never have lots or gains
+    Par = "Par",
+    // held as lots valued at their peg and disposed of at it, so only a stablecoin bought off its peg has a gain
+    Pegged = "Pegged",
+    // like any other currency
+    Crypto = "Crypto",
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Stablecoins {
+    #[serde(rename = "fiatCurrency")]
+    pub fiat_currency: String,
+    pub policy: StablecoinPolicy,
+    #[serde(default = "default_stablecoins")]
+    pub currencies: Vec<String>,
+    // value of one unit in the fiat currency, one when missing
+    #[serde(default)]
+    pub pegs: HashMap<String, Decimal>,
+}
+
+impl Stablecoins {
+    pub fn new(fiat_currency: &str, policy: StablecoinPolicy) -> Stablecoins {
+        Stablecoins {
+            fiat_currency: fiat_currency.to_string(),
+            policy,
+            currencies: default_stablecoins(),
+            pegs: HashMap::new(),
+        }
+    }
+
+    pub fn is_stablecoin(&self, currency: &str) -> bool {
+        self.policy != StablecoinPolicy::Crypto
+            && self.currencies.iter().any(|coin| coin == currency)
+    }
+
+    pub fn peg(&self, currency: &str) -> Decimal {
+        if self.policy == StablecoinPolicy::Par {
+            Decimal::one()
+        } else {
+            self.pegs
+                .get(currency)
+                .cloned()
+                .unwrap_or_else(Decimal::one)
+        }
+    }
+
+    // the trade as the engine should see it under the policy
+    pub fn trade(&self, mut trade: Trade) -> Trade {
+        let sold_stable = self.is_stablecoin(&trade.sold_currency);
+        let bought_stable = self.is_stablecoin(&trade.bought_currency);
+        if !sold_stable && !bought_stable && !self.is_stablecoin(&trade.transaction_fee_currency) {
+            return trade;
+        }
+
+        let sold_peg = self.peg(&trade.sold_currency);
+        let bought_peg = self.peg(&trade.bought_currency);
+
+        // fees in a stablecoin which isn't part of the trade are just fiat
+        if self.is_stablecoin(&trade.transaction_fee_currency)
+            && trade.transaction_fee_currency != trade.sold_currency
+            && trade.transaction_fee_currency != trade.bought_currency
+        {
+            trade.transaction_fee *= self.peg(&trade.transaction_fee_currency);
+            trade.transaction_fee_currency = self.fiat_currency.clone();
+        }
+
+        match self.policy {
+            StablecoinPolicy::Par => {
+                if sold_stable {
+                    if trade.transaction_fee_currency == trade.sold_currency {
+                        trade.transaction_fee_currency = self.fiat_currency.clone();
+                    }
+                    trade.sold_currency = self.fiat_currency.clone();
+                }
+                if bought_stable {
+                    if trade.transaction_fee_currency == trade.bought_currency {
+                        trade.transaction_fee_currency = self.fiat_currency.clone();
+                    }
+                    trade.bought_currency = self.fiat_currency.clone();
+                }
+                if trade.sold_currency == self.fiat_currency {
+                    // buying with fiat prices what was bought
+                    trade.fiat_rate = Some(trade.rate);
+                } else if bought_stable && !trade.rate.is_zero() {
+                    trade.fiat_rate = Some(Decimal::one() / trade.rate);
+                }
+            }
+            StablecoinPolicy::Pegged => {
+                // swapping one stablecoin for another disposes of the sold one at its peg
+                if sold_stable {
+                    trade.fiat_rate = Some(sold_peg);
+                } else if trade.sold_currency == self.fiat_currency {
+                    trade.fiat_rate = Some(bought_peg);
+                } else if !trade.rate.is_zero() {
+                    trade.fiat_rate = Some(bought_peg / trade.rate);
+                }
+            }
+            _ => {}
+        }
+
+        trade
+    }
+
+    // at par swapping fiat for a stablecoin, or one stablecoin for another, changes nothing
+    pub fn is_unchanged(&self, trade: &Trade) -> bool {
+        let is_fiat =
+            |currency: &str| currency == self.fiat_currency || self.is_stablecoin(currency);
+        self.policy == StablecoinPolicy::Par
+            && is_fiat(&trade.sold_currency)
+            && is_fiat(&trade.bought_currency)
+            && (self.is_stablecoin(&trade.sold_currency)
+                || self.is_stablecoin(&trade.bought_currency))
+    }
+
+    pub fn income(&self, mut income: Income) -> Income {
+        if self.is_stablecoin(&income.currency) {
+            income.fiat_rate = Some(self.peg(&income.currency));
+            if self.policy == StablecoinPolicy::Par {
+                income.currency = self.fiat_currency.clone();
+            }
+        }
+        income
+    }
+
+    // at par stablecoin lots are fiat lots, pegged they are valued at their peg
+    pub fn holdings(&self, mut holdings: Holdings) -> Holdings {
+        if self.policy == StablecoinPolicy::Crypto {
+            return holdings;
+        }
+
+        if self.policy == StablecoinPolicy::Pegged {
+            for currency in self.currencies.iter() {
+                let peg = self.peg(currency);
+                if let Some(currency_holdings) = holdings.0.get_mut(currency) {
+                    for currency_holding in currency_holdings.iter_mut() {
+                        currency_holding.rate_in_fiat = peg;
+                    }
+                }
+            }
+            return holdings;
+        }
+
+        for currency in self.currencies.iter() {
+            if let Some(currency_holdings) = holdings.0.remove(currency) {
+                let fiat_holdings = holdings.0.entry(self.fiat_currency.clone()).or_default();
+                fiat_holdings.extend(currency_holdings.into_iter().map(|currency_holding| {
+                    CurrencyHolding {
+                        rate_in_fiat: Decimal::one(),
+                        ..currency_holding
+                    }
+                }));
+                fiat_holdings.sort_by_key(|currency_holding| currency_holding.date);
+            }
+        }
+        holdings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StablecoinPolicy, Stablecoins};
+    use crate::calculate_gains::calculate_gains_with_stablecoins;
+    use crate::holding::{CurrencyHolding, Holdings};
+    use crate::holding_selection::holding_selection_with_stablecoins;
+    use crate::method::Method;
+    use crate::mocks;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::{Decimal, Zero};
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "USD";
+
+    fn trade(
+        id: &str,
+        sold_currency: &str,
+        bought_currency: &str,
+        amount_sold: Decimal,
+        rate: Decimal,
+        fiat_rate: Decimal,
+        date: u64,
+    ) -> Trade {
+        Trade {
+            fiat_rate: Some(fiat_rate),
+            ..mocks::trade(id, sold_currency, bought_currency, amount_sold, rate, date)
+        }
+    }
+
+    // usdt drifts around its peg while being bought, spent on btc and then bought back
+    fn trades() -> Vec<Trade> {
+        vec![
+            trade(
+                "a",
+                FIAT_CURRENCY,
+                "USDT",
+                dec!(1000),
+                dec!(0.99),
+                dec!(0.99),
+                1000,
+            ),
+            trade(
+                "b",
+                "USDT",
+                "BTC",
+                dec!(1000),
+                dec!(10000),
+                dec!(1.01),
+                2000,
+            ),
+            trade(
+                "c",
+                "BTC",
+                "USDT",
+                dec!(0.1),
+                dec!(0.00008),
+                dec!(12500),
+                3000,
+            ),
+        ]
+    }
+
+    fn gain(policy: StablecoinPolicy) -> (Decimal, Holdings) {
+        let result = calculate_gains_with_stablecoins(
+            Holdings::default(),
+            trades(),
+            vec![],
+            &Stablecoins::new(FIAT_CURRENCY, policy),
+            Method::FIFO,
+        );
+        (result.short_term_gain, result.new_holdings)
+    }
+
+    #[test]
+    fn stablecoins_follow_the_policy() {
+        let (crypto_gain, _) = gain(StablecoinPolicy::Crypto);
+        let (pegged_gain, pegged_holdings) = gain(StablecoinPolicy::Pegged);
+        let (par_gain, par_holdings) = gain(StablecoinPolicy::Par);
+
+        // as crypto the drift in usdt is a gain on top of the btc gain
+        assert!(crypto_gain > dec!(250));
+        assert_eq!(pegged_gain, dec!(250));
+        assert_eq!(par_gain, dec!(250));
+
+        assert!(pegged_holdings.0.contains_key("USDT"));
+        assert!(!par_holdings.0.contains_key("USDT"));
+        let usdt = pegged_holdings.0.get("USDT").unwrap();
+        assert!(usdt
+            .iter()
+            .all(|currency_holding| currency_holding.rate_in_fiat == dec!(1)));
+    }
+
+    #[test]
+    fn stablecoin_swaps_have_no_gain() {
+        let mut stablecoins = Stablecoins::new(FIAT_CURRENCY, StablecoinPolicy::Pegged);
+        stablecoins.pegs.insert("USDC".to_string(), dec!(0.98));
+        let mut holdings = Holdings::default();
+        holdings.0.insert(
+            "USDT".to_string(),
+            vec![CurrencyHolding {
+                amount: dec!(100),
+                rate_in_fiat: dec!(1.02),
+                date: 0,
+                location: "".to_string(),
+                id: "usdt".to_string(),
+                acquisition_id: "usdt".to_string(),
+                parent_ids: vec![],
+            }],
+        );
+        let swap = trade("a", "USDT", "USDC", dec!(100), dec!(1), dec!(1.05), 1000);
+
+        let result = holdings.clone().process_trade_with_stablecoins(
+            swap.clone(),
+            &stablecoins,
+            Method::FIFO,
+        );
+        assert!(result.short_term_gain.is_zero());
+        let selection =
+            holding_selection_with_stablecoins(holdings, swap, &stablecoins, Method::FIFO);
+        assert_eq!(selection.deducted_holdings[0].rate_in_fiat, dec!(1));
+    }
+
+    #[test]
+    fn only_trades_the_policy_rewrote_are_dropped() {
+        let usdt = trade(
+            "a",
+            "USDT",
+            FIAT_CURRENCY,
+            dec!(100),
+            dec!(1),
+            dec!(1),
+            1000,
+        );
+        let btc = trade("b", "BTC", "BTC", dec!(1), dec!(1), dec!(10000), 1000);
+
+        let par = Stablecoins::new(FIAT_CURRENCY, StablecoinPolicy::Par);
+        assert!(par.is_unchanged(&usdt));
+        assert!(!par.is_unchanged(&btc));
+        let crypto = Stablecoins::new(FIAT_CURRENCY, StablecoinPolicy::Crypto);
+        assert!(!crypto.is_unchanged(&usdt));
+        assert!(!crypto.is_unchanged(&btc));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::calculate_gain_per_holdings::calculate_gain_per_holdings;
+use crate::date::{self, InvalidDate};
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::method::Method;
+use crate::provenance::{calculate_provenance, Provenance};
+use crate::tax_report::Disposal;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use rust_decimal_macros::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+const TAX_YEAR_STARTING_MONTH: u32 = 7;
+const CGT_DISCOUNT: Decimal = dec!(0.5);
+// personal use assets bought for less than this are exempt from CGT
+const PERSONAL_USE_ASSET_LIMIT: Decimal = dec!(10000);
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AustraliaOptions {
+    // trades disposing of assets which were acquired for personal use
+    #[serde(rename = "personalUseTrades", default)]
+    pub personal_use_trades: Vec<String>,
+    #[serde(rename = "carriedForwardLoss", default)]
+    pub carried_forward_loss: Decimal,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AustraliaYear {
+    pub year: i32,
+    pub lines: Vec<Disposal>,
+    #[serde(rename = "nonDiscountableGain")]
+    pub non_discountable_gain: Decimal,
+    #[serde(rename = "discountableGain")]
+    pub discountable_gain: Decimal,
+    #[serde(rename = "capitalLoss")]
+    pub capital_loss: Decimal,
+    #[serde(rename = "priorYearLoss")]
+    pub prior_year_loss: Decimal,
+    #[serde(rename = "personalUseExempt")]
+    pub personal_use_exempt: Decimal,
+    // losses on personal use assets are disregarded whatever they cost
+    #[serde(rename = "personalUseLoss")]
+    pub personal_use_loss: Decimal,
+    pub discount: Decimal,
+    #[serde(rename = "netCapitalGain")]
+    pub net_capital_gain: Decimal,
+    #[serde(rename = "carriedForwardLoss")]
+    pub carried_forward_loss: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AustraliaReport {
+    pub years: BTreeMap<i32, AustraliaYear>,
+}
+
+#[wasm_bindgen]
+pub fn calculate_australia_report_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+    method: Method,
+    options: &JsValue,
+) -> Result<JsValue, JsValue> {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+    let options: AustraliaOptions = options.into_serde().unwrap();
+
+    calculate_australia_report(holdings, trades, incomes, fiat_currency, method, options)
+        .map(|report| JsValue::from_serde(&report).unwrap())
+        .map_err(|error| JsValue::from_serde(&error).unwrap())
+}
+
+pub fn calculate_australia_report(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+    method: Method,
+    options: AustraliaOptions,
+) -> Result<AustraliaReport, InvalidDate> {
+    // the personal use limit applies to what the whole asset cost, not each piece sold
+    let provenance = calculate_provenance(
+        holdings.clone(),
+        trades.clone(),
+        incomes.clone(),
+        fiat_currency.clone(),
+        method,
+    );
+    let result = calculate_gain_per_holdings(holdings, trades, incomes, fiat_currency, method);
+    let mut disposals: Vec<Disposal> = result
+        .short_term_trades
+        .iter()
+        .chain(result.long_term_trades.iter())
+        .map(Disposal::from)
+        .collect();
+    disposals.sort_by_key(|disposal| disposal.date_sold);
+
+    australia_report(disposals, &provenance, options)
+}
+
+pub fn australia_report(
+    disposals: Vec<Disposal>,
+    provenance: &Provenance,
+    options: AustraliaOptions,
+) -> Result<AustraliaReport, InvalidDate> {
+    let mut years: BTreeMap<i32, AustraliaYear> = BTreeMap::new();
+
+    for disposal in disposals {
+        let year = years
+            .entry(date::tax_year(disposal.date_sold, TAX_YEAR_STARTING_MONTH)?)
+            .or_default();
+
+        let personal_use = options.personal_use_trades.contains(&disposal.trade_id);
+        let acquisition_cost = provenance
+            .acquisition_cost(&disposal.lot_id)
+            .unwrap_or(disposal.cost_basis);
+
+        if personal_use && acquisition_cost < PERSONAL_USE_ASSET_LIMIT {
+            year.personal_use_exempt += disposal.gain;
+        } else if personal_use && disposal.gain < Zero::zero() {
+            year.personal_use_loss -= disposal.gain;
+        } else if disposal.gain < Zero::zero() {
+            year.capital_loss -= disposal.gain;
+        } else if disposal.long_term {
+            year.discountable_gain += disposal.gain;
+        } else {
+            year.non_discountable_gain += disposal.gain;
+        }
+        year.lines.push(disposal);
+    }
+
+    let mut carried_forward_loss = options.carried_forward_loss;
+    for (year, australia_year) in years.iter_mut() {
+        australia_year.year = *year;
+        australia_year.prior_year_loss = carried_forward_loss;
+
+        // losses have to be used on gains which do not get the discount first
+        let mut losses = australia_year.capital_loss + carried_forward_loss;
+        let non_discountable_gain = australia_year.non_discountable_gain - losses;
+        losses = (-non_discountable_gain).max(Zero::zero());
+        let discountable_gain = australia_year.discountable_gain - losses;
+        losses = (-discountable_gain).max(Zero::zero());
+
+        let discountable_gain = discountable_gain.max(Zero::zero());
+        australia_year.discount = discountable_gain * CGT_DISCOUNT;
+        australia_year.net_capital_gain =
+            non_discountable_gain.max(Zero::zero()) + discountable_gain - australia_year.discount;
+        australia_year.carried_forward_loss = losses;
+        carried_forward_loss = losses;
+    }
+
+    Ok(AustraliaReport { years })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{australia_report, AustraliaOptions};
+    use crate::holding::CurrencyHolding;
+    use crate::provenance::Provenance;
+    use crate::tax_report::Disposal;
+    use rust_decimal::prelude::{Decimal, Zero};
+    use rust_decimal_macros::*;
+
+    // 2022-08-01
+    const SALE_DATE: u64 = 1659312000000;
+
+    fn disposal(trade_id: &str, cost_basis: Decimal, gain: Decimal, long_term: bool) -> Disposal {
+        Disposal {
+            currency: "BTC".to_string(),
+            lot_id: trade_id.to_string(),
+            trade_id: trade_id.to_string(),
+            location: "".to_string(),
+            amount: dec!(1),
+            date_acquired: 0,
+            date_sold: SALE_DATE,
+            proceeds: cost_basis + gain,
+            cost_basis,
+            gain,
+            long_term,
+        }
+    }
+
+    #[test]
+    fn losses_reduce_non_discountable_gains_first() {
+        let report = australia_report(
+            vec![
+                disposal("short", dec!(100), dec!(300), false),
+                disposal("long", dec!(100), dec!(1000), true),
+                disposal("loss", dec!(1000), dec!(-400), false),
+            ],
+            &Provenance::default(),
+            AustraliaOptions::default(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert_eq!(year.capital_loss, dec!(400));
+        assert_eq!(year.discount, dec!(450));
+        assert_eq!(year.net_capital_gain, dec!(450));
+        assert!(year.carried_forward_loss.is_zero());
+    }
+
+    #[test]
+    fn unused_losses_carry_forward() {
+        let report = australia_report(
+            vec![
+                disposal("long", dec!(100), dec!(100), true),
+                disposal("loss", dec!(1000), dec!(-400), false),
+            ],
+            &Provenance::default(),
+            AustraliaOptions {
+                personal_use_trades: vec![],
+                carried_forward_loss: dec!(50),
+            },
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert_eq!(year.prior_year_loss, dec!(50));
+        assert!(year.net_capital_gain.is_zero());
+        assert_eq!(year.carried_forward_loss, dec!(350));
+    }
+
+    #[test]
+    fn personal_use_assets_are_exempt() {
+        let report = australia_report(
+            vec![
+                disposal("personal", dec!(500), dec!(200), false),
+                disposal("expensive", dec!(20000), dec!(200), false),
+            ],
+            &Provenance::default(),
+            AustraliaOptions {
+                personal_use_trades: vec!["personal".to_string(), "expensive".to_string()],
+                carried_forward_loss: Zero::zero(),
+            },
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert_eq!(year.personal_use_exempt, dec!(200));
+        assert_eq!(year.net_capital_gain, dec!(200));
+    }
+
+    #[test]
+    fn personal_use_limit_is_for_the_whole_asset() {
+        let lot = CurrencyHolding {
+            amount: dec!(2),
+            rate_in_fiat: dec!(6000),
+            date: 0,
+            location: "".to_string(),
+            id: "bought".to_string(),
+            acquisition_id: "bought".to_string(),
+            parent_ids: vec![],
+        };
+        let mut provenance = Provenance::default();
+        provenance.record("BTC", &lot);
+        let piece = |trade_id: &str| CurrencyHolding {
+            amount: dec!(1),
+            id: format!("bought/{}", trade_id),
+            parent_ids: vec!["bought".to_string()],
+            ..lot.clone()
+        };
+        provenance.record_disposal("BTC", &piece("first"), "first");
+        provenance.record_disposal("BTC", &piece("second"), "second");
+
+        let mut first = disposal("first", dec!(6000), dec!(500), false);
+        first.lot_id = "bought/first".to_string();
+        let mut second = disposal("second", dec!(6000), dec!(-1000), false);
+        second.lot_id = "bought/second".to_string();
+
+        let report = australia_report(
+            vec![first, second],
+            &provenance,
+            AustraliaOptions {
+                personal_use_trades: vec!["first".to_string(), "second".to_string()],
+                carried_forward_loss: Zero::zero(),
+            },
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert!(year.personal_use_exempt.is_zero());
+        assert_eq!(year.non_discountable_gain, dec!(500));
+        assert!(year.capital_loss.is_zero());
+        assert_eq!(year.personal_use_loss, dec!(1000));
+        assert_eq!(year.net_capital_gain, dec!(500));
+    }
+}
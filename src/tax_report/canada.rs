@@ -0,0 +1,530 @@
+use crate::date::{self, InvalidDate};
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::trade::Trade;
+use crate::MIN_HOLDING_SIZE;
+use rust_decimal::prelude::{Decimal, Zero};
+use rust_decimal_macros::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use wasm_bindgen::prelude::*;
+
+const INCLUSION_RATE: Decimal = dec!(0.5);
+const SUPERFICIAL_LOSS_WINDOW: u64 = 30 * 24 * 60 * 60 * 1000;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AdjustedCostBase {
+    pub amount: Decimal,
+    #[serde(rename = "adjustedCostBase")]
+    pub adjusted_cost_base: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ScheduleThreeLine {
+    pub currency: String,
+    #[serde(rename = "tradeID")]
+    pub trade_id: String,
+    pub amount: Decimal,
+    #[serde(rename = "dateSold")]
+    pub date_sold: u64,
+    pub proceeds: Decimal,
+    #[serde(rename = "adjustedCostBase")]
+    pub adjusted_cost_base: Decimal,
+    pub outlays: Decimal,
+    pub gain: Decimal,
+    #[serde(rename = "deniedLoss")]
+    pub denied_loss: Decimal,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct CanadaYear {
+    pub year: i32,
+    pub lines: Vec<ScheduleThreeLine>,
+    pub proceeds: Decimal,
+    #[serde(rename = "adjustedCostBase")]
+    pub adjusted_cost_base: Decimal,
+    pub outlays: Decimal,
+    pub gain: Decimal,
+    #[serde(rename = "deniedLoss")]
+    pub denied_loss: Decimal,
+    #[serde(rename = "taxableCapitalGain")]
+    pub taxable_capital_gain: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CanadaReport {
+    pub years: BTreeMap<i32, CanadaYear>,
+    pub pools: HashMap<String, AdjustedCostBase>,
+}
+
+struct Movement {
+    currency: String,
+    id: String,
+    date: u64,
+    // positive for acquisitions and negative for disposals
+    amount: Decimal,
+    cost: Decimal,
+    proceeds: Decimal,
+    outlays: Decimal,
+    // a fee paid in a third currency, disposed of at its cost which then goes towards the trade
+    fee: bool,
+}
+
+#[wasm_bindgen]
+pub fn calculate_canada_report_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+) -> Result<JsValue, JsValue> {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+
+    calculate_canada_report(holdings, trades, incomes, fiat_currency)
+        .map(|report| JsValue::from_serde(&report).unwrap())
+        .map_err(|error| JsValue::from_serde(&error).unwrap())
+}
+
+pub fn calculate_canada_report(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+) -> Result<CanadaReport, InvalidDate> {
+    let mut pools: HashMap<String, AdjustedCostBase> = HashMap::new();
+    for (currency, currency_holdings) in holdings.0 {
+        let pool = pools.entry(currency).or_default();
+        for currency_holding in currency_holdings {
+            pool.amount += currency_holding.amount;
+            pool.adjusted_cost_base += currency_holding.amount * currency_holding.rate_in_fiat;
+        }
+    }
+
+    let movements = movements(trades, incomes, &fiat_currency);
+
+    // what was held and what was acquired by date, looked up for each loss instead of going through every movement again
+    let mut held: HashMap<String, RunningTotal> = pools
+        .iter()
+        .map(|(currency, pool)| (currency.clone(), RunningTotal::starting_at(pool.amount)))
+        .collect();
+    let mut acquired: HashMap<String, RunningTotal> = HashMap::new();
+    for movement in movements.iter() {
+        held.entry(movement.currency.clone())
+            .or_default()
+            .add(movement.date, movement.amount);
+        if movement.amount > Zero::zero() {
+            acquired
+                .entry(movement.currency.clone())
+                .or_default()
+                .add(movement.date, movement.amount);
+        }
+    }
+
+    let mut years: BTreeMap<i32, CanadaYear> = BTreeMap::new();
+    // cost of fees paid in a third currency by trade, taken by the first movement of the trade after them
+    let mut fee_costs: HashMap<String, Decimal> = HashMap::new();
+
+    for movement in movements.iter() {
+        let pool = pools.entry(movement.currency.clone()).or_default();
+
+        if movement.amount > Zero::zero() {
+            pool.amount += movement.amount;
+            pool.adjusted_cost_base +=
+                movement.cost + fee_costs.remove(&movement.id).unwrap_or_default();
+            continue;
+        }
+
+        let amount = -movement.amount;
+        let adjusted_cost_base = if pool.amount > MIN_HOLDING_SIZE {
+            pool.adjusted_cost_base * amount.min(pool.amount) / pool.amount
+        } else {
+            Zero::zero()
+        };
+        pool.amount = (pool.amount - amount).max(Zero::zero());
+        pool.adjusted_cost_base -= adjusted_cost_base;
+
+        let (proceeds, outlays) = if movement.fee {
+            fee_costs.insert(movement.id.clone(), adjusted_cost_base);
+            (adjusted_cost_base, Zero::zero())
+        } else {
+            (
+                movement.proceeds,
+                movement.outlays + fee_costs.remove(&movement.id).unwrap_or_default(),
+            )
+        };
+
+        let mut gain = proceeds - adjusted_cost_base - outlays;
+        let mut denied_loss = Zero::zero();
+        if gain < Zero::zero() {
+            let window_start = movement.date.saturating_sub(SUPERFICIAL_LOSS_WINDOW);
+            let window_end = movement.date + SUPERFICIAL_LOSS_WINDOW;
+            let acquired = acquired
+                .get(&movement.currency)
+                .map_or_else(Zero::zero, |acquired| {
+                    acquired.between(window_start, window_end)
+                });
+            let held = held
+                .get(&movement.currency)
+                .map_or_else(Zero::zero, |held| held.at(window_end).max(Zero::zero()));
+
+            let denied_amount = amount.min(acquired).min(held);
+            if denied_amount > Zero::zero() {
+                // the denied loss moves into the cost of the identical property bought back
+                denied_loss = -gain * denied_amount / amount;
+                gain += denied_loss;
+                pool.adjusted_cost_base += denied_loss;
+            }
+        }
+
+        let year = years.entry(date::year(movement.date)?).or_default();
+        year.proceeds += proceeds;
+        year.adjusted_cost_base += adjusted_cost_base;
+        year.outlays += outlays;
+        year.gain += gain;
+        year.denied_loss += denied_loss;
+        year.lines.push(ScheduleThreeLine {
+            currency: movement.currency.clone(),
+            trade_id: movement.id.clone(),
+            amount,
+            date_sold: movement.date,
+            proceeds,
+            adjusted_cost_base,
+            outlays,
+            gain,
+            denied_loss,
+        });
+    }
+
+    for (year, canada_year) in years.iter_mut() {
+        canada_year.year = *year;
+        canada_year.taxable_capital_gain = canada_year.gain * INCLUSION_RATE;
+    }
+
+    Ok(CanadaReport { years, pools })
+}
+
+// a total after each date it changed on
+#[derive(Default)]
+struct RunningTotal {
+    start: Decimal,
+    totals: Vec<(u64, Decimal)>,
+}
+
+impl RunningTotal {
+    fn starting_at(start: Decimal) -> RunningTotal {
+        RunningTotal {
+            start,
+            totals: vec![],
+        }
+    }
+
+    // dates have to be added in order
+    fn add(&mut self, date: u64, amount: Decimal) {
+        let total = self.at(date) + amount;
+        match self.totals.last_mut() {
+            Some(last) if last.0 == date => last.1 = total,
+            _ => self.totals.push((date, total)),
+        }
+    }
+
+    // the total once everything up to and including the date was added
+    fn at(&self, date: u64) -> Decimal {
+        match self.totals.partition_point(|(other, _)| *other <= date) {
+            0 => self.start,
+            index => self.totals[index - 1].1,
+        }
+    }
+
+    fn between(&self, start: u64, end: u64) -> Decimal {
+        let before = match start.checked_sub(1) {
+            Some(date) => self.at(date),
+            None => self.start,
+        };
+        self.at(end) - before
+    }
+}
+
+fn movements(trades: Vec<Trade>, incomes: Vec<Income>, fiat_currency: &str) -> Vec<Movement> {
+    let mut movements: Vec<Movement> = vec![];
+
+    for trade in trades {
+        // handle this better somewhere else
+        if trade.amount_sold <= Zero::zero() {
+            continue;
+        }
+
+        // goes first so the trade's own movements can take its cost
+        if trade.fee_in_other_currency(fiat_currency) {
+            movements.push(Movement {
+                currency: trade.transaction_fee_currency.clone(),
+                id: trade.id.clone(),
+                date: trade.date,
+                amount: -trade.transaction_fee,
+                cost: Zero::zero(),
+                proceeds: Zero::zero(),
+                outlays: Zero::zero(),
+                fee: true,
+            });
+        }
+
+        if trade.sold_currency == fiat_currency {
+            let (amount, _) = trade.amount_bought(fiat_currency);
+            // a fee paid in fiat is an outlay of buying so it is part of the cost
+            let fee = if trade.transaction_fee_currency == fiat_currency {
+                trade.transaction_fee
+            } else {
+                Zero::zero()
+            };
+            movements.push(Movement {
+                currency: trade.bought_currency.clone(),
+                id: trade.id.clone(),
+                date: trade.date,
+                amount,
+                cost: amount * trade.fiat_rate() + fee,
+                proceeds: Zero::zero(),
+                outlays: Zero::zero(),
+                fee: false,
+            });
+            continue;
+        }
+
+        let (amount_to_add, outlays) = trade.amount_bought(fiat_currency);
+
+        movements.push(Movement {
+            currency: trade.sold_currency.clone(),
+            id: trade.id.clone(),
+            date: trade.date,
+            amount: -trade.amount_sold,
+            cost: Zero::zero(),
+            proceeds: trade.amount_sold * trade.fiat_rate(),
+            outlays,
+            fee: false,
+        });
+
+        if trade.bought_currency != fiat_currency && amount_to_add > MIN_HOLDING_SIZE {
+            movements.push(Movement {
+                currency: trade.bought_currency.clone(),
+                id: trade.id.clone(),
+                date: trade.date,
+                amount: amount_to_add,
+                cost: amount_to_add * trade.fiat_rate() * trade.rate,
+                proceeds: Zero::zero(),
+                outlays: Zero::zero(),
+                fee: false,
+            });
+        }
+    }
+
+    // incomes come after trades on the same date like everywhere else
+    for income in incomes {
+        movements.push(Movement {
+            currency: income.currency.clone(),
+            id: income.id.clone(),
+            date: income.date,
+            amount: income.amount,
+            cost: income.amount * income.clone().fiat_rate(),
+            proceeds: Zero::zero(),
+            outlays: Zero::zero(),
+            fee: false,
+        });
+    }
+
+    movements.sort_by_key(|movement| movement.date);
+    movements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::calculate_canada_report;
+    use crate::holding::{CurrencyHolding, Holdings};
+    use crate::income::Income;
+    use crate::mocks;
+    use crate::trade::Trade;
+    use rust_decimal::prelude::{Decimal, Zero};
+    use rust_decimal_macros::*;
+
+    static FIAT_CURRENCY: &str = "CAD";
+    // 2022-03-01
+    const START_DATE: u64 = 1646092800000;
+    const DAY_IN_MILLISECONDS: u64 = 24 * 60 * 60 * 1000;
+
+    fn trade(id: &str, buy: bool, amount: Decimal, price: Decimal, day: u64) -> Trade {
+        let date = START_DATE + day * DAY_IN_MILLISECONDS;
+        let trade = if buy {
+            mocks::trade(id, FIAT_CURRENCY, "BTC", amount * price, price, date)
+        } else {
+            mocks::trade(id, "BTC", FIAT_CURRENCY, amount, dec!(1) / price, date)
+        };
+        Trade {
+            fiat_rate: Some(price),
+            ..trade
+        }
+    }
+
+    #[test]
+    fn adjusted_cost_base_is_pooled() {
+        let report = calculate_canada_report(
+            Holdings::default(),
+            vec![
+                trade("first", true, dec!(1), dec!(100), 0),
+                trade("second", true, dec!(1), dec!(300), 1),
+                trade("sale", false, dec!(1), dec!(400), 2),
+            ],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2022).unwrap();
+        assert_eq!(year.lines.len(), 1);
+        assert_eq!(year.adjusted_cost_base, dec!(200));
+        assert_eq!(year.gain, dec!(200));
+        assert_eq!(year.taxable_capital_gain, dec!(100));
+        assert_eq!(
+            report.pools.get("BTC").unwrap().adjusted_cost_base,
+            dec!(200)
+        );
+    }
+
+    #[test]
+    fn superficial_loss_is_denied_and_added_to_cost() {
+        let report = calculate_canada_report(
+            Holdings::default(),
+            vec![
+                trade("first", true, dec!(1), dec!(300), 0),
+                trade("sale", false, dec!(1), dec!(100), 100),
+                trade("again", true, dec!(1), dec!(120), 110),
+            ],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2022).unwrap();
+        assert_eq!(year.denied_loss, dec!(200));
+        assert!(year.gain.is_zero());
+        assert_eq!(
+            report.pools.get("BTC").unwrap().adjusted_cost_base,
+            dec!(320)
+        );
+    }
+
+    #[test]
+    fn loss_is_allowed_when_repurchase_is_sold_within_window() {
+        let report = calculate_canada_report(
+            Holdings::default(),
+            vec![
+                trade("first", true, dec!(1), dec!(300), 0),
+                trade("sale", false, dec!(1), dec!(100), 100),
+                trade("again", true, dec!(1), dec!(120), 110),
+                trade("sold again", false, dec!(1), dec!(120), 120),
+            ],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2022).unwrap();
+        assert!(year.denied_loss.is_zero());
+        assert_eq!(year.gain, dec!(-200));
+    }
+
+    #[test]
+    fn loss_is_denied_when_opening_holdings_are_still_held() {
+        let mut holdings = Holdings::default();
+        holdings.0.insert(
+            "BTC".to_string(),
+            vec![CurrencyHolding {
+                amount: dec!(2),
+                rate_in_fiat: dec!(300),
+                date: 0,
+                location: "".to_string(),
+                id: "opening".to_string(),
+                acquisition_id: "opening".to_string(),
+                parent_ids: vec![],
+            }],
+        );
+
+        // the repurchase is sold again but the opening lot left over is still identical property
+        let report = calculate_canada_report(
+            holdings,
+            vec![
+                trade("sale", false, dec!(1), dec!(100), 100),
+                trade("again", true, dec!(1), dec!(120), 110),
+                trade("sold again", false, dec!(1), dec!(120), 120),
+            ],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2022).unwrap();
+        assert_eq!(year.lines[0].denied_loss, dec!(200));
+    }
+
+    #[test]
+    fn incomes_on_the_day_of_a_sale_come_after_it() {
+        let sale = trade("sale", false, dec!(1), dec!(400), 5);
+        let income = Income {
+            amount: dec!(1),
+            currency: "BTC".to_string(),
+            transaction_id: None,
+            id: "staking".to_string(),
+            fee: None,
+            date: sale.date,
+            fiat_rate: Some(dec!(1000)),
+        };
+
+        let report = calculate_canada_report(
+            Holdings::default(),
+            vec![trade("first", true, dec!(1), dec!(100), 0), sale],
+            vec![income],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2022).unwrap();
+        assert_eq!(year.adjusted_cost_base, dec!(100));
+        assert_eq!(year.gain, dec!(300));
+    }
+
+    #[test]
+    fn fees_are_part_of_the_cost_or_disposed_of() {
+        let mut holdings = Holdings::default();
+        holdings.0.insert(
+            "BNB".to_string(),
+            vec![CurrencyHolding {
+                amount: dec!(2),
+                rate_in_fiat: dec!(50),
+                date: 0,
+                location: "".to_string(),
+                id: "bnb".to_string(),
+                acquisition_id: "bnb".to_string(),
+                parent_ids: vec![],
+            }],
+        );
+        let mut buy = trade("buy", true, dec!(1), dec!(100), 0);
+        buy.amount_sold += dec!(10);
+        buy.transaction_fee = dec!(10);
+        buy.transaction_fee_currency = FIAT_CURRENCY.to_string();
+        let mut sale = trade("sale", false, dec!(1), dec!(400), 1);
+        sale.transaction_fee = dec!(0.5);
+        sale.transaction_fee_currency = "BNB".to_string();
+
+        let report =
+            calculate_canada_report(holdings, vec![buy, sale], vec![], FIAT_CURRENCY.to_string())
+                .unwrap();
+
+        let year = report.years.get(&2022).unwrap();
+        let fee = &year.lines[0];
+        assert_eq!(fee.currency, "BNB");
+        assert_eq!(fee.adjusted_cost_base, dec!(25));
+        assert!(fee.gain.is_zero());
+        let sale = &year.lines[1];
+        assert_eq!(sale.adjusted_cost_base, dec!(110));
+        assert_eq!(sale.outlays, dec!(25));
+        assert_eq!(sale.gain, dec!(265));
+        assert_eq!(report.pools.get("BNB").unwrap().amount, dec!(1.5));
+    }
+}
@@ -0,0 +1,470 @@
+use crate::date::{self, InvalidDate};
+use crate::event::{events, Event};
+use crate::holding::lot_store::LotStore;
+use crate::holding::Holdings;
+use crate::income::Income;
+use crate::method::Method;
+use crate::tax_report::Disposal;
+use crate::trade::Trade;
+use rust_decimal::prelude::{Decimal, Zero};
+use rust_decimal_macros::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use wasm_bindgen::prelude::*;
+
+// §23 EStG, private sales stay tax free when the yearly total is below this
+pub fn private_sales_exemption_limit(year: i32) -> Decimal {
+    if year >= 2024 {
+        dec!(1000)
+    } else {
+        dec!(600)
+    }
+}
+
+// §22 Nr. 3 EStG, covers staking and similar income
+pub const OTHER_INCOME_EXEMPTION_LIMIT: Decimal = dec!(256);
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct GermanyYear {
+    pub year: i32,
+    // private sales held for one year or less, these are the Anlage SO lines
+    pub lines: Vec<Disposal>,
+    #[serde(rename = "privateSalesGain")]
+    pub private_sales_gain: Decimal,
+    #[serde(rename = "privateSalesExempt")]
+    pub private_sales_exempt: bool,
+    #[serde(rename = "taxablePrivateSalesGain")]
+    pub taxable_private_sales_gain: Decimal,
+    #[serde(rename = "taxFreeGain")]
+    pub tax_free_gain: Decimal,
+    #[serde(rename = "otherIncome")]
+    pub other_income: Decimal,
+    #[serde(rename = "otherIncomeExempt")]
+    pub other_income_exempt: bool,
+    #[serde(rename = "taxableOtherIncome")]
+    pub taxable_other_income: Decimal,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct GermanyReport {
+    pub years: BTreeMap<i32, GermanyYear>,
+    pub holdings: Holdings,
+}
+
+#[wasm_bindgen]
+pub fn calculate_germany_report_wasm(
+    holdings: &JsValue,
+    trade: &JsValue,
+    incomes: &JsValue,
+    fiat_currency: String,
+) -> Result<JsValue, JsValue> {
+    let holdings: Holdings = holdings.into_serde().unwrap();
+    let trades: Vec<Trade> = trade.into_serde().unwrap();
+    let incomes: Vec<Income> = incomes.into_serde().unwrap();
+
+    calculate_germany_report(holdings, trades, incomes, fiat_currency)
+        .map(|report| JsValue::from_serde(&report).unwrap())
+        .map_err(|error| JsValue::from_serde(&error).unwrap())
+}
+
+pub fn calculate_germany_report(
+    holdings: Holdings,
+    trades: Vec<Trade>,
+    incomes: Vec<Income>,
+    fiat_currency: String,
+) -> Result<GermanyReport, InvalidDate> {
+    let mut years: BTreeMap<i32, GermanyYear> = BTreeMap::new();
+
+    for income in incomes.iter() {
+        let year = date::year(income.date)?;
+        years.entry(year).or_default().other_income += income.amount * income.clone().fiat_rate();
+    }
+
+    // lots have to be used up first in first out within the location they are held at, anything short is moved from elsewhere
+    let mut lots_by_location: HashMap<String, LotStore> = split_by_location(holdings)
+        .into_iter()
+        .map(|(location, location_holdings)| (location, location_holdings.into()))
+        .collect();
+
+    for event in events(trades, incomes) {
+        let trade = match event {
+            // incomes do not say where they were received so they are kept apart until sold somewhere
+            Event::Income(income) => {
+                lots_by_location
+                    .entry("".to_owned())
+                    .or_default()
+                    .add_income(&income);
+                continue;
+            }
+            Event::Trade(trade) => trade,
+        };
+
+        // handle this better somewhere else
+        if trade.amount_sold > Zero::zero() {
+            move_shortfall(&mut lots_by_location, &trade);
+            let result = lots_by_location
+                .entry(trade.exchange.clone())
+                .or_default()
+                .process_trade(&trade, &fiat_currency, Method::FIFO);
+
+            for cost_basis_trade in result.cost_basis_trades.iter() {
+                let disposal = Disposal::from(cost_basis_trade);
+                let year = years.entry(disposal.year()?).or_default();
+                if disposal.long_term {
+                    year.tax_free_gain += disposal.gain;
+                } else {
+                    year.private_sales_gain += disposal.gain;
+                    year.lines.push(disposal);
+                }
+            }
+        }
+    }
+
+    for (year, germany_year) in years.iter_mut() {
+        germany_year.year = *year;
+
+        germany_year.private_sales_exempt =
+            germany_year.private_sales_gain < private_sales_exemption_limit(*year);
+        germany_year.taxable_private_sales_gain = if germany_year.private_sales_exempt {
+            Zero::zero()
+        } else {
+            germany_year.private_sales_gain
+        };
+
+        germany_year.other_income_exempt = germany_year.other_income < OTHER_INCOME_EXEMPTION_LIMIT;
+        germany_year.taxable_other_income = if germany_year.other_income_exempt {
+            Zero::zero()
+        } else {
+            germany_year.other_income
+        };
+    }
+
+    Ok(GermanyReport {
+        years,
+        holdings: merge_locations(
+            lots_by_location
+                .into_iter()
+                .map(|(location, lots)| (location, lots.into()))
+                .collect(),
+        ),
+    })
+}
+
+// coins sold on an exchange which does not hold enough of them must have been moved there, the oldest by date go first
+fn move_shortfall(lots_by_location: &mut HashMap<String, LotStore>, trade: &Trade) {
+    let currency = &trade.sold_currency;
+    let mut shortfall = trade.amount_sold
+        - lots_by_location
+            .get(&trade.exchange)
+            .map_or_else(Zero::zero, |lots| lots.amount(currency));
+    let move_id = format!("{}-move", trade.id);
+    let mut moved = vec![];
+
+    while shortfall > Zero::zero() {
+        let oldest = lots_by_location
+            .iter()
+            .filter(|(location, _)| **location != trade.exchange)
+            .filter_map(|(location, lots)| {
+                lots.lots(currency)
+                    .filter(|lot| !lot.amount.is_zero())
+                    .min_by_key(|lot| lot.date)
+                    .map(|lot| (lot.date, location.clone(), lot.amount))
+            })
+            .min();
+        let (_, location, amount) = match oldest {
+            Some(oldest) => oldest,
+            None => break,
+        };
+
+        let amount = amount.min(shortfall);
+        moved.extend(
+            lots_by_location
+                .get_mut(&location)
+                .unwrap()
+                .withdraw(currency, amount, trade.date, &move_id),
+        );
+        shortfall -= amount;
+    }
+
+    if !moved.is_empty() {
+        lots_by_location
+            .entry(trade.exchange.clone())
+            .or_default()
+            .deposit(currency, moved, &trade.exchange);
+    }
+}
+
+fn split_by_location(holdings: Holdings) -> HashMap<String, Holdings> {
+    let mut holdings_by_location: HashMap<String, Holdings> = HashMap::new();
+
+    for (currency, currency_holdings) in holdings.0 {
+        for currency_holding in currency_holdings {
+            holdings_by_location
+                .entry(currency_holding.location.clone())
+                .or_default()
+                .0
+                .entry(currency.clone())
+                .or_default()
+                .push(currency_holding);
+        }
+    }
+
+    // first in first out goes by the order lots are in
+    for location_holdings in holdings_by_location.values_mut() {
+        for currency_holdings in location_holdings.0.values_mut() {
+            currency_holdings.sort_by_key(|currency_holding| currency_holding.date);
+        }
+    }
+
+    holdings_by_location
+}
+
+fn merge_locations(holdings_by_location: HashMap<String, Holdings>) -> Holdings {
+    let mut holdings = Holdings::default();
+
+    for location_holdings in holdings_by_location.into_values() {
+        for (currency, currency_holdings) in location_holdings.0 {
+            holdings
+                .0
+                .entry(currency)
+                .or_default()
+                .extend(currency_holdings);
+        }
+    }
+
+    for currency_holdings in holdings.0.values_mut() {
+        currency_holdings.sort_by_key(|currency_holding| currency_holding.date);
+    }
+
+    holdings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::calculate_germany_report;
+    use crate::holding::{CurrencyHolding, Holdings};
+    use crate::income::Income;
+    use crate::mocks;
+    use crate::YEAR_IN_MILLISECONDS;
+    use rust_decimal::prelude::{Decimal, Zero};
+    use rust_decimal_macros::*;
+    use std::collections::HashMap;
+
+    static FIAT_CURRENCY: &str = "EUR";
+    // 2023-06-01
+    const SALE_DATE: u64 = 1685577600000;
+
+    fn lot(
+        id: &str,
+        amount: Decimal,
+        rate_in_fiat: Decimal,
+        date: u64,
+        location: &str,
+    ) -> CurrencyHolding {
+        CurrencyHolding {
+            amount,
+            rate_in_fiat,
+            date,
+            location: location.to_string(),
+            id: id.to_string(),
+            acquisition_id: id.to_string(),
+            parent_ids: vec![],
+        }
+    }
+
+    fn sale(amount_sold: Decimal, fiat_rate: Decimal, exchange: &str) -> crate::trade::Trade {
+        let holdings = mocks::mock_holdings(1, 1, None, None);
+        let mut trade = mocks::mock_trades(1, SALE_DATE, holdings, false).remove(0);
+        trade.sold_currency = "BTC".to_string();
+        trade.bought_currency = FIAT_CURRENCY.to_string();
+        trade.amount_sold = amount_sold;
+        trade.rate = Decimal::from(1) / fiat_rate;
+        trade.fiat_rate = Some(fiat_rate);
+        trade.exchange = exchange.to_string();
+        trade.date = SALE_DATE;
+        trade
+    }
+
+    #[test]
+    fn uses_first_in_first_out_per_location() {
+        let mut holdings = Holdings(HashMap::new());
+        holdings.0.insert(
+            "BTC".to_string(),
+            vec![
+                lot("old", dec!(1), dec!(100), SALE_DATE - 1000, "Kraken"),
+                lot("new", dec!(1), dec!(200), SALE_DATE - 500, "Coinbase"),
+            ],
+        );
+
+        let report = calculate_germany_report(
+            holdings,
+            vec![sale(dec!(1), dec!(300), "Coinbase")],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert_eq!(year.lines.len(), 1);
+        assert_eq!(year.lines[0].lot_id, "new");
+        assert_eq!(year.private_sales_gain, dec!(100));
+        assert!(year.private_sales_exempt);
+        assert!(year.taxable_private_sales_gain.is_zero());
+
+        let remaining = report.holdings.0.get("BTC").unwrap();
+        assert!(remaining
+            .iter()
+            .any(|currency_holding| currency_holding.id == "old"
+                && currency_holding.amount == dec!(1)));
+    }
+
+    #[test]
+    fn exemption_limit_is_not_an_allowance() {
+        let mut holdings = Holdings(HashMap::new());
+        holdings.0.insert(
+            "BTC".to_string(),
+            vec![
+                lot("short", dec!(1), dec!(100), SALE_DATE - 1000, "Kraken"),
+                lot(
+                    "long",
+                    dec!(1),
+                    dec!(100),
+                    SALE_DATE - YEAR_IN_MILLISECONDS * 2,
+                    "Kraken",
+                ),
+            ],
+        );
+        holdings
+            .0
+            .get_mut("BTC")
+            .unwrap()
+            .sort_by_key(|currency_holding| currency_holding.date);
+
+        let report = calculate_germany_report(
+            holdings,
+            vec![sale(dec!(2), dec!(800), "Kraken")],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert_eq!(year.tax_free_gain, dec!(700));
+        assert_eq!(year.private_sales_gain, dec!(700));
+        assert!(!year.private_sales_exempt);
+        assert_eq!(year.taxable_private_sales_gain, dec!(700));
+    }
+
+    #[test]
+    fn staking_income_exemption() {
+        let income = |id: &str, fiat_rate: Decimal| Income {
+            amount: dec!(1),
+            currency: "ETH".to_string(),
+            transaction_id: None,
+            id: id.to_string(),
+            fee: None,
+            date: SALE_DATE,
+            fiat_rate: Some(fiat_rate),
+        };
+
+        let report = calculate_germany_report(
+            Holdings(HashMap::new()),
+            vec![],
+            vec![income("first", dec!(200))],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+        let year = report.years.get(&2023).unwrap();
+        assert!(year.other_income_exempt);
+        assert!(year.taxable_other_income.is_zero());
+
+        let report = calculate_germany_report(
+            Holdings(HashMap::new()),
+            vec![],
+            vec![income("first", dec!(200)), income("second", dec!(100))],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+        let year = report.years.get(&2023).unwrap();
+        assert!(!year.other_income_exempt);
+        assert_eq!(year.taxable_other_income, dec!(300));
+    }
+
+    #[test]
+    fn income_sold_on_an_exchange_is_moved_there() {
+        let income = Income {
+            amount: dec!(2),
+            currency: "BTC".to_string(),
+            transaction_id: None,
+            id: "staking".to_string(),
+            fee: None,
+            date: SALE_DATE - 1000,
+            fiat_rate: Some(dec!(100)),
+        };
+        let mut holdings = Holdings(HashMap::new());
+        holdings.0.insert(
+            "BTC".to_string(),
+            vec![lot(
+                "kraken",
+                dec!(0.5),
+                dec!(200),
+                SALE_DATE - 500,
+                "Kraken",
+            )],
+        );
+
+        let trade = sale(dec!(1.5), dec!(300), "Kraken");
+        let report = calculate_germany_report(
+            holdings,
+            vec![trade.clone()],
+            vec![income],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert_eq!(year.lines.len(), 2);
+        assert_eq!(year.lines[0].lot_id, format!("staking/{}-move", trade.id));
+        assert_eq!(year.lines[0].location, "Kraken");
+        assert_eq!(year.lines[1].lot_id, "kraken");
+        assert_eq!(year.private_sales_gain, dec!(250));
+
+        let remaining = report.holdings.0.get("BTC").unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, "staking");
+        assert_eq!(remaining[0].amount, dec!(1));
+    }
+
+    #[test]
+    fn moved_lots_older_than_the_exchange_lots_are_sold_first() {
+        let mut holdings = Holdings(HashMap::new());
+        holdings.0.insert(
+            "BTC".to_string(),
+            vec![
+                lot("kraken", dec!(0.5), dec!(200), SALE_DATE - 500, "Kraken"),
+                lot("newer", dec!(1), dec!(150), SALE_DATE - 1500, "Coinbase"),
+                lot("oldest", dec!(1), dec!(100), SALE_DATE - 2000, "Coinbase"),
+            ],
+        );
+
+        let trade = sale(dec!(0.75), dec!(300), "Kraken");
+        let report = calculate_germany_report(
+            holdings,
+            vec![trade.clone()],
+            vec![],
+            FIAT_CURRENCY.to_string(),
+        )
+        .unwrap();
+
+        let year = report.years.get(&2023).unwrap();
+        assert_eq!(year.lines.len(), 2);
+        assert_eq!(year.lines[0].lot_id, format!("oldest/{}-move", trade.id));
+        assert_eq!(year.lines[1].lot_id, "kraken");
+
+        let remaining = report.holdings.0.get("BTC").unwrap();
+        assert!(remaining
+            .iter()
+            .any(|currency_holding| currency_holding.id == "oldest"
+                && currency_holding.amount == dec!(0.75)));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::date::{self, InvalidDate};
+use crate::trade::Trade;
+use rust_decimal::prelude::Decimal;
+use serde::{Deserialize, Serialize};
+
+pub mod australia;
+pub mod canada;
+pub mod germany;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Disposal {
+    pub currency: String,
+    #[serde(rename = "lotID")]
+    pub lot_id: String,
+    #[serde(rename = "tradeID")]
+    pub trade_id: String,
+    pub location: String,
+    pub amount: Decimal,
+    #[serde(rename = "dateAcquired")]
+    pub date_acquired: u64,
+    #[serde(rename = "dateSold")]
+    pub date_sold: u64,
+    pub proceeds: Decimal,
+    #[serde(rename = "costBasis")]
+    pub cost_basis: Decimal,
+    pub gain: Decimal,
+    #[serde(rename = "longTerm")]
+    pub long_term: bool,
+}
+
+impl Disposal {
+    pub fn year(&self) -> Result<i32, InvalidDate> {
+        date::year(self.date_sold)
+    }
+}
+
+// turns a per lot trade from process_trade into a disposal line
+impl From<&Trade> for Disposal {
+    fn from(trade: &Trade) -> Self {
+        let long_term = trade.long_term_trade.unwrap_or(false);
+        Disposal {
+            currency: trade.sold_currency.clone(),
+            lot_id: trade.lot_id.clone().unwrap_or_default(),
+            trade_id: trade.id.clone(),
+            location: trade.exchange.clone(),
+            amount: trade.amount_sold,
+            date_acquired: trade.date_acquired.unwrap_or(trade.date),
+            date_sold: trade.date,
+            proceeds: trade.fiat_rate() * trade.amount_sold,
+            cost_basis: trade.cost_basis(),
+            gain: if long_term {
+                trade.long_term.unwrap_or_default()
+            } else {
+                trade.short_term.unwrap_or_default()
+            },
+            long_term,
+        }
+    }
+}
@@ -33,6 +33,10 @@ pub struct Trade {
     pub cost_basis: Option<Decimal>,
     #[serde(rename = "longtermTrade")]
     pub long_term_trade: Option<bool>,
+    #[serde(rename = "lotID")]
+    pub lot_id: Option<String>,
+    #[serde(rename = "acquisitionID")]
+    pub acquisition_id: Option<String>,
 }
 
 impl Trade {
@@ -43,6 +47,36 @@ impl Trade {
     pub fn cost_basis(&self) -> Decimal {
         self.cost_basis.unwrap_or_else(Zero::zero)
     }
+
+    // fees paid in a currency the trade neither bought nor sold, such as BNB on binance
+    pub fn fee_in_other_currency(&self, fiat_currency: &str) -> bool {
+        !self.transaction_fee.is_zero()
+            && self.transaction_fee_currency != self.bought_currency
+            && self.transaction_fee_currency != self.sold_currency
+            && self.transaction_fee_currency != fiat_currency
+    }
+
+    // amount of the bought currency left after the fee along with the fees cost in fiat,
+    // a fee in another currency is paid from lots of its own so it is left to LotStore::process_trade
+    pub fn amount_bought(&self, fiat_currency: &str) -> (Decimal, Decimal) {
+        let mut fee_fiat_cost: Decimal = Zero::zero();
+        let mut amount_bought = self.amount_sold / self.rate;
+
+        if !self.transaction_fee.is_zero() {
+            if self.transaction_fee_currency == self.bought_currency {
+                fee_fiat_cost += self.transaction_fee * self.rate * self.fiat_rate();
+                amount_bought -= self.transaction_fee;
+            } else if self.transaction_fee_currency == self.sold_currency {
+                fee_fiat_cost += self.transaction_fee * self.fiat_rate();
+                amount_bought -= self.transaction_fee / self.rate;
+            } else if self.transaction_fee_currency == fiat_currency {
+                fee_fiat_cost += self.transaction_fee;
+                amount_bought -= self.transaction_fee / self.fiat_rate();
+            }
+        }
+
+        (amount_bought, fee_fiat_cost)
+    }
 }
 
 impl Arbitrary<'_> for Trade {
@@ -64,6 +98,8 @@ impl Arbitrary<'_> for Trade {
             date_acquired: None,
             cost_basis: None,
             long_term_trade: None,
+            lot_id: None,
+            acquisition_id: None,
         })
     }
 }
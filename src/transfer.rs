@@ -0,0 +1,24 @@
+use rust_decimal::prelude::Decimal;
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TransferDirection {
+    Deposit,
+    Withdrawal,
+}
+
+// crypto moving into or out of an exchange, these do not change holdings
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Transfer {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub currency: String,
+    pub amount: Decimal,
+    pub date: u64,
+    pub direction: TransferDirection,
+    pub exchange: String,
+    // in the transferred currency
+    pub fee: Option<Decimal>,
+    #[serde(rename = "transactionID")]
+    pub transaction_id: Option<String>,
+}